@@ -0,0 +1,316 @@
+//! Reactive obstacle avoidance from range sensors, like the Multi-ranger
+//! "push" demo. Velocity and hover setpoints get a push away from anything
+//! closer than the safety radius, and position setpoints are kept that far
+//! from sensed obstacles.
+
+use async_trait::async_trait;
+use glam::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    middleware::{Layer, Middleware},
+    types::{
+        DroneCommand, DroneError, DroneInterface, DroneState, HoverCommand, PositionCommand,
+        Ranges, VelocityCommand,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ObstacleAvoidance {
+    pub radius: f32,    // meters, keep this far from obstacles
+    pub push_gain: f32, // m/s per meter inside the radius
+    pub max_push: f32,  // m/s
+}
+
+impl Default for ObstacleAvoidance {
+    fn default() -> Self {
+        Self {
+            radius: 0.4,
+            push_gain: 2.0,
+            max_push: 0.5,
+        }
+    }
+}
+
+impl ObstacleAvoidance {
+    /// Sensed directions in the body frame with their readings. Down is left
+    /// out so the drone can still land.
+    fn readings(ranges: &Ranges) -> [(Vec3, Option<f32>); 5] {
+        [
+            (Vec3::X, ranges.front),
+            (Vec3::NEG_X, ranges.back),
+            (Vec3::Y, ranges.left),
+            (Vec3::NEG_Y, ranges.right),
+            (Vec3::Z, ranges.up),
+        ]
+    }
+
+    /// Body to world, by heading only.
+    fn heading(state: &DroneState) -> Quat {
+        Quat::from_rotation_z(state.yaw.to_radians())
+    }
+
+    /// World-frame push away from obstacles inside the radius.
+    pub fn push(&self, state: &DroneState) -> Vec3 {
+        let push: Vec3 = Self::readings(&state.ranges)
+            .iter()
+            .filter_map(|&(direction, range)| {
+                let range = range?;
+                (range < self.radius).then(|| -direction * (self.radius - range) * self.push_gain)
+            })
+            .sum();
+        Self::heading(state) * push.clamp_length_max(self.max_push)
+    }
+
+    /// Removes velocity towards close obstacles and adds the push away.
+    pub fn filter_velocity(&self, cmd: VelocityCommand, state: &DroneState) -> VelocityCommand {
+        let heading = Self::heading(state);
+        let mut velocity = heading.inverse() * Vec3::new(cmd.vx, cmd.vy, cmd.vz);
+        for (direction, range) in Self::readings(&state.ranges) {
+            let towards = velocity.dot(direction);
+            if range.is_some_and(|range| range < self.radius) && towards > 0.0 {
+                velocity -= direction * towards;
+            }
+        }
+
+        let velocity = heading * velocity + self.push(state);
+        VelocityCommand {
+            vx: velocity.x,
+            vy: velocity.y,
+            vz: velocity.z,
+            yaw_rate: cmd.yaw_rate,
+        }
+    }
+
+    /// [`Self::filter_velocity`] for a body-frame hover setpoint. The height
+    /// is held, so only the horizontal part applies.
+    pub fn filter_hover(&self, cmd: HoverCommand, state: &DroneState) -> HoverCommand {
+        let heading = Self::heading(state);
+        let world = heading * Vec3::new(cmd.vx, cmd.vy, 0.0);
+        let filtered = self.filter_velocity(
+            VelocityCommand {
+                vx: world.x,
+                vy: world.y,
+                vz: 0.0,
+                yaw_rate: cmd.yaw_rate,
+            },
+            state,
+        );
+        let body = heading.inverse() * Vec3::new(filtered.vx, filtered.vy, 0.0);
+        HoverCommand {
+            vx: body.x,
+            vy: body.y,
+            ..cmd
+        }
+    }
+
+    /// Keeps a position target at least the radius away from sensed obstacles.
+    pub fn filter_position(&self, cmd: PositionCommand, state: &DroneState) -> PositionCommand {
+        let heading = Self::heading(state);
+        let current = state.position();
+        let mut offset = heading.inverse() * (Vec3::new(cmd.x, cmd.y, cmd.z) - current);
+        for (direction, range) in Self::readings(&state.ranges) {
+            if let Some(range) = range {
+                let limit = range - self.radius;
+                let along = offset.dot(direction);
+                if along > limit {
+                    offset -= direction * (along - limit);
+                }
+            }
+        }
+
+        let target = current + heading * offset;
+        PositionCommand {
+            x: target.x,
+            y: target.y,
+            z: target.z,
+            yaw: cmd.yaw,
+        }
+    }
+
+    pub fn filter(&self, cmd: DroneCommand, state: &DroneState) -> DroneCommand {
+        match cmd {
+            DroneCommand::Velocity(cmd) => DroneCommand::Velocity(self.filter_velocity(cmd, state)),
+            DroneCommand::Position(cmd) => DroneCommand::Position(self.filter_position(cmd, state)),
+            DroneCommand::Hover(cmd) => DroneCommand::Hover(self.filter_hover(cmd, state)),
+            other => other,
+        }
+    }
+}
+
+/// Applies the avoidance to velocity, hover and position setpoints, using the range
+/// readings in the state of the driver below.
+#[async_trait]
+impl Middleware for ObstacleAvoidance {
+    async fn send_command(
+        &mut self,
+        inner: &mut dyn DroneInterface,
+        cmd: DroneCommand,
+    ) -> Result<(), DroneError> {
+        let state = inner.get_state().await?;
+        inner.send_command(self.filter(cmd, &state)).await
+    }
+}
+
+/// A driver behind [`ObstacleAvoidance`], e.g.
+/// `AvoidingDrone::new(Box::new(driver), ObstacleAvoidance::default())`.
+pub type AvoidingDrone = Layer<ObstacleAvoidance>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::mock::MockDriver;
+
+    fn state(yaw: f32, ranges: Ranges) -> DroneState {
+        DroneState {
+            z: 1.0,
+            yaw,
+            ranges,
+            ..Default::default()
+        }
+    }
+
+    fn velocity(vx: f32, vy: f32) -> VelocityCommand {
+        VelocityCommand {
+            vx,
+            vy,
+            vz: 0.0,
+            yaw_rate: 0.0,
+        }
+    }
+
+    fn near(a: Vec3, b: Vec3) -> bool {
+        a.distance(b) < 1e-4
+    }
+
+    #[test]
+    fn velocity_pushed_away_from_close_obstacles() {
+        let avoidance = ObstacleAvoidance::default();
+        let close_front = Ranges {
+            front: Some(0.2),
+            ..Default::default()
+        };
+
+        let cmd = avoidance.filter_velocity(velocity(0.5, 0.3), &state(0.0, close_front));
+        assert!(near(
+            Vec3::new(cmd.vx, cmd.vy, cmd.vz),
+            Vec3::new(-0.4, 0.3, 0.0)
+        ));
+
+        // Facing +y, the front sensor looks along world +y
+        let cmd = avoidance.filter_velocity(velocity(0.3, 0.5), &state(90.0, close_front));
+        assert!(near(
+            Vec3::new(cmd.vx, cmd.vy, cmd.vz),
+            Vec3::new(0.3, -0.4, 0.0)
+        ));
+    }
+
+    #[test]
+    fn velocity_unchanged_away_from_obstacles() {
+        let avoidance = ObstacleAvoidance::default();
+        let ranges = Ranges {
+            front: Some(2.0),
+            back: Some(0.5),
+            down: Some(0.1),
+            ..Default::default()
+        };
+        let cmd = avoidance.filter_velocity(velocity(0.5, -0.2), &state(0.0, ranges));
+        assert!(near(
+            Vec3::new(cmd.vx, cmd.vy, cmd.vz),
+            Vec3::new(0.5, -0.2, 0.0)
+        ));
+    }
+
+    #[test]
+    fn hover_pushed_away_in_the_body_frame() {
+        let avoidance = ObstacleAvoidance::default();
+        let close_front = Ranges {
+            front: Some(0.2),
+            ..Default::default()
+        };
+        let hover = HoverCommand {
+            vx: 0.5,
+            vy: 0.3,
+            yaw_rate: 10.0,
+            z_distance: 0.5,
+        };
+
+        // Heading doesn't matter, the sensors turn with the body
+        for yaw in [0.0, 90.0, -135.0] {
+            let cmd = avoidance.filter_hover(hover, &state(yaw, close_front));
+            assert!(near(
+                Vec3::new(cmd.vx, cmd.vy, 0.0),
+                Vec3::new(-0.4, 0.3, 0.0)
+            ));
+            assert_eq!((cmd.yaw_rate, cmd.z_distance), (10.0, 0.5));
+        }
+
+        let cmd = avoidance.filter(DroneCommand::Hover(hover), &state(0.0, close_front));
+        assert!(matches!(cmd, DroneCommand::Hover(HoverCommand { vx, .. }) if vx < 0.0));
+    }
+
+    #[test]
+    fn push_is_limited() {
+        let avoidance = ObstacleAvoidance::default();
+        let ranges = Ranges {
+            front: Some(0.0),
+            left: Some(0.0),
+            ..Default::default()
+        };
+        let push = avoidance.push(&state(0.0, ranges));
+        assert!((push.length() - avoidance.max_push).abs() < 1e-4);
+        assert!(push.x < 0.0 && push.y < 0.0);
+    }
+
+    #[test]
+    fn position_kept_clear_of_obstacles() {
+        let avoidance = ObstacleAvoidance::default();
+        let ranges = Ranges {
+            front: Some(1.0),
+            down: Some(0.1),
+            ..Default::default()
+        };
+        let target = PositionCommand {
+            x: 3.0,
+            y: 0.5,
+            z: 0.2,
+            yaw: 0.0,
+        };
+        let cmd = avoidance.filter_position(target, &state(0.0, ranges));
+        // Stops the radius short of the front obstacle, down isn't limited
+        assert!(near(
+            Vec3::new(cmd.x, cmd.y, cmd.z),
+            Vec3::new(0.6, 0.5, 0.2)
+        ));
+
+        let cmd = avoidance.filter_position(target, &state(0.0, Ranges::default()));
+        assert!(near(
+            Vec3::new(cmd.x, cmd.y, cmd.z),
+            Vec3::new(3.0, 0.5, 0.2)
+        ));
+    }
+
+    #[tokio::test]
+    async fn filters_commands_as_middleware() {
+        let mut drone = MockDriver::with_state(state(
+            0.0,
+            Ranges {
+                front: Some(0.2),
+                ..Default::default()
+            },
+        ));
+        let mut avoidance = ObstacleAvoidance::default();
+        avoidance
+            .send_command(&mut drone, DroneCommand::Velocity(velocity(0.5, 0.0)))
+            .await
+            .unwrap();
+
+        let Some(DroneCommand::Velocity(cmd)) = drone.sent.last() else {
+            panic!("expected a velocity command, got {:?}", drone.sent);
+        };
+        assert!(near(
+            Vec3::new(cmd.vx, cmd.vy, cmd.vz),
+            Vec3::new(-0.4, 0.0, 0.0)
+        ));
+    }
+}
@@ -0,0 +1,57 @@
+use super::{AxisMode, Setpoint, StateEstimate};
+use crate::types::{AssistMode, RpytCommand};
+
+/// Thrust stick centre, where the climb rate is zero.
+const THRUST_CENTER: f32 = 32767.0;
+
+/// Manual flight assist, like the firmware's `flightmode.althold`. With
+/// altitude hold the thrust channel of an [`RpytCommand`] is a climb rate
+/// around the stick centre, and the current height is held while centred.
+/// Zero thrust still stops the motors.
+#[derive(Debug, Clone)]
+pub struct AltitudeAssist {
+    pub mode: AssistMode,
+    pub max_climb_rate: f32, // m/s at full stick
+    pub deadband: f32,       // Fraction of half the stick range treated as centred
+    hold_height: Option<f32>,
+}
+
+impl Default for AltitudeAssist {
+    fn default() -> Self {
+        Self {
+            mode: AssistMode::Off,
+            max_climb_rate: 1.0,
+            deadband: 0.05,
+            hold_height: None,
+        }
+    }
+}
+
+impl AltitudeAssist {
+    pub fn set_mode(&mut self, mode: AssistMode) {
+        self.mode = mode;
+        self.hold_height = None;
+    }
+
+    /// Turns a manual command into a setpoint for the current mode.
+    pub fn setpoint(&mut self, cmd: RpytCommand, state: &StateEstimate) -> Setpoint {
+        let mut setpoint = Setpoint::from(cmd);
+        if self.mode == AssistMode::Off || cmd.thrust == 0 {
+            self.hold_height = None;
+            return setpoint;
+        }
+
+        let stick = (cmd.thrust as f32 - THRUST_CENTER) / THRUST_CENTER;
+        setpoint.thrust = 0.0;
+        if stick.abs() <= self.deadband {
+            let height = *self.hold_height.get_or_insert(state.position.z);
+            setpoint.position.z = height;
+            setpoint.mode.z = AxisMode::Abs;
+        } else {
+            self.hold_height = None;
+            setpoint.velocity.z = stick.clamp(-1.0, 1.0) * self.max_climb_rate;
+            setpoint.mode.z = AxisMode::Velocity;
+        }
+        setpoint
+    }
+}
@@ -0,0 +1,148 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use super::{AxisMode, Setpoint};
+use crate::types::CommanderStatus;
+
+/// Without a new setpoint for this long the drone levels out.
+pub const WATCHDOG_STABILIZE: f32 = 0.5; // seconds
+/// Without a new setpoint for this long the motors are cut.
+pub const WATCHDOG_SHUTDOWN: f32 = 2.0; // seconds
+
+/// Setpoint sources, lowest first. A setpoint is only accepted if its source
+/// has at least the priority of the one currently in control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+pub enum Priority {
+    #[default]
+    Disable,
+    HighLevel,
+    Crtp,
+    ExtRx,
+}
+
+/// Arbitrates setpoints between sources and applies the firmware's two-stage
+/// watchdog, like `commander.c`. Time is advanced by [`Commander::update`].
+#[derive(Debug, Clone, Default)]
+pub struct Commander {
+    setpoint: Setpoint,
+    priority: Priority,
+    timestamp: Option<f64>, // seconds, when the setpoint was received
+    time: f64,
+}
+
+impl Commander {
+    /// Offers a setpoint from `priority`. Returns false if a higher priority
+    /// source is in control.
+    pub fn set_setpoint(&mut self, setpoint: Setpoint, priority: Priority) -> bool {
+        if priority < self.priority {
+            return false;
+        }
+        self.setpoint = setpoint;
+        self.priority = priority;
+        self.timestamp = Some(self.time);
+        true
+    }
+
+    /// Lets lower priority sources take over again while the current setpoint
+    /// keeps being flown, like `commanderRelaxPriority`.
+    pub fn relax_priority(&mut self) {
+        self.priority = Priority::HighLevel;
+    }
+
+    /// Lets the current setpoint run out `remain_valid` seconds from now
+    /// rather than through the whole watchdog, so another source can take
+    /// over, like `commanderNotifySetpointsStop`.
+    pub fn notify_setpoint_stop(&mut self, remain_valid: f32) {
+        if let Some(timestamp) = &mut self.timestamp {
+            let setback = (WATCHDOG_SHUTDOWN - remain_valid) as f64;
+            *timestamp = (self.time - setback).max(0.0);
+        }
+    }
+
+    /// Drops the current setpoint and stops the motors.
+    pub fn stop(&mut self) {
+        self.setpoint = Setpoint::default();
+        self.priority = Priority::Disable;
+        self.timestamp = None;
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Seconds since the last accepted setpoint.
+    pub fn age(&self) -> Option<f32> {
+        self.timestamp
+            .map(|timestamp| (self.time - timestamp) as f32)
+    }
+
+    pub fn status(&self) -> CommanderStatus {
+        status_for_age(self.age())
+    }
+
+    /// Advances by `dt` and returns the setpoint to fly.
+    pub fn update(&mut self, dt: f32) -> Setpoint {
+        self.time += dt as f64;
+
+        match self.status() {
+            CommanderStatus::Idle | CommanderStatus::TimedOut => {
+                self.priority = Priority::Disable;
+                Setpoint::default()
+            }
+            CommanderStatus::LevelingOut => {
+                self.priority = Priority::Disable;
+                // Level out and stop turning, keeping z as it is
+                let mut setpoint = self.setpoint;
+                setpoint.mode.x = AxisMode::Disable;
+                setpoint.mode.y = AxisMode::Disable;
+                setpoint.mode.roll = AxisMode::Abs;
+                setpoint.mode.pitch = AxisMode::Abs;
+                setpoint.mode.yaw = AxisMode::Velocity;
+                setpoint.attitude.x = 0.0;
+                setpoint.attitude.y = 0.0;
+                setpoint.attitude_rate = Vec3::ZERO;
+                setpoint
+            }
+            CommanderStatus::Active => self.setpoint,
+        }
+    }
+}
+
+/// Watchdog stage for a setpoint received `age` seconds ago.
+pub fn status_for_age(age: Option<f32>) -> CommanderStatus {
+    match age {
+        None => CommanderStatus::Idle,
+        Some(age) if age > WATCHDOG_SHUTDOWN => CommanderStatus::TimedOut,
+        Some(age) if age > WATCHDOG_STABILIZE => CommanderStatus::LevelingOut,
+        Some(_) => CommanderStatus::Active,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setpoint_stop_expires_after_remain_valid() {
+        let mut commander = Commander::default();
+        commander.update(5.0);
+        assert!(commander.set_setpoint(Setpoint::default(), Priority::Crtp));
+        commander.update(0.01);
+
+        commander.notify_setpoint_stop(0.1);
+        commander.update(0.05);
+        assert_ne!(commander.status(), CommanderStatus::TimedOut);
+
+        commander.update(0.1);
+        assert_eq!(commander.status(), CommanderStatus::TimedOut);
+        assert!(commander.set_setpoint(Setpoint::default(), Priority::HighLevel));
+    }
+
+    #[test]
+    fn setpoint_stop_without_setpoint() {
+        let mut commander = Commander::default();
+        commander.notify_setpoint_stop(0.0);
+        commander.update(0.01);
+        assert_eq!(commander.status(), CommanderStatus::Idle);
+    }
+}
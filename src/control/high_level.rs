@@ -0,0 +1,177 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use super::{pid::wrap_angle, Setpoint, StateEstimate};
+use crate::trajectory::{Poly4d, Trajectory, TrajectoryPoint};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PlannerState {
+    #[default]
+    Idle,
+    Flying,
+    Landing,
+}
+
+/// Trajectory being flown, with the transforms requested when it was started.
+#[derive(Debug, Clone)]
+struct Plan {
+    trajectory: Trajectory,
+    offset: Vec3,
+    yaw_offset: f32,
+    time_scale: f32,
+    reversed: bool,
+}
+
+impl Plan {
+    fn new(trajectory: Trajectory) -> Self {
+        Self {
+            trajectory,
+            offset: Vec3::ZERO,
+            yaw_offset: 0.0,
+            time_scale: 1.0,
+            reversed: false,
+        }
+    }
+
+    fn duration(&self) -> f32 {
+        self.trajectory.duration() * self.time_scale
+    }
+
+    fn sample(&self, elapsed: f32) -> TrajectoryPoint {
+        let t = (elapsed / self.time_scale).min(self.trajectory.duration());
+        let (t, direction) = if self.reversed {
+            (self.trajectory.duration() - t, -1.0)
+        } else {
+            (t, 1.0)
+        };
+        let point = self.trajectory.eval(t);
+        TrajectoryPoint {
+            position: point.position + self.offset,
+            velocity: point.velocity * direction / self.time_scale,
+            acceleration: point.acceleration / (self.time_scale * self.time_scale),
+            yaw: point.yaw + self.yaw_offset,
+        }
+    }
+}
+
+/// On-board style high-level commander. Each command plans a smooth
+/// trajectory from where the drone currently is (or is heading) and
+/// [`HighLevelCommander::update`] samples it into full-state setpoints.
+#[derive(Debug, Clone, Default)]
+pub struct HighLevelCommander {
+    state: PlannerState,
+    plan: Option<Plan>,
+    elapsed: f32,
+}
+
+impl HighLevelCommander {
+    pub fn state(&self) -> PlannerState {
+        self.state
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state != PlannerState::Idle
+    }
+
+    /// Climbs to `height` meters above the origin over `duration` seconds,
+    /// keeping the current x/y and heading.
+    pub fn takeoff(&mut self, state: &StateEstimate, height: f32, duration: f32) {
+        let start = self.current_point(state);
+        let goal = Vec3::new(start.position.x, start.position.y, height);
+        self.start_plan(start, TrajectoryPoint::at_rest(goal, start.yaw), duration);
+        self.state = PlannerState::Flying;
+    }
+
+    /// Descends to `height` meters over `duration` seconds, then goes idle.
+    pub fn land(&mut self, state: &StateEstimate, height: f32, duration: f32) {
+        let start = self.current_point(state);
+        let goal = Vec3::new(start.position.x, start.position.y, height);
+        self.start_plan(start, TrajectoryPoint::at_rest(goal, start.yaw), duration);
+        self.state = PlannerState::Landing;
+    }
+
+    /// Flies to `position` with `yaw` in degrees. With `relative` both are
+    /// offsets from the current target.
+    pub fn go_to(
+        &mut self,
+        state: &StateEstimate,
+        position: Vec3,
+        yaw: f32,
+        duration: f32,
+        relative: bool,
+    ) {
+        if !self.is_active() {
+            return;
+        }
+        let start = self.current_point(state);
+        let yaw = yaw.to_radians();
+        let goal = if relative {
+            TrajectoryPoint::at_rest(start.position + position, start.yaw + yaw)
+        } else {
+            // Turn the short way round
+            let yaw = start.yaw + wrap_angle(yaw - start.yaw);
+            TrajectoryPoint::at_rest(position, yaw)
+        };
+        self.start_plan(start, goal, duration);
+        self.state = PlannerState::Flying;
+    }
+
+    /// Flies an uploaded trajectory. `time_scale` stretches it (2.0 is half
+    /// speed). With `relative` it is shifted to start at the current target.
+    pub fn start_trajectory(
+        &mut self,
+        state: &StateEstimate,
+        trajectory: Trajectory,
+        time_scale: f32,
+        relative: bool,
+        reversed: bool,
+    ) {
+        if !self.is_active() || trajectory.pieces.is_empty() {
+            return;
+        }
+        let mut plan = Plan::new(trajectory);
+        plan.time_scale = time_scale.max(0.01);
+        plan.reversed = reversed;
+        if relative {
+            let current = self.current_point(state);
+            let first = plan.sample(0.0);
+            plan.offset = current.position - first.position;
+            plan.yaw_offset = current.yaw - first.yaw;
+        }
+
+        self.plan = Some(plan);
+        self.elapsed = 0.0;
+        self.state = PlannerState::Flying;
+    }
+
+    /// Drops the current plan. The caller is responsible for stopping motors.
+    pub fn stop(&mut self) {
+        self.state = PlannerState::Idle;
+        self.plan = None;
+    }
+
+    /// Advances by `dt` and returns the setpoint to fly, or `None` once idle.
+    pub fn update(&mut self, dt: f32) -> Option<Setpoint> {
+        let plan = self.plan.as_ref()?;
+        self.elapsed += dt;
+
+        if self.state == PlannerState::Landing && self.elapsed >= plan.duration() {
+            self.stop();
+            return None;
+        }
+        Some(plan.sample(self.elapsed).into())
+    }
+
+    fn start_plan(&mut self, start: TrajectoryPoint, goal: TrajectoryPoint, duration: f32) {
+        let piece = Poly4d::plan(&start, &goal, duration.max(0.1));
+        self.plan = Some(Plan::new(piece.into()));
+        self.elapsed = 0.0;
+    }
+
+    fn current_point(&self, state: &StateEstimate) -> TrajectoryPoint {
+        match &self.plan {
+            Some(plan) if self.is_active() => plan.sample(self.elapsed),
+            _ => TrajectoryPoint::at_rest(state.position, state.rpy_degrees().z.to_radians()),
+        }
+    }
+}
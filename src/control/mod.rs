@@ -0,0 +1,264 @@
+pub mod assist;
+pub mod commander;
+pub mod high_level;
+pub mod pid;
+pub mod position;
+pub mod supervisor;
+
+use glam::{EulerRot, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    trajectory::TrajectoryPoint,
+    types::{
+        FullStateCommand, HoverCommand, PositionCommand, RpytCommand, VelocityCommand,
+        ZDistanceCommand,
+    },
+};
+
+pub use assist::AltitudeAssist;
+pub use commander::{Commander, Priority};
+pub use high_level::HighLevelCommander;
+pub use pid::PidController;
+pub use position::PositionController;
+pub use supervisor::Supervisor;
+
+/// How a setpoint axis should be tracked, mirroring the firmware's `stab_mode_t`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AxisMode {
+    #[default]
+    Disable,
+    Abs,
+    Velocity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SetpointMode {
+    pub x: AxisMode,
+    pub y: AxisMode,
+    pub z: AxisMode,
+    pub roll: AxisMode,
+    pub pitch: AxisMode,
+    pub yaw: AxisMode,
+}
+
+/// Target handed to a [`Controller`] every tick.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Setpoint {
+    pub attitude: Vec3,      // roll, pitch, yaw in degrees
+    pub attitude_rate: Vec3, // roll, pitch, yaw in degrees/sec
+    pub thrust: f32,         // 0-65535
+    pub position: Vec3,      // meters, world frame
+    pub velocity: Vec3,      // m/s, world frame
+    pub acceleration: Vec3,  // m/s², world frame feedforward
+    pub mode: SetpointMode,
+}
+
+impl Setpoint {
+    /// Hover setpoint, with the body-frame velocity turned into the world
+    /// frame by the current `yaw` in degrees.
+    pub fn hover(cmd: HoverCommand, yaw: f32) -> Self {
+        let velocity = Quat::from_rotation_z(yaw.to_radians()) * Vec3::new(cmd.vx, cmd.vy, 0.0);
+        Self {
+            attitude_rate: Vec3::new(0.0, 0.0, cmd.yaw_rate),
+            position: Vec3::new(0.0, 0.0, cmd.z_distance),
+            velocity,
+            mode: SetpointMode {
+                x: AxisMode::Velocity,
+                y: AxisMode::Velocity,
+                z: AxisMode::Abs,
+                roll: AxisMode::Disable,
+                pitch: AxisMode::Disable,
+                yaw: AxisMode::Velocity,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// True when any translational axis is tracked by the position loop.
+    pub fn uses_position_loop(&self) -> bool {
+        [self.mode.x, self.mode.y, self.mode.z]
+            .iter()
+            .any(|mode| *mode != AxisMode::Disable)
+    }
+}
+
+impl From<RpytCommand> for Setpoint {
+    fn from(cmd: RpytCommand) -> Self {
+        Self {
+            attitude: Vec3::new(cmd.roll, cmd.pitch, 0.0),
+            attitude_rate: Vec3::new(0.0, 0.0, cmd.yaw),
+            thrust: cmd.thrust as f32,
+            mode: SetpointMode {
+                roll: AxisMode::Abs,
+                pitch: AxisMode::Abs,
+                yaw: AxisMode::Velocity,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl From<PositionCommand> for Setpoint {
+    fn from(cmd: PositionCommand) -> Self {
+        Self {
+            attitude: Vec3::new(0.0, 0.0, cmd.yaw),
+            position: Vec3::new(cmd.x, cmd.y, cmd.z),
+            mode: SetpointMode {
+                x: AxisMode::Abs,
+                y: AxisMode::Abs,
+                z: AxisMode::Abs,
+                roll: AxisMode::Disable,
+                pitch: AxisMode::Disable,
+                yaw: AxisMode::Abs,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl From<VelocityCommand> for Setpoint {
+    fn from(cmd: VelocityCommand) -> Self {
+        Self {
+            attitude_rate: Vec3::new(0.0, 0.0, cmd.yaw_rate),
+            velocity: Vec3::new(cmd.vx, cmd.vy, cmd.vz),
+            mode: SetpointMode {
+                x: AxisMode::Velocity,
+                y: AxisMode::Velocity,
+                z: AxisMode::Velocity,
+                roll: AxisMode::Disable,
+                pitch: AxisMode::Disable,
+                yaw: AxisMode::Velocity,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl From<ZDistanceCommand> for Setpoint {
+    fn from(cmd: ZDistanceCommand) -> Self {
+        Self {
+            attitude: Vec3::new(cmd.roll, cmd.pitch, 0.0),
+            attitude_rate: Vec3::new(0.0, 0.0, cmd.yaw_rate),
+            position: Vec3::new(0.0, 0.0, cmd.z_distance),
+            mode: SetpointMode {
+                z: AxisMode::Abs,
+                roll: AxisMode::Abs,
+                pitch: AxisMode::Abs,
+                yaw: AxisMode::Velocity,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl From<FullStateCommand> for Setpoint {
+    fn from(cmd: FullStateCommand) -> Self {
+        let (yaw, pitch, roll) = cmd.attitude.to_euler(EulerRot::ZYX);
+        Self {
+            attitude: Vec3::new(roll, pitch, yaw) * 180.0 / std::f32::consts::PI,
+            attitude_rate: cmd.rates,
+            position: cmd.position,
+            velocity: cmd.velocity,
+            acceleration: cmd.acceleration,
+            mode: SetpointMode {
+                x: AxisMode::Abs,
+                y: AxisMode::Abs,
+                z: AxisMode::Abs,
+                roll: AxisMode::Disable,
+                pitch: AxisMode::Disable,
+                yaw: AxisMode::Abs,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl From<TrajectoryPoint> for Setpoint {
+    fn from(point: TrajectoryPoint) -> Self {
+        Self {
+            attitude: Vec3::new(0.0, 0.0, point.yaw.to_degrees()),
+            position: point.position,
+            velocity: point.velocity,
+            acceleration: point.acceleration,
+            mode: SetpointMode {
+                x: AxisMode::Abs,
+                y: AxisMode::Abs,
+                z: AxisMode::Abs,
+                roll: AxisMode::Disable,
+                pitch: AxisMode::Disable,
+                yaw: AxisMode::Abs,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Vehicle state as seen by a controller. World frame is Z-up, body frame is
+/// x forward, y left, z up, like the Crazyflie.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct StateEstimate {
+    pub position: Vec3,         // meters
+    pub velocity: Vec3,         // m/s, world frame
+    pub attitude: Quat,         // body to world
+    pub angular_velocity: Vec3, // rad/s, body frame
+}
+
+impl StateEstimate {
+    /// Roll, pitch and yaw in degrees.
+    pub fn rpy_degrees(&self) -> Vec3 {
+        let (yaw, pitch, roll) = self.attitude.to_euler(EulerRot::ZYX);
+        Vec3::new(roll, pitch, yaw) * 180.0 / std::f32::consts::PI
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ControlOutput {
+    /// Collective thrust in newtons and body torques in N·m.
+    Wrench { thrust: f32, torque: Vec3 },
+    /// Per-motor throttle (0-1), M1 to M4.
+    Motors([f32; 4]),
+}
+
+impl ControlOutput {
+    pub const IDLE: Self = Self::Motors([0.0; 4]);
+}
+
+/// Physical parameters a controller needs to turn setpoints into forces.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct VehicleParams {
+    pub mass: f32,       // kg
+    pub inertia: Vec3,   // kg·m², body axes
+    pub max_thrust: f32, // N, all four motors at full throttle
+}
+
+impl Default for VehicleParams {
+    // Crazyflie 2.1
+    fn default() -> Self {
+        Self {
+            mass: 0.027,
+            inertia: Vec3::new(1.66e-5, 1.66e-5, 2.93e-5),
+            max_thrust: 0.6,
+        }
+    }
+}
+
+pub trait Controller: Send + Sync {
+    fn update(&mut self, setpoint: &Setpoint, state: &StateEstimate, dt: f32) -> ControlOutput;
+
+    /// Clears integrators and filters, e.g. after disarming.
+    fn reset(&mut self) {}
+
+    /// Tuning parameters under their firmware names and in the firmware's
+    /// units, e.g. `pid_rate.roll_kp`, so the same values tune hardware.
+    fn params(&self) -> Vec<(String, f32)> {
+        Vec::new()
+    }
+
+    /// Returns false if the controller has no parameter called `name`.
+    fn set_param(&mut self, _name: &str, _value: f32) -> bool {
+        false
+    }
+}
@@ -0,0 +1,289 @@
+use glam::Vec3;
+
+use super::{
+    AxisMode, ControlOutput, Controller, PositionController, Setpoint, StateEstimate, VehicleParams,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Pid {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    pub i_limit: f32,
+    integral: f32,
+    last_error: Option<f32>,
+}
+
+impl Pid {
+    pub fn new(kp: f32, ki: f32, kd: f32, i_limit: f32) -> Self {
+        Self {
+            kp,
+            ki,
+            kd,
+            i_limit,
+            integral: 0.0,
+            last_error: None,
+        }
+    }
+
+    pub fn update(&mut self, error: f32, dt: f32) -> f32 {
+        self.integral = (self.integral + error * dt).clamp(-self.i_limit, self.i_limit);
+        let derivative = match self.last_error {
+            Some(last) if dt > 0.0 => (error - last) / dt,
+            _ => 0.0,
+        };
+        self.last_error = Some(error);
+        self.kp * error + self.ki * self.integral + self.kd * derivative
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.last_error = None;
+    }
+
+    /// Gain by firmware suffix, "kp", "ki" or "kd" in any case.
+    pub(crate) fn gain_mut(&mut self, gain: &str) -> Option<&mut f32> {
+        match gain.to_ascii_lowercase().as_str() {
+            "kp" => Some(&mut self.kp),
+            "ki" => Some(&mut self.ki),
+            "kd" => Some(&mut self.kd),
+            _ => None,
+        }
+    }
+}
+
+const ATTITUDE_AXES: [&str; 3] = ["roll", "pitch", "yaw"];
+const GAINS: [&str; 3] = ["kp", "ki", "kd"];
+
+/// Crazyflie 2.1 geometry the firmware mixes its rate loop output with.
+const ARM_LENGTH: f32 = 0.046; // meters, motor to center
+const YAW_TORQUE_COEFF: f32 = 0.006; // reaction torque per newton of thrust
+
+/// Cascaded attitude/rate PID, structured like the firmware's `controller_pid`.
+/// Angles are tracked in radians and the rate loop outputs angular acceleration,
+/// which is scaled by the vehicle inertia into torque. Position and velocity
+/// setpoints go through [`PositionController`] first.
+pub struct PidController {
+    pub vehicle: VehicleParams,
+    pub position: PositionController,
+    pub attitude: [Pid; 3],
+    pub rate: [Pid; 3],
+    yaw_target: Option<f32>,
+}
+
+impl PidController {
+    pub fn new(vehicle: VehicleParams) -> Self {
+        Self {
+            vehicle,
+            position: PositionController::new(vehicle),
+            attitude: [
+                Pid::new(8.0, 0.0, 0.0, 0.0),
+                Pid::new(8.0, 0.0, 0.0, 0.0),
+                Pid::new(4.0, 0.0, 0.0, 0.0),
+            ],
+            rate: [
+                Pid::new(40.0, 10.0, 0.0, 0.5),
+                Pid::new(40.0, 10.0, 0.0, 0.5),
+                Pid::new(20.0, 5.0, 0.0, 0.5),
+            ],
+            yaw_target: None,
+        }
+    }
+}
+
+impl Default for PidController {
+    fn default() -> Self {
+        Self::new(VehicleParams::default())
+    }
+}
+
+impl PidController {
+    /// Firmware rate gain per gain of this rate loop on `axis`. The firmware
+    /// takes deg/s and outputs motor command units, which its mixer adds to
+    /// each motor's 16-bit command, halved for roll and pitch. This loop
+    /// takes rad/s and outputs angular acceleration.
+    fn rate_gain_scale(&self, axis: usize) -> f32 {
+        let thrust_per_unit = self.vehicle.max_thrust / 4.0 / 65535.0;
+        let torque_per_unit = if axis == 2 {
+            4.0 * thrust_per_unit * YAW_TORQUE_COEFF
+        } else {
+            2.0 * thrust_per_unit * ARM_LENGTH * std::f32::consts::FRAC_1_SQRT_2
+        };
+        let accel_per_unit = torque_per_unit / self.vehicle.inertia[axis];
+        1.0f32.to_radians() / accel_per_unit
+    }
+
+    fn pids_mut(&mut self, group: &str) -> Option<&mut [Pid; 3]> {
+        match group {
+            "pid_attitude" => Some(&mut self.attitude),
+            "pid_rate" => Some(&mut self.rate),
+            _ => None,
+        }
+    }
+}
+
+impl Controller for PidController {
+    fn update(&mut self, setpoint: &Setpoint, state: &StateEstimate, dt: f32) -> ControlOutput {
+        let setpoint = &if setpoint.uses_position_loop() {
+            self.position.update(setpoint, state, dt)
+        } else {
+            self.position.reset();
+            *setpoint
+        };
+
+        // Zero thrust means motors off, same as the firmware
+        if setpoint.thrust <= 0.0 {
+            self.reset();
+            return ControlOutput::IDLE;
+        }
+
+        let current = state.rpy_degrees().to_array().map(f32::to_radians);
+        let target = setpoint.attitude.to_array().map(f32::to_radians);
+        let target_rate = setpoint.attitude_rate.to_array().map(f32::to_radians);
+        let modes = [setpoint.mode.roll, setpoint.mode.pitch, setpoint.mode.yaw];
+
+        // Yaw in velocity mode holds the heading it was last commanded to
+        if modes[2] == AxisMode::Velocity {
+            let yaw = self.yaw_target.get_or_insert(current[2]);
+            *yaw = wrap_angle(*yaw + target_rate[2] * dt);
+        } else {
+            self.yaw_target = None;
+        }
+
+        let mut desired_rate = [0.0; 3];
+        for axis in 0..3 {
+            let target = match (axis, modes[axis]) {
+                (2, AxisMode::Velocity) => self.yaw_target,
+                (_, AxisMode::Abs) => Some(target[axis]),
+                _ => None,
+            };
+            desired_rate[axis] = match target {
+                Some(target) => {
+                    let error = wrap_angle(target - current[axis]);
+                    self.attitude[axis].update(error, dt)
+                }
+                None => {
+                    self.attitude[axis].reset();
+                    target_rate[axis]
+                }
+            };
+        }
+
+        let rates = state.angular_velocity.to_array();
+        let mut angular_accel = [0.0; 3];
+        for axis in 0..3 {
+            angular_accel[axis] = self.rate[axis].update(desired_rate[axis] - rates[axis], dt);
+        }
+
+        ControlOutput::Wrench {
+            thrust: setpoint.thrust / 65535.0 * self.vehicle.max_thrust,
+            torque: Vec3::from(angular_accel) * self.vehicle.inertia,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.position.reset();
+        for pid in self.attitude.iter_mut().chain(self.rate.iter_mut()) {
+            pid.reset();
+        }
+        self.yaw_target = None;
+    }
+
+    fn params(&self) -> Vec<(String, f32)> {
+        let mut params = Vec::new();
+        for (group, pids) in [("pid_attitude", &self.attitude), ("pid_rate", &self.rate)] {
+            for (axis, (name, pid)) in ATTITUDE_AXES.iter().zip(pids).enumerate() {
+                // Attitude gains map angles to rates, the same in any angle unit
+                let scale = match group {
+                    "pid_rate" => self.rate_gain_scale(axis),
+                    _ => 1.0,
+                };
+                for (gain, value) in GAINS.iter().zip([pid.kp, pid.ki, pid.kd]) {
+                    params.push((format!("{}.{}_{}", group, name, gain), value * scale));
+                }
+            }
+        }
+        params.extend(self.position.params());
+        params
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) -> bool {
+        if self.position.set_param(name, value) {
+            return true;
+        }
+        let Some((group, param)) = name.split_once('.') else {
+            return false;
+        };
+        let Some((axis, gain)) = param.split_once('_') else {
+            return false;
+        };
+        let Some(axis) = ATTITUDE_AXES.iter().position(|a| *a == axis) else {
+            return false;
+        };
+        let scale = match group {
+            "pid_rate" => self.rate_gain_scale(axis),
+            _ => 1.0,
+        };
+        match self
+            .pids_mut(group)
+            .and_then(|pids| pids[axis].gain_mut(gain))
+        {
+            Some(target) => {
+                *target = value / scale;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub(crate) fn wrap_angle(angle: f32) -> f32 {
+    use std::f32::consts::{PI, TAU};
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(controller: &PidController, name: &str) -> f32 {
+        controller
+            .params()
+            .into_iter()
+            .find(|(param, _)| param == name)
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn rate_gains_in_firmware_units() {
+        let mut controller = PidController::default();
+        assert!(controller.set_param("pid_rate.roll_kp", 250.0));
+        assert!((param(&controller, "pid_rate.roll_kp") - 250.0).abs() < 1e-3);
+        // 1 deg/s of error asks for 250 motor command units, split over four motors
+        let torque = 250.0 / 2.0 * 4.0 * controller.vehicle.max_thrust / 4.0 / 65535.0
+            * ARM_LENGTH
+            * std::f32::consts::FRAC_1_SQRT_2;
+        let accel = torque / controller.vehicle.inertia.x;
+        assert!((controller.rate[0].kp * 1.0f32.to_radians() - accel).abs() < 1e-3 * accel);
+
+        assert!(controller.set_param("pid_rate.yaw_ki", 16.7));
+        assert!((param(&controller, "pid_rate.yaw_ki") - 16.7).abs() < 1e-3);
+    }
+
+    #[test]
+    fn attitude_gains_unscaled() {
+        let mut controller = PidController::default();
+        assert!(controller.set_param("pid_attitude.pitch_kp", 6.0));
+        assert_eq!(controller.attitude[1].kp, 6.0);
+        assert_eq!(param(&controller, "pid_attitude.pitch_kp"), 6.0);
+    }
+
+    #[test]
+    fn unknown_params() {
+        let mut controller = PidController::default();
+        assert!(!controller.set_param("pid_rate.roll_kq", 1.0));
+        assert!(!controller.set_param("pid_thrust.roll_kp", 1.0));
+        assert!(!controller.set_param("rollkp", 1.0));
+    }
+}
@@ -0,0 +1,229 @@
+use glam::Vec3;
+
+use super::{pid::Pid, AxisMode, Setpoint, StateEstimate, VehicleParams};
+
+const GRAVITY: f32 = 9.81;
+/// Firmware `posCtlPid.thrustScale`, motor command units per unit of vz loop output.
+const THRUST_SCALE: f32 = 1000.0;
+
+/// Outer position/velocity loop, like the firmware's `position_controller_pid`.
+/// Turns world-frame position or velocity targets into roll/pitch and thrust
+/// for the attitude loop. Velocity and acceleration in an absolute position
+/// setpoint are used as feedforward.
+pub struct PositionController {
+    pub vehicle: VehicleParams,
+    pub position: [Pid; 3],
+    pub velocity: [Pid; 3],
+    pub max_velocity: Vec3, // m/s
+    pub max_tilt: f32,      // degrees
+}
+
+impl PositionController {
+    pub fn new(vehicle: VehicleParams) -> Self {
+        Self {
+            vehicle,
+            position: [
+                Pid::new(2.0, 0.0, 0.0, 0.0),
+                Pid::new(2.0, 0.0, 0.0, 0.0),
+                Pid::new(2.0, 0.0, 0.0, 0.0),
+            ],
+            velocity: [
+                Pid::new(4.0, 1.0, 0.0, 1.0),
+                Pid::new(4.0, 1.0, 0.0, 1.0),
+                Pid::new(6.0, 2.0, 0.0, 1.0),
+            ],
+            max_velocity: Vec3::new(1.0, 1.0, 1.0),
+            max_tilt: 20.0,
+        }
+    }
+
+    /// Returns a copy of `setpoint` with roll, pitch and thrust filled in for
+    /// every axis the position loop controls.
+    pub fn update(&mut self, setpoint: &Setpoint, state: &StateEstimate, dt: f32) -> Setpoint {
+        let modes = [setpoint.mode.x, setpoint.mode.y, setpoint.mode.z];
+        let mut accel = Vec3::ZERO;
+
+        for axis in 0..3 {
+            let velocity_target = match modes[axis] {
+                AxisMode::Abs => {
+                    let error = setpoint.position[axis] - state.position[axis];
+                    let limit = self.max_velocity[axis];
+                    let correction = self.position[axis].update(error, dt).clamp(-limit, limit);
+                    correction + setpoint.velocity[axis]
+                }
+                AxisMode::Velocity => {
+                    self.position[axis].reset();
+                    setpoint.velocity[axis]
+                }
+                AxisMode::Disable => {
+                    self.position[axis].reset();
+                    self.velocity[axis].reset();
+                    continue;
+                }
+            };
+            accel[axis] = self.velocity[axis].update(velocity_target - state.velocity[axis], dt)
+                + setpoint.acceleration[axis];
+        }
+
+        let mut out = *setpoint;
+        let yaw = state.rpy_degrees().z.to_radians();
+        let (sin_yaw, cos_yaw) = yaw.sin_cos();
+        let vertical = (accel.z + GRAVITY).max(0.0);
+        let max_tilt = self.max_tilt.to_radians();
+
+        if modes[0] != AxisMode::Disable || modes[1] != AxisMode::Disable {
+            // Rotate the horizontal demand into the heading frame
+            let forward = cos_yaw * accel.x + sin_yaw * accel.y;
+            let left = -sin_yaw * accel.x + cos_yaw * accel.y;
+            let pitch = forward.atan2(vertical).clamp(-max_tilt, max_tilt);
+            let roll = (-left).atan2(vertical).clamp(-max_tilt, max_tilt);
+            out.attitude.x = roll.to_degrees();
+            out.attitude.y = pitch.to_degrees();
+            out.mode.roll = AxisMode::Abs;
+            out.mode.pitch = AxisMode::Abs;
+        }
+
+        if modes[2] != AxisMode::Disable {
+            let tilt = out.attitude.x.to_radians().cos() * out.attitude.y.to_radians().cos();
+            let thrust = self.vehicle.mass * vertical / tilt.max(0.5);
+            out.thrust = (thrust / self.vehicle.max_thrust * 65535.0).clamp(0.0, 65535.0);
+        }
+
+        out
+    }
+
+    pub fn reset(&mut self) {
+        for pid in self.position.iter_mut().chain(self.velocity.iter_mut()) {
+            pid.reset();
+        }
+    }
+
+    /// Firmware velocity gain per gain of this velocity loop on `axis`. The
+    /// firmware outputs tilt in degrees for x and y and thrust over
+    /// [`THRUST_SCALE`] for z, where this loop outputs acceleration.
+    fn velocity_gain_scale(&self, axis: usize) -> f32 {
+        if axis == 2 {
+            self.vehicle.mass / self.vehicle.max_thrust * 65535.0 / THRUST_SCALE
+        } else {
+            // Small tilts accelerate by g per radian
+            1.0f32.to_degrees() / GRAVITY
+        }
+    }
+
+    /// Gains and limits under the firmware's `posCtlPid` names and units.
+    pub fn params(&self) -> Vec<(String, f32)> {
+        let mut params = Vec::new();
+        for (prefix, pids) in [("", &self.position), ("v", &self.velocity)] {
+            for (axis, (name, pid)) in ["x", "y", "z"].iter().zip(pids).enumerate() {
+                let scale = match prefix {
+                    "v" => self.velocity_gain_scale(axis),
+                    _ => 1.0,
+                };
+                for (gain, value) in ["Kp", "Ki", "Kd"].iter().zip([pid.kp, pid.ki, pid.kd]) {
+                    params.push((
+                        format!("posCtlPid.{}{}{}", prefix, name, gain),
+                        value * scale,
+                    ));
+                }
+            }
+        }
+        params.push(("posCtlPid.xyVelMax".into(), self.max_velocity.x));
+        params.push(("posCtlPid.zVelMax".into(), self.max_velocity.z));
+        params.push(("posCtlPid.rLimit".into(), self.max_tilt));
+        params.push(("posCtlPid.pLimit".into(), self.max_tilt));
+        params
+    }
+
+    /// Returns false if `name` isn't one of [`Self::params`].
+    pub fn set_param(&mut self, name: &str, value: f32) -> bool {
+        let Some(param) = name.strip_prefix("posCtlPid.") else {
+            return false;
+        };
+        match param {
+            "xyVelMax" => {
+                self.max_velocity.x = value;
+                self.max_velocity.y = value;
+                return true;
+            }
+            "zVelMax" => {
+                self.max_velocity.z = value;
+                return true;
+            }
+            // Roll and pitch share one tilt limit here
+            "rLimit" | "pLimit" => {
+                self.max_tilt = value;
+                return true;
+            }
+            _ => {}
+        }
+
+        let (velocity, param) = match param.strip_prefix('v') {
+            Some(rest) => (true, rest),
+            None => (false, param),
+        };
+        let axis = match param.get(..1) {
+            Some("x") => 0,
+            Some("y") => 1,
+            Some("z") => 2,
+            _ => return false,
+        };
+        let (pids, scale) = if velocity {
+            let scale = self.velocity_gain_scale(axis);
+            (&mut self.velocity, scale)
+        } else {
+            (&mut self.position, 1.0)
+        };
+        match pids[axis].gain_mut(&param[1..]) {
+            Some(target) => {
+                *target = value / scale;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Default for PositionController {
+    fn default() -> Self {
+        Self::new(VehicleParams::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(controller: &PositionController, name: &str) -> f32 {
+        controller
+            .params()
+            .into_iter()
+            .find(|(param, _)| param == name)
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn velocity_gains_in_firmware_units() {
+        let mut controller = PositionController::default();
+        // Close to the firmware's own default of 25
+        assert!((param(&controller, "posCtlPid.vxKp") - 25.0).abs() < 3.0);
+
+        for name in ["posCtlPid.vyKi", "posCtlPid.vzKp"] {
+            assert!(controller.set_param(name, 25.0));
+            assert!((param(&controller, name) - 25.0).abs() < 1e-3);
+        }
+        // 25 degrees of tilt per m/s of error, for small tilts
+        let accel = GRAVITY * 25.0f32.to_radians();
+        assert!((controller.velocity[1].ki - accel).abs() < 1e-3 * accel);
+    }
+
+    #[test]
+    fn position_gains_and_limits_unscaled() {
+        let mut controller = PositionController::default();
+        assert!(controller.set_param("posCtlPid.zKp", 2.5));
+        assert_eq!(controller.position[2].kp, 2.5);
+        assert!(controller.set_param("posCtlPid.xyVelMax", 0.5));
+        assert_eq!(controller.max_velocity.y, 0.5);
+        assert!(!controller.set_param("posCtlPid.wKp", 1.0));
+    }
+}
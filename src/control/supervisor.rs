@@ -0,0 +1,275 @@
+use glam::Vec3;
+
+use super::{AxisMode, Setpoint, StateEstimate};
+use crate::types::{CommanderStatus, SupervisorState};
+
+/// Tilt beyond which the drone counts as tumbled, in degrees.
+pub const TUMBLE_TILT: f32 = 60.0;
+/// How long the tilt must persist before the motors are cut.
+pub const TUMBLE_TIMEOUT: f32 = 1.0; // seconds
+/// Upside down cuts the motors much sooner.
+pub const UPSIDE_DOWN_TIMEOUT: f32 = 0.1; // seconds
+
+/// Safety state machine, like the firmware's `supervisor.c`. It decides
+/// whether the motors may run and locks them out after a crash, a watchdog
+/// shutdown in flight or an emergency stop.
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    pub auto_arm: bool, // Arm as soon as the pre-flight checks pass
+    state: SupervisorState,
+    armed: bool,
+    tilted_for: f32,      // seconds
+    upside_down_for: f32, // seconds
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
+
+impl Supervisor {
+    pub fn new(auto_arm: bool) -> Self {
+        Self {
+            auto_arm,
+            state: SupervisorState::PreFlight,
+            armed: auto_arm,
+            tilted_for: 0.0,
+            upside_down_for: 0.0,
+        }
+    }
+
+    pub fn state(&self) -> SupervisorState {
+        self.state
+    }
+
+    pub fn is_armed(&self) -> bool {
+        matches!(
+            self.state,
+            SupervisorState::ReadyToFly | SupervisorState::Flying | SupervisorState::Landed
+        )
+    }
+
+    pub fn motors_allowed(&self) -> bool {
+        self.is_armed()
+    }
+
+    pub fn is_tumbled(&self) -> bool {
+        self.tilted_for > TUMBLE_TIMEOUT || self.upside_down_for > UPSIDE_DOWN_TIMEOUT
+    }
+
+    /// Arming request. After a crash it also recovers, once upright again.
+    pub fn arm(&mut self) {
+        self.armed = true;
+        if self.state == SupervisorState::Crashed && !self.is_tumbled() {
+            self.state = SupervisorState::PreFlight;
+        }
+    }
+
+    pub fn disarm(&mut self) {
+        self.armed = false;
+        if self.is_armed() {
+            self.state = SupervisorState::PreFlight;
+        }
+    }
+
+    /// Cuts the motors until [`Supervisor::reset`]. The sim resets when
+    /// `stabilizer.stop` is set back to 0.
+    pub fn emergency_stop(&mut self) {
+        self.state = SupervisorState::EmergencyStopped;
+    }
+
+    /// Back to pre-flight from any state, as after a reboot.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.auto_arm);
+    }
+
+    /// Steps the state machine with the latest estimate and the setpoint
+    /// about to be flown.
+    pub fn update(
+        &mut self,
+        state: &StateEstimate,
+        setpoint: &Setpoint,
+        commander: CommanderStatus,
+        dt: f32,
+    ) {
+        let up = (state.attitude * Vec3::Z).z;
+        self.tilted_for = if up < TUMBLE_TILT.to_radians().cos() {
+            self.tilted_for + dt
+        } else {
+            0.0
+        };
+        self.upside_down_for = if up < -0.5 {
+            self.upside_down_for + dt
+        } else {
+            0.0
+        };
+
+        let wants_thrust = setpoint.thrust > 0.0 || setpoint.mode.z != AxisMode::Disable;
+        let checks_passed = state.position.is_finite() && up > 0.0 && !self.is_tumbled();
+
+        self.state = match self.state {
+            SupervisorState::Locked | SupervisorState::EmergencyStopped => self.state,
+            SupervisorState::Crashed => SupervisorState::Crashed,
+            _ if self.is_armed() && self.is_tumbled() => SupervisorState::Crashed,
+            SupervisorState::Flying if commander == CommanderStatus::TimedOut => {
+                SupervisorState::Locked
+            }
+            SupervisorState::PreFlight if self.armed && checks_passed => {
+                SupervisorState::ReadyToFly
+            }
+            SupervisorState::ReadyToFly | SupervisorState::Landed if wants_thrust => {
+                SupervisorState::Flying
+            }
+            SupervisorState::Flying if !wants_thrust => SupervisorState::Landed,
+            current => current,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Quat;
+
+    const DT: f32 = 0.01;
+
+    /// Runs the supervisor for `seconds` at `attitude`, asking for thrust.
+    fn fly(supervisor: &mut Supervisor, attitude: Quat, commander: CommanderStatus, seconds: f32) {
+        let state = StateEstimate {
+            position: Vec3::new(0.0, 0.0, 1.0),
+            attitude,
+            ..Default::default()
+        };
+        let setpoint = Setpoint {
+            thrust: 30000.0,
+            ..Default::default()
+        };
+        for _ in 0..(seconds / DT).round() as usize {
+            supervisor.update(&state, &setpoint, commander, DT);
+        }
+    }
+
+    fn flying() -> Supervisor {
+        let mut supervisor = Supervisor::default();
+        fly(
+            &mut supervisor,
+            Quat::IDENTITY,
+            CommanderStatus::Active,
+            0.05,
+        );
+        assert_eq!(supervisor.state(), SupervisorState::Flying);
+        supervisor
+    }
+
+    #[test]
+    fn tumbling_crashes_after_the_timeout() {
+        let mut supervisor = flying();
+        let tilted = Quat::from_rotation_x(70f32.to_radians());
+        fly(&mut supervisor, tilted, CommanderStatus::Active, 0.9);
+        assert_eq!(supervisor.state(), SupervisorState::Flying);
+        fly(&mut supervisor, tilted, CommanderStatus::Active, 0.2);
+        assert_eq!(supervisor.state(), SupervisorState::Crashed);
+        assert!(!supervisor.motors_allowed());
+
+        // A shorter tilt is forgiven once level again
+        let mut supervisor = flying();
+        fly(&mut supervisor, tilted, CommanderStatus::Active, 0.5);
+        fly(
+            &mut supervisor,
+            Quat::IDENTITY,
+            CommanderStatus::Active,
+            0.1,
+        );
+        fly(&mut supervisor, tilted, CommanderStatus::Active, 0.5);
+        assert_eq!(supervisor.state(), SupervisorState::Flying);
+    }
+
+    #[test]
+    fn upside_down_crashes_sooner() {
+        let mut supervisor = flying();
+        let upside_down = Quat::from_rotation_x(180f32.to_radians());
+        fly(&mut supervisor, upside_down, CommanderStatus::Active, 0.05);
+        assert_eq!(supervisor.state(), SupervisorState::Flying);
+        fly(&mut supervisor, upside_down, CommanderStatus::Active, 0.1);
+        assert_eq!(supervisor.state(), SupervisorState::Crashed);
+    }
+
+    #[test]
+    fn commander_timeout_in_flight_locks() {
+        let mut supervisor = flying();
+        fly(
+            &mut supervisor,
+            Quat::IDENTITY,
+            CommanderStatus::TimedOut,
+            DT,
+        );
+        assert_eq!(supervisor.state(), SupervisorState::Locked);
+        assert!(!supervisor.motors_allowed());
+
+        // Only a reset gets out of it
+        supervisor.arm();
+        fly(
+            &mut supervisor,
+            Quat::IDENTITY,
+            CommanderStatus::Active,
+            0.1,
+        );
+        assert_eq!(supervisor.state(), SupervisorState::Locked);
+        supervisor.reset();
+        assert_eq!(supervisor.state(), SupervisorState::PreFlight);
+        fly(
+            &mut supervisor,
+            Quat::IDENTITY,
+            CommanderStatus::Active,
+            0.05,
+        );
+        assert_eq!(supervisor.state(), SupervisorState::Flying);
+    }
+
+    #[test]
+    fn rearming_upright_recovers_from_a_crash() {
+        let mut supervisor = flying();
+        let upside_down = Quat::from_rotation_x(180f32.to_radians());
+        fly(&mut supervisor, upside_down, CommanderStatus::Active, 0.2);
+        assert_eq!(supervisor.state(), SupervisorState::Crashed);
+
+        // Still upside down, arming doesn't help
+        supervisor.arm();
+        assert_eq!(supervisor.state(), SupervisorState::Crashed);
+
+        fly(&mut supervisor, Quat::IDENTITY, CommanderStatus::Active, DT);
+        assert_eq!(supervisor.state(), SupervisorState::Crashed);
+        supervisor.arm();
+        assert_eq!(supervisor.state(), SupervisorState::PreFlight);
+        fly(
+            &mut supervisor,
+            Quat::IDENTITY,
+            CommanderStatus::Active,
+            0.05,
+        );
+        assert_eq!(supervisor.state(), SupervisorState::Flying);
+    }
+
+    #[test]
+    fn emergency_stop_holds_until_reset() {
+        let mut supervisor = flying();
+        supervisor.emergency_stop();
+        supervisor.arm();
+        fly(
+            &mut supervisor,
+            Quat::IDENTITY,
+            CommanderStatus::Active,
+            0.1,
+        );
+        assert_eq!(supervisor.state(), SupervisorState::EmergencyStopped);
+        supervisor.reset();
+        fly(
+            &mut supervisor,
+            Quat::IDENTITY,
+            CommanderStatus::Active,
+            0.05,
+        );
+        assert_eq!(supervisor.state(), SupervisorState::Flying);
+    }
+}
@@ -1,16 +1,28 @@
-use crazyflie_lib::Crazyflie;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use anyhow::Result;
-use types::{DroneInterface, DroneState, DroneCommand, RpytCommand};
+use crazyflie_lib::{Crazyflie, Value, ValueType};
+use crazyflie_lib::subsystems::high_level_commander::TrajectoryType;
+use crazyflie_lib::subsystems::memory::{MemoryType, RawMemory};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::{mpsc, watch};
+use crate::control::commander::status_for_age;
+use crate::estimator::GRAVITY;
+use crate::trajectory::Trajectory;
+use crate::types::{DroneInterface, DroneState, DroneCommand, RpytCommand, PositionCommand, VelocityCommand, ZDistanceCommand, HoverCommand, HighLevelCommand, SupervisorState, AssistMode, Ranges, DroneError, Capabilities, CommandSupport, Sensors, ParamInfo, ParamType, ParamValue, LogSample, LogStream, LogVariable};
 
 pub struct CrazyflieDriver {
     cf: Arc<Crazyflie>,
-    state: Arc<Mutex<DroneState>>,
+    state: Arc<watch::Sender<DroneState>>,
+    trajectory_offset: u32, // Next free byte in trajectory memory
+    trajectory_slots: HashMap<u8, (u32, u32)>, // Offset and size in trajectory memory, by id
+    last_setpoint: Arc<Mutex<Option<Instant>>>, // Low-level setpoints only, the high-level commander runs on board
+    capabilities: Capabilities,
 }
 
 impl CrazyflieDriver {
-    pub async fn new(uri: &str) -> Result<Self> {
+    pub async fn new(uri: &str) -> Result<Self, DroneError> {
         let link_ctx = crazyflie_lib::LinkContext::new();
         let cf = Arc::new(Crazyflie::connect_from_uri(&link_ctx, uri).await?);
         
@@ -20,65 +32,527 @@ impl CrazyflieDriver {
         block.add_variable("stabilizer.pitch").await?;
         block.add_variable("stabilizer.yaw").await?;
         block.add_variable("pm.vbat").await?;
+        block.add_variable("supervisor.info").await?;
         
+        // Position goes in a second block, one packet can't hold it all
+        let mut position_block = cf.log.create_block().await?;
+        position_block.add_variable("stateEstimate.x").await?;
+        position_block.add_variable("stateEstimate.y").await?;
+        position_block.add_variable("stateEstimate.z").await?;
+        position_block.add_variable("stateEstimate.vx").await?;
+        position_block.add_variable("stateEstimate.vy").await?;
+        position_block.add_variable("stateEstimate.vz").await?;
+
+        // Acceleration (in G) and body rates
+        let mut motion_block = cf.log.create_block().await?;
+        motion_block.add_variable("stateEstimate.ax").await?;
+        motion_block.add_variable("stateEstimate.ay").await?;
+        motion_block.add_variable("stateEstimate.az").await?;
+        motion_block.add_variable("gyro.x").await?;
+        motion_block.add_variable("gyro.y").await?;
+        motion_block.add_variable("gyro.z").await?;
+
+        let mut attitude_block = cf.log.create_block().await?;
+        attitude_block.add_variable("stateEstimate.qx").await?;
+        attitude_block.add_variable("stateEstimate.qy").await?;
+        attitude_block.add_variable("stateEstimate.qz").await?;
+        attitude_block.add_variable("stateEstimate.qw").await?;
+
         let period = crazyflie_lib::LogPeriod::from_millis(10)?; // 100Hz
         let mut stream = block.start(period).await?;
+        let mut position_stream = position_block.start(crazyflie_lib::LogPeriod::from_millis(10)?).await?;
+        let mut motion_stream = motion_block.start(crazyflie_lib::LogPeriod::from_millis(10)?).await?;
+        let mut attitude_stream = attitude_block.start(crazyflie_lib::LogPeriod::from_millis(10)?).await?;
         
         // Start state update task
-        let state = Arc::new(Mutex::new(DroneState::default()));
+        let (state, _) = watch::channel(DroneState::default());
+        let state = Arc::new(state);
         let state_clone = state.clone();
         tokio::spawn(async move {
             while let Ok(data) = stream.next().await {
-                let mut state = state_clone.lock().await;
-                if let Some(value) = data.get("stabilizer.roll") {
-                    state.roll = value.as_f32().unwrap_or(0.0);
-                }
-                if let Some(value) = data.get("stabilizer.pitch") {
-                    state.pitch = value.as_f32().unwrap_or(0.0);
-                }
-                if let Some(value) = data.get("stabilizer.yaw") {
-                    state.yaw = value.as_f32().unwrap_or(0.0);
-                }
-                if let Some(value) = data.get("pm.vbat") {
-                    state.battery_voltage = value.as_f32().unwrap_or(0.0);
+                state_clone.send_modify(|state| {
+                    if let Some(value) = data.get("stabilizer.roll") {
+                        state.roll = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("stabilizer.pitch") {
+                        state.pitch = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("stabilizer.yaw") {
+                        state.yaw = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("pm.vbat") {
+                        state.battery_voltage = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("supervisor.info") {
+                        let info = value.as_f32().unwrap_or(0.0) as u16;
+                        state.supervisor = supervisor_state(info, state.supervisor);
+                        state.armed = info & SUPERVISOR_IS_ARMED != 0;
+                    }
+                });
+            }
+        });
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            while let Ok(data) = position_stream.next().await {
+                state_clone.send_modify(|state| {
+                    if let Some(value) = data.get("stateEstimate.x") {
+                        state.x = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("stateEstimate.y") {
+                        state.y = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("stateEstimate.z") {
+                        state.z = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("stateEstimate.vx") {
+                        state.vx = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("stateEstimate.vy") {
+                        state.vy = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("stateEstimate.vz") {
+                        state.vz = value.as_f32().unwrap_or(0.0);
+                    }
+                    // Firmware timestamp, milliseconds since boot
+                    state.timestamp = data.timestamp as f64 / 1000.0;
+                });
+            }
+        });
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            while let Ok(data) = motion_stream.next().await {
+                state_clone.send_modify(|state| {
+                    if let Some(value) = data.get("stateEstimate.ax") {
+                        state.ax = value.as_f32().unwrap_or(0.0) * GRAVITY;
+                    }
+                    if let Some(value) = data.get("stateEstimate.ay") {
+                        state.ay = value.as_f32().unwrap_or(0.0) * GRAVITY;
+                    }
+                    if let Some(value) = data.get("stateEstimate.az") {
+                        state.az = value.as_f32().unwrap_or(0.0) * GRAVITY;
+                    }
+                    if let Some(value) = data.get("gyro.x") {
+                        state.roll_rate = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("gyro.y") {
+                        state.pitch_rate = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("gyro.z") {
+                        state.yaw_rate = value.as_f32().unwrap_or(0.0);
+                    }
+                });
+            }
+        });
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            while let Ok(data) = attitude_stream.next().await {
+                state_clone.send_modify(|state| {
+                    if let Some(value) = data.get("stateEstimate.qx") {
+                        state.qx = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("stateEstimate.qy") {
+                        state.qy = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("stateEstimate.qz") {
+                        state.qz = value.as_f32().unwrap_or(0.0);
+                    }
+                    if let Some(value) = data.get("stateEstimate.qw") {
+                        state.qw = value.as_f32().unwrap_or(1.0);
+                    }
+                });
+            }
+        });
+
+        // Range sensors are optional, their variables only exist with the decks fitted
+        let mut range_block = cf.log.create_block().await?;
+        let mut has_ranges = false;
+        for name in RANGE_VARIABLES {
+            has_ranges |= range_block.add_variable(name).await.is_ok();
+        }
+        if has_ranges {
+            let mut range_stream = range_block.start(crazyflie_lib::LogPeriod::from_millis(20)?).await?;
+            let state_clone = state.clone();
+            tokio::spawn(async move {
+                while let Ok(data) = range_stream.next().await {
+                    state_clone.send_modify(|state| {
+                        for name in RANGE_VARIABLES {
+                            if let Some(value) = data.get(name) {
+                                set_range(&mut state.ranges, name, value.as_f32().unwrap_or(0.0));
+                            }
+                        }
+                    });
                 }
+            });
+        }
+
+        // Age the watchdog stage in the published state, setpoints or not
+        let last_setpoint = Arc::new(Mutex::new(None));
+        let weak_setpoint = Arc::downgrade(&last_setpoint);
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_millis(50));
+            loop {
+                interval.tick().await;
+                let Some(last_setpoint) = weak_setpoint.upgrade() else { break };
+                update_commander(&state_clone, &last_setpoint);
             }
         });
 
-        Ok(Self { cf, state })
+        let capabilities = detect_capabilities(&cf).await;
+        Ok(Self { cf, state, trajectory_offset: 0, trajectory_slots: HashMap::new(), last_setpoint, capabilities })
+    }
+
+    fn check_param(&self, name: &str) -> Result<(), DroneError> {
+        if !self.cf.param.names().iter().any(|param| param == name) {
+            return Err(DroneError::InvalidArgument(format!("unknown parameter {}", name)));
+        }
+        Ok(())
+    }
+}
+
+/// Mirrors the firmware watchdog, which isn't logged, into the state.
+fn update_commander(state: &watch::Sender<DroneState>, last_setpoint: &Mutex<Option<Instant>>) {
+    let status = status_for_age(last_setpoint.lock().unwrap().map(|t| t.elapsed().as_secs_f32()));
+    state.send_if_modified(|state| {
+        let changed = state.commander != status;
+        state.commander = status;
+        changed
+    });
+}
+
+// Decks with a `deck.<name>` flag in the firmware
+const DECKS: [&str; 10] = [
+    "bcFlow", "bcFlow2", "bcZRanger", "bcZRanger2", "bcMultiranger",
+    "bcLighthouse4", "bcLoco", "bcAI", "bcLedRing", "bcUSD",
+];
+
+async fn detect_capabilities(cf: &Crazyflie) -> Capabilities {
+    // Flags of decks the firmware doesn't know are missing, same as not fitted
+    let mut decks = Vec::new();
+    for name in DECKS {
+        if cf.param.get::<u8>(&format!("deck.{}", name)).await.unwrap_or(0) != 0 {
+            decks.push(name.to_string());
+        }
+    }
+    let has = |names: &[&str]| names.iter().any(|name| decks.iter().any(|deck| deck == name));
+
+    let firmware = cf.param.get::<u32>("firmware.revision0").await.ok().map(|revision| format!("{:08x}", revision));
+    let trajectory_memory = cf.memory.get_memories(Some(MemoryType::Trajectory))
+        .into_iter()
+        .next()
+        .map(|memory| memory.size as usize);
+
+    Capabilities {
+        commands: CommandSupport { full_state: false, trajectories: trajectory_memory.is_some(), ..CommandSupport::all() },
+        sensors: Sensors {
+            imu: true,
+            battery: true,
+            position: has(&["bcLighthouse4", "bcLoco"]),
+            flow: has(&["bcFlow", "bcFlow2"]),
+            z_range: has(&["bcFlow", "bcFlow2", "bcZRanger", "bcZRanger2"]),
+            multiranger: has(&["bcMultiranger"]),
+        },
+        decks,
+        firmware,
+        trajectory_memory,
+        max_setpoint_rate: 100.0, // The position loop runs at 100Hz
+        state_rate: 100.0, // Log period
     }
 }
 
+// The firmware only declares 8 to 32 bit variables, and half floats for some logs
+fn typed_value(value: Value) -> Option<ParamValue> {
+    match value {
+        Value::U8(v) => Some(ParamValue::U8(v)),
+        Value::U16(v) => Some(ParamValue::U16(v)),
+        Value::U32(v) => Some(ParamValue::U32(v)),
+        Value::I8(v) => Some(ParamValue::I8(v)),
+        Value::I16(v) => Some(ParamValue::I16(v)),
+        Value::I32(v) => Some(ParamValue::I32(v)),
+        Value::F32(v) => Some(ParamValue::F32(v)),
+        Value::F16(v) => Some(ParamValue::F32(v.to_f32())),
+        _ => None,
+    }
+}
+
+fn typed_kind(kind: ValueType) -> Option<ParamType> {
+    match kind {
+        ValueType::U8 => Some(ParamType::U8),
+        ValueType::U16 => Some(ParamType::U16),
+        ValueType::U32 => Some(ParamType::U32),
+        ValueType::I8 => Some(ParamType::I8),
+        ValueType::I16 => Some(ParamType::I16),
+        ValueType::I32 => Some(ParamType::I32),
+        ValueType::F32 | ValueType::F16 => Some(ParamType::F32),
+        _ => None,
+    }
+}
+
+impl From<crazyflie_lib::Error> for DroneError {
+    fn from(err: crazyflie_lib::Error) -> Self {
+        DroneError::Link(err.to_string())
+    }
+}
+
+// Multi-ranger and Flow deck distances, in millimeters
+const RANGE_VARIABLES: [&str; 6] =
+    ["range.front", "range.back", "range.left", "range.right", "range.up", "range.zrange"];
+const RANGE_MAX_MM: f32 = 4000.0;
+
+fn set_range(ranges: &mut Ranges, name: &str, millimeters: f32) {
+    // Out of range reads as a large value
+    let meters = (millimeters < RANGE_MAX_MM).then_some(millimeters / 1000.0);
+    match name {
+        "range.front" => ranges.front = meters,
+        "range.back" => ranges.back = meters,
+        "range.left" => ranges.left = meters,
+        "range.right" => ranges.right = meters,
+        "range.up" => ranges.up = meters,
+        "range.zrange" => ranges.down = meters,
+        _ => {}
+    }
+}
+
+// Bits of the firmware's `supervisor.info` log variable
+const SUPERVISOR_IS_ARMED: u16 = 1 << 1;
+const SUPERVISOR_CAN_FLY: u16 = 1 << 3;
+const SUPERVISOR_IS_FLYING: u16 = 1 << 4;
+const SUPERVISOR_IS_TUMBLED: u16 = 1 << 5;
+const SUPERVISOR_IS_LOCKED: u16 = 1 << 6;
+const SUPERVISOR_IS_CRASHED: u16 = 1 << 7;
+
+fn supervisor_state(info: u16, previous: SupervisorState) -> SupervisorState {
+    if previous == SupervisorState::EmergencyStopped {
+        // Holds until reboot, whatever the firmware reports
+        previous
+    } else if info & SUPERVISOR_IS_LOCKED != 0 {
+        SupervisorState::Locked
+    } else if info & (SUPERVISOR_IS_CRASHED | SUPERVISOR_IS_TUMBLED) != 0 {
+        SupervisorState::Crashed
+    } else if info & SUPERVISOR_IS_FLYING != 0 {
+        SupervisorState::Flying
+    } else if info & SUPERVISOR_CAN_FLY != 0 {
+        match previous {
+            SupervisorState::Flying | SupervisorState::Landed => SupervisorState::Landed,
+            _ => SupervisorState::ReadyToFly,
+        }
+    } else {
+        SupervisorState::PreFlight
+    }
+}
+
+#[async_trait]
 impl DroneInterface for CrazyflieDriver {
-    async fn init(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    async fn init(&mut self) -> Result<(), DroneError> {
         // Safety: Send initial zero thrust to unlock
         self.cf.commander.setpoint_rpyt(0.0, 0.0, 0.0, 0).await?;
         Ok(())
     }
     
-    async fn get_state(&self) -> Result<DroneState, Box<dyn std::error::Error>> {
-        Ok(self.state.lock().await.clone())
+    async fn get_state(&self) -> Result<DroneState, DroneError> {
+        update_commander(&self.state, &self.last_setpoint);
+        Ok(*self.state.borrow())
+    }
+
+    fn subscribe_state(&self) -> watch::Receiver<DroneState> {
+        self.state.subscribe()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities.clone()
     }
     
-    async fn send_command(&mut self, cmd: DroneCommand) -> Result<(), Box<dyn std::error::Error>> {
+    async fn send_command(&mut self, cmd: DroneCommand) -> Result<(), DroneError> {
+        // Only streamed setpoints feed the watchdog, anything that ends the stream stops it
+        let watchdog = if cmd.is_setpoint() {
+            Some(true)
+        } else if matches!(cmd, DroneCommand::HighLevel(_) | DroneCommand::NotifySetpointStop { .. } | DroneCommand::Stop | DroneCommand::Disarm | DroneCommand::EmergencyStop) {
+            Some(false)
+        } else {
+            None
+        };
         match cmd {
             DroneCommand::Rpyt(RpytCommand { roll, pitch, yaw, thrust }) => {
                 self.cf.commander.setpoint_rpyt(roll, pitch, yaw, thrust).await?;
-                let mut state = self.state.lock().await;
-                state.thrust = thrust;
-                state.armed = thrust > 0;
+                self.state.send_modify(|state| state.thrust = thrust);
             },
-            DroneCommand::Arm => {
-                self.cf.commander.setpoint_rpyt(0.0, 0.0, 0.0, 0).await?;
-                let mut state = self.state.lock().await;
-                state.armed = true;
+            DroneCommand::Position(PositionCommand { x, y, z, yaw }) => {
+                self.cf.commander.setpoint_position(x, y, z, yaw).await?;
+            },
+            DroneCommand::Velocity(VelocityCommand { vx, vy, vz, yaw_rate }) => {
+                self.cf.commander.setpoint_velocity_world(vx, vy, vz, yaw_rate).await?;
+            },
+            DroneCommand::ZDistance(ZDistanceCommand { roll, pitch, yaw_rate, z_distance }) => {
+                self.cf.commander.setpoint_zdistance(roll, pitch, yaw_rate, z_distance).await?;
+            },
+            DroneCommand::Hover(HoverCommand { vx, vy, yaw_rate, z_distance }) => {
+                self.cf.commander.setpoint_hover(vx, vy, yaw_rate, z_distance).await?;
+            },
+            DroneCommand::FullState(_) => {
+                // crazyflie-lib has no full-state setpoint yet
+                return Err(DroneError::UnsupportedCommand(cmd.name()));
+            },
+            DroneCommand::Stop => {
+                self.cf.commander.setpoint_stop().await?;
             },
-            DroneCommand::Disarm => {
+            DroneCommand::NotifySetpointStop { remain_valid_ms } => {
+                self.cf.commander.notify_setpoint_stop(remain_valid_ms).await?;
+            },
+            DroneCommand::HighLevel(cmd) => {
+                let hl = &self.cf.high_level_commander;
+                match cmd {
+                    HighLevelCommand::Takeoff { height, duration } => {
+                        hl.take_off(height, None, duration, None).await?;
+                    },
+                    HighLevelCommand::Land { height, duration } => {
+                        hl.land(height, None, duration, None).await?;
+                    },
+                    HighLevelCommand::GoTo { x, y, z, yaw, duration, relative } => {
+                        hl.go_to(x, y, z, yaw.to_radians(), duration, relative, false, None).await?;
+                    },
+                    HighLevelCommand::StartTrajectory { id, time_scale, relative, reversed } => {
+                        hl.start_trajectory(id, time_scale, relative, reversed, None).await?;
+                    },
+                    HighLevelCommand::Stop => {
+                        hl.stop(None).await?;
+                    },
+                }
+            },
+            DroneCommand::Assist(mode) => {
+                // The firmware does the stick mapping on board
+                let althold = (mode == AssistMode::AltitudeHold) as u8;
+                self.cf.param.set("flightmode.althold", althold).await?;
+            },
+            DroneCommand::Arm | DroneCommand::Disarm => {
+                // Armed state comes back through `supervisor.info`
                 self.cf.commander.setpoint_rpyt(0.0, 0.0, 0.0, 0).await?;
-                let mut state = self.state.lock().await;
-                state.armed = false;
+            },
+            DroneCommand::EmergencyStop => {
+                self.cf.param.set("stabilizer.stop", 1u8).await?;
+                self.state.send_modify(|state| state.supervisor = SupervisorState::EmergencyStopped);
             }
         }
+
+        // Only once sent, a failed send leaves the watchdog as it was
+        if let Some(setpoint) = watchdog {
+            *self.last_setpoint.lock().unwrap() = setpoint.then(Instant::now);
+            update_commander(&self.state, &self.last_setpoint);
+        }
         Ok(())
     }
+
+    async fn upload_trajectory(&mut self, id: u8, trajectory: &Trajectory) -> Result<(), DroneError> {
+        trajectory.validate().map_err(|err| DroneError::InvalidArgument(format!("trajectory {}: {}", id, err)))?;
+        let pieces = u8::try_from(trajectory.pieces.len())
+            .map_err(|_| DroneError::InvalidArgument(format!("trajectory {} has {} pieces, at most 255 fit", id, trajectory.pieces.len())))?;
+        let data = trajectory.to_poly4d_bytes();
+        let memory = self.cf.memory.get_memories(Some(MemoryType::Trajectory))
+            .into_iter()
+            .next()
+            .ok_or(DroneError::UnsupportedCommand("trajectory upload"))?;
+
+        // Re-uploading an id reuses its slot if the new trajectory fits, or if it's the last slot and can grow
+        let size = data.len() as u32;
+        let offset = match self.trajectory_slots.get(&id) {
+            Some(&(offset, capacity)) if size <= capacity || offset + capacity == self.trajectory_offset => offset,
+            _ => self.trajectory_offset,
+        };
+        if offset as usize + data.len() > memory.size as usize {
+            return Err(DroneError::InvalidArgument(format!("trajectory {} doesn't fit in the remaining trajectory memory", id)));
+        }
+
+        let mut raw = self.cf.memory.open_memory::<RawMemory>(memory).await
+            .ok_or_else(|| DroneError::Link("trajectory memory unavailable".to_string()))??;
+        raw.write(offset as usize, &data).await?;
+        self.cf.high_level_commander
+            .define_trajectory(id, offset, pieces, TrajectoryType::Poly4d)
+            .await?;
+
+        let capacity = match self.trajectory_slots.get(&id) {
+            Some(&(previous, capacity)) if previous == offset => capacity.max(size),
+            _ => size,
+        };
+        self.trajectory_slots.insert(id, (offset, capacity));
+        self.trajectory_offset = self.trajectory_offset.max(offset + capacity);
+        Ok(())
+    }
+
+    async fn get_param(&self, name: &str) -> Result<ParamValue, DroneError> {
+        self.check_param(name)?;
+        let value = self.cf.param.get::<Value>(name).await?;
+        typed_value(value).ok_or_else(|| DroneError::InvalidArgument(format!("{} has an unsupported type", name)))
+    }
+
+    async fn set_param(&mut self, name: &str, value: ParamValue) -> Result<(), DroneError> {
+        self.check_param(name)?;
+        // Converted to whatever type the firmware declares
+        self.cf.param.set_lossy(name, value.as_f64()).await?;
+        Ok(())
+    }
+
+    async fn list_params(&self) -> Result<Vec<ParamInfo>, DroneError> {
+        // Only names are exposed from the TOC, so read each value for its type
+        let mut params = Vec::new();
+        for name in self.cf.param.names() {
+            if let Some(value) = typed_value(self.cf.param.get::<Value>(&name).await?) {
+                params.push(ParamInfo { name, kind: value.kind() });
+            }
+        }
+        Ok(params)
+    }
+
+    async fn list_log_variables(&self) -> Result<Vec<LogVariable>, DroneError> {
+        let mut variables = Vec::new();
+        for name in self.cf.log.names() {
+            if let Some(kind) = typed_kind(self.cf.log.get_type(&name)?) {
+                variables.push(LogVariable { name, kind });
+            }
+        }
+        Ok(variables)
+    }
+
+    async fn subscribe_log(&self, variables: &[&str], period: Duration) -> Result<LogStream, DroneError> {
+        if variables.is_empty() {
+            return Err(DroneError::InvalidArgument("a log subscription needs variables".to_string()));
+        }
+        // 10ms steps up to 2.55s
+        let log_period = crazyflie_lib::LogPeriod::from_millis(period.as_millis() as u64)
+            .map_err(|err| DroneError::InvalidArgument(format!("log period {:?}: {}", period, err)))?;
+
+        let names = self.cf.log.names();
+        let mut block = self.cf.log.create_block().await?;
+        for &name in variables {
+            if !names.iter().any(|variable| variable == name) {
+                return Err(DroneError::InvalidArgument(format!("unknown log variable {}", name)));
+            }
+            // A block holds one radio packet of data
+            block.add_variable(name).await
+                .map_err(|err| DroneError::InvalidArgument(format!("can't log {}: {}", name, err)))?;
+        }
+
+        let variables: Vec<String> = variables.iter().map(|name| name.to_string()).collect();
+        let mut stream = block.start(log_period).await?;
+        let (samples, sample_rx) = mpsc::channel(32);
+        let names = variables.clone();
+        tokio::spawn(async move {
+            while let Ok(data) = stream.next().await {
+                // A value that's missing or can't be decoded drops the whole sample, a zero would pass for data
+                let Some(values) = names.iter()
+                    .map(|name| data.get(name).cloned().and_then(typed_value))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    tracing::debug!("Dropping a log sample with missing values");
+                    continue;
+                };
+                let sample = LogSample { timestamp: data.timestamp as f64 / 1000.0, values };
+                // Dropping the log stream ends the subscription, a slow reader misses samples
+                if let Err(mpsc::error::TrySendError::Closed(_)) = samples.try_send(sample) {
+                    break;
+                }
+            }
+            // Frees the block on the Crazyflie
+            let _ = stream.stop().await;
+        });
+        Ok(LogStream::new(variables, sample_rx))
+    }
 }
\ No newline at end of file
@@ -0,0 +1,118 @@
+use async_trait::async_trait;
+use std::{collections::HashMap, time::Duration};
+use tokio::sync::watch;
+
+use crate::{
+    trajectory::Trajectory,
+    types::{
+        Capabilities, CommandSupport, DroneCommand, DroneError, DroneInterface, DroneState,
+        LogStream, LogVariable, ParamInfo, ParamValue,
+    },
+};
+
+/// In-memory drone for tests and dry runs. Every command is accepted and
+/// recorded, and the state only changes through [`MockDriver::set_state`].
+pub struct MockDriver {
+    state: watch::Sender<DroneState>,
+    pub sent: Vec<DroneCommand>,
+    pub trajectories: HashMap<u8, Trajectory>,
+    pub params: HashMap<String, ParamValue>,
+}
+
+impl MockDriver {
+    pub fn new() -> Self {
+        Self::with_state(DroneState::default())
+    }
+
+    pub fn with_state(state: DroneState) -> Self {
+        Self {
+            state: watch::channel(state).0,
+            sent: Vec::new(),
+            trajectories: HashMap::new(),
+            params: HashMap::new(),
+        }
+    }
+
+    /// Publishes `state` to subscribers, as if the drone reported it.
+    pub fn set_state(&self, state: DroneState) {
+        self.state.send_replace(state);
+    }
+}
+
+impl Default for MockDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl DroneInterface for MockDriver {
+    async fn init(&mut self) -> Result<(), DroneError> {
+        Ok(())
+    }
+
+    async fn get_state(&self) -> Result<DroneState, DroneError> {
+        Ok(*self.state.borrow())
+    }
+
+    fn subscribe_state(&self) -> watch::Receiver<DroneState> {
+        self.state.subscribe()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            commands: CommandSupport::all(),
+            ..Default::default()
+        }
+    }
+
+    async fn send_command(&mut self, cmd: DroneCommand) -> Result<(), DroneError> {
+        self.sent.push(cmd);
+        Ok(())
+    }
+
+    async fn upload_trajectory(
+        &mut self,
+        id: u8,
+        trajectory: &Trajectory,
+    ) -> Result<(), DroneError> {
+        self.trajectories.insert(id, trajectory.clone());
+        Ok(())
+    }
+
+    async fn get_param(&self, name: &str) -> Result<ParamValue, DroneError> {
+        self.params
+            .get(name)
+            .copied()
+            .ok_or_else(|| DroneError::InvalidArgument(format!("unknown parameter {}", name)))
+    }
+
+    /// Any name can be set, and keeps the type it was set with.
+    async fn set_param(&mut self, name: &str, value: ParamValue) -> Result<(), DroneError> {
+        self.params.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    async fn list_params(&self) -> Result<Vec<ParamInfo>, DroneError> {
+        Ok(self
+            .params
+            .iter()
+            .map(|(name, value)| ParamInfo {
+                name: name.clone(),
+                kind: value.kind(),
+            })
+            .collect())
+    }
+
+    async fn list_log_variables(&self) -> Result<Vec<LogVariable>, DroneError> {
+        Ok(Vec::new())
+    }
+
+    async fn subscribe_log(
+        &self,
+        _variables: &[&str],
+        _period: Duration,
+    ) -> Result<LogStream, DroneError> {
+        Err(DroneError::UnsupportedCommand("log subscription"))
+    }
+}
@@ -1 +1,12 @@
-pub mod crazyflie;
\ No newline at end of file
+#[cfg(feature = "crazyflie")]
+pub mod crazyflie;
+pub mod mock;
+pub mod replay;
+#[cfg(feature = "sim")]
+pub mod sim;
+
+mod registry;
+mod uri;
+
+pub use registry::{open, register, DriverRegistry};
+pub use uri::DriverUri;
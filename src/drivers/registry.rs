@@ -0,0 +1,136 @@
+use futures_util::future::BoxFuture;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+#[cfg(feature = "crazyflie")]
+use super::crazyflie::CrazyflieDriver;
+#[cfg(feature = "sim")]
+use super::sim::{SimOptions, SimulationDriver};
+use super::{mock::MockDriver, replay::ReplayDriver, DriverUri};
+use crate::types::{DroneError, DroneInterface};
+
+type Backend = Arc<
+    dyn Fn(DriverUri) -> BoxFuture<'static, Result<Box<dyn DroneInterface>, DroneError>>
+        + Send
+        + Sync,
+>;
+
+/// Opens drivers from URIs, by scheme. The default registry knows:
+///
+/// - `sim://default?headless=1`: one drone in a new sim world, with the
+///   `sim` feature
+/// - `radio://0/80/2M/E7E7E7E7E7`, `usb://0`: a Crazyflie over crazyflie-lib,
+///   with the `crazyflie` feature
+/// - `replay://flight.mcap?rate=2`: a recording played back, see [`ReplayDriver`]
+/// - `mock://`: a [`MockDriver`]
+#[derive(Clone)]
+pub struct DriverRegistry {
+    backends: HashMap<String, Backend>,
+}
+
+impl DriverRegistry {
+    /// A registry without any backends.
+    pub fn empty() -> Self {
+        Self {
+            backends: HashMap::new(),
+        }
+    }
+
+    /// Opens `scheme://` URIs with `open`, replacing any backend already
+    /// registered for the scheme.
+    pub fn register<F, Fut, D>(&mut self, scheme: &str, open: F)
+    where
+        F: Fn(DriverUri) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<D, DroneError>> + Send + 'static,
+        D: DroneInterface + 'static,
+    {
+        let backend: Backend = Arc::new(move |uri| {
+            let driver = open(uri);
+            Box::pin(async move { Ok(Box::new(driver.await?) as Box<dyn DroneInterface>) })
+        });
+        self.backends.insert(scheme.to_ascii_lowercase(), backend);
+    }
+
+    pub fn schemes(&self) -> Vec<&str> {
+        let mut schemes: Vec<&str> = self.backends.keys().map(String::as_str).collect();
+        schemes.sort();
+        schemes
+    }
+
+    pub async fn open(&self, uri: &str) -> Result<Box<dyn DroneInterface>, DroneError> {
+        let uri = DriverUri::parse(uri)?;
+        let backend = self.backend(&uri.scheme)?;
+        backend(uri).await
+    }
+
+    fn backend(&self, scheme: &str) -> Result<Backend, DroneError> {
+        self.backends.get(scheme).cloned().ok_or_else(|| {
+            DroneError::InvalidArgument(format!(
+                "no driver for {}://, known schemes are {}",
+                scheme,
+                self.schemes().join(", ")
+            ))
+        })
+    }
+}
+
+impl Default for DriverRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        #[cfg(feature = "sim")]
+        registry.register("sim", |uri: DriverUri| async move {
+            if !matches!(uri.location.as_str(), "" | "default") {
+                return Err(DroneError::InvalidArgument(format!(
+                    "unknown sim world {:?}",
+                    uri.location
+                )));
+            }
+            let options = SimOptions {
+                headless: uri.flag("headless"),
+                ..Default::default()
+            };
+            SimulationDriver::with_options(options).await
+        });
+        // crazyflie-lib parses the rest of the URI itself
+        #[cfg(feature = "crazyflie")]
+        for scheme in ["radio", "usb"] {
+            registry.register(scheme, |uri: DriverUri| async move {
+                CrazyflieDriver::new(&uri.to_string()).await
+            });
+        }
+        registry.register("replay", |uri: DriverUri| async move {
+            let rate = uri.get("rate")?.unwrap_or(1.0);
+            ReplayDriver::open(&uri.location, rate).await
+        });
+        registry.register("mock", |_| async { Ok(MockDriver::new()) });
+        registry
+    }
+}
+
+fn global() -> &'static RwLock<DriverRegistry> {
+    static REGISTRY: OnceLock<RwLock<DriverRegistry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(DriverRegistry::default()))
+}
+
+/// Adds a backend to the registry behind [`open`], e.g. from `main` before
+/// the configured URI is opened.
+pub fn register<F, Fut, D>(scheme: &str, open: F)
+where
+    F: Fn(DriverUri) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<D, DroneError>> + Send + 'static,
+    D: DroneInterface + 'static,
+{
+    global().write().unwrap().register(scheme, open);
+}
+
+/// Opens a driver from a URI with the built-in backends and any added
+/// with [`register`].
+pub async fn open(uri: &str) -> Result<Box<dyn DroneInterface>, DroneError> {
+    let uri = DriverUri::parse(uri)?;
+    // Not held across the await
+    let backend = global().read().unwrap().backend(&uri.scheme)?;
+    backend(uri).await
+}
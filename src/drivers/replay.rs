@@ -0,0 +1,403 @@
+//! Plays back recorded drone states, e.g. to test a ground station or a
+//! visualisation against a real flight. Recordings are either JSON lines of
+//! [`DroneState`], or an MCAP bag of the ROS node's `cf/state` topic. MCAP
+//! chunks must be uncompressed, e.g. recorded with
+//! `ros2 bag record -s mcap --storage-preset-profile fastwrite cf/state`.
+
+use async_trait::async_trait;
+use std::{path::Path, time::Duration};
+use tokio::sync::watch;
+
+use crate::{
+    trajectory::Trajectory,
+    types::{
+        Capabilities, DroneCommand, DroneError, DroneInterface, DroneState, LogStream, LogVariable,
+        ParamInfo, ParamValue,
+    },
+};
+
+/// Replays states at their recorded pace, scaled by `rate`. Commands are
+/// refused, and the driver reports the link lost once the recording ends.
+pub struct ReplayDriver {
+    state: watch::Receiver<DroneState>,
+    capabilities: Capabilities,
+}
+
+impl ReplayDriver {
+    pub async fn open(path: impl AsRef<Path>, rate: f32) -> Result<Self, DroneError> {
+        let path = path.as_ref();
+        let bytes = tokio::fs::read(path).await.map_err(|err| {
+            DroneError::InvalidArgument(format!("reading {}: {}", path.display(), err))
+        })?;
+        let states = if path.extension().is_some_and(|ext| ext == "mcap") {
+            mcap_states(&bytes)
+        } else {
+            json_states(&bytes)
+        }
+        .map_err(|err| DroneError::InvalidArgument(format!("{}: {}", path.display(), err)))?;
+        Self::from_states(states, rate).await
+    }
+
+    /// Replays `states` from memory. Async because playback runs as a task
+    /// on the current Tokio runtime.
+    pub async fn from_states(states: Vec<DroneState>, rate: f32) -> Result<Self, DroneError> {
+        if !(rate > 0.0 && rate.is_finite()) {
+            return Err(DroneError::InvalidArgument(format!(
+                "replay rate {} must be positive",
+                rate
+            )));
+        }
+        let Some(&first) = states.first() else {
+            return Err(DroneError::InvalidArgument(
+                "the recording has no states".to_string(),
+            ));
+        };
+
+        // The recorded state rate is what subscribers will see
+        let duration = states
+            .last()
+            .map_or(0.0, |last| last.timestamp - first.timestamp);
+        let state_rate = if duration > 0.0 {
+            ((states.len() - 1) as f64 / duration) as f32 * rate
+        } else {
+            0.0
+        };
+
+        let (state_tx, state) = watch::channel(first);
+        tokio::spawn(async move {
+            for pair in states.windows(2) {
+                let wait = (pair[1].timestamp - pair[0].timestamp) / rate as f64;
+                if wait > 0.0 {
+                    tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+                }
+                state_tx.send_replace(pair[1]);
+            }
+        });
+        Ok(Self {
+            state,
+            capabilities: Capabilities {
+                state_rate,
+                ..Default::default()
+            },
+        })
+    }
+}
+
+#[async_trait]
+impl DroneInterface for ReplayDriver {
+    async fn init(&mut self) -> Result<(), DroneError> {
+        Ok(())
+    }
+
+    async fn get_state(&self) -> Result<DroneState, DroneError> {
+        if self.state.has_changed().is_err() {
+            return Err(DroneError::Link("the replay has ended".to_string()));
+        }
+        Ok(*self.state.borrow())
+    }
+
+    fn subscribe_state(&self) -> watch::Receiver<DroneState> {
+        self.state.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities.clone()
+    }
+
+    async fn send_command(&mut self, cmd: DroneCommand) -> Result<(), DroneError> {
+        Err(DroneError::UnsupportedCommand(cmd.name()))
+    }
+
+    async fn upload_trajectory(
+        &mut self,
+        _id: u8,
+        _trajectory: &Trajectory,
+    ) -> Result<(), DroneError> {
+        Err(DroneError::UnsupportedCommand("trajectory upload"))
+    }
+
+    async fn get_param(&self, name: &str) -> Result<ParamValue, DroneError> {
+        Err(DroneError::InvalidArgument(format!(
+            "unknown parameter {}",
+            name
+        )))
+    }
+
+    async fn set_param(&mut self, name: &str, _value: ParamValue) -> Result<(), DroneError> {
+        Err(DroneError::InvalidArgument(format!(
+            "unknown parameter {}",
+            name
+        )))
+    }
+
+    async fn list_params(&self) -> Result<Vec<ParamInfo>, DroneError> {
+        Ok(Vec::new())
+    }
+
+    async fn list_log_variables(&self) -> Result<Vec<LogVariable>, DroneError> {
+        Ok(Vec::new())
+    }
+
+    async fn subscribe_log(
+        &self,
+        _variables: &[&str],
+        _period: Duration,
+    ) -> Result<LogStream, DroneError> {
+        Err(DroneError::UnsupportedCommand("log subscription"))
+    }
+}
+
+fn json_states(bytes: &[u8]) -> Result<Vec<DroneState>, String> {
+    let text = std::str::from_utf8(bytes).map_err(|err| err.to_string())?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|err| format!("line {}: {}", i + 1, err))
+        })
+        .collect()
+}
+
+const MCAP_MAGIC: &[u8] = b"\x89MCAP0\r\n";
+const MCAP_CHANNEL: u8 = 0x04;
+const MCAP_MESSAGE: u8 = 0x05;
+const MCAP_CHUNK: u8 = 0x06;
+const STATE_TOPIC: &str = "cf/state";
+
+/// Reads the records of an MCAP file in order, keeping the state messages.
+fn mcap_states(bytes: &[u8]) -> Result<Vec<DroneState>, String> {
+    let records = bytes.strip_prefix(MCAP_MAGIC).ok_or("not an MCAP file")?;
+    let mut state_channels = Vec::new();
+    let mut states = Vec::new();
+    mcap_records(records, &mut state_channels, &mut states)?;
+    if state_channels.is_empty() {
+        return Err(format!("no {} topic in the recording", STATE_TOPIC));
+    }
+    Ok(states)
+}
+
+fn mcap_records(
+    mut records: &[u8],
+    state_channels: &mut Vec<u16>,
+    states: &mut Vec<DroneState>,
+) -> Result<(), String> {
+    // The trailing magic is shorter than a record header
+    while records.len() >= 9 {
+        let mut reader = Reader(records);
+        let opcode = reader.u8()?;
+        let length = reader.u64()? as usize;
+        let mut body = Reader(reader.take(length)?);
+        records = reader.0;
+
+        match opcode {
+            MCAP_CHANNEL => {
+                let id = body.u16()?;
+                let _schema = body.u16()?;
+                let topic = body.string()?;
+                let encoding = body.string()?;
+                if topic.trim_start_matches('/') == STATE_TOPIC {
+                    if encoding != "cdr" {
+                        return Err(format!("{} is {} encoded, expected cdr", topic, encoding));
+                    }
+                    // The summary section repeats the channels
+                    if !state_channels.contains(&id) {
+                        state_channels.push(id);
+                    }
+                }
+            }
+            MCAP_MESSAGE => {
+                let channel = body.u16()?;
+                if state_channels.contains(&channel) {
+                    body.take(4 + 8 + 8)?; // Sequence, log and publish time
+                    states.push(cdr_state(body.0)?);
+                }
+            }
+            MCAP_CHUNK => {
+                body.take(8 + 8 + 8 + 4)?; // Time range, size and CRC
+                let compression = body.string()?;
+                if !compression.is_empty() {
+                    return Err(format!(
+                        "{} compressed chunks aren't supported",
+                        compression
+                    ));
+                }
+                let length = body.u64()? as usize;
+                mcap_records(body.take(length)?, state_channels, states)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// A `std_msgs/String` holding the JSON state, as the ROS node publishes it.
+fn cdr_state(data: &[u8]) -> Result<DroneState, String> {
+    let mut reader = Reader(data);
+    reader.take(4)?; // Encapsulation header, little-endian
+    let length = reader.u32()? as usize;
+    let text = reader.take(length)?;
+    let text = text.strip_suffix(b"\0").unwrap_or(text);
+    serde_json::from_slice(text).map_err(|err| err.to_string())
+}
+
+/// Little-endian reader over a byte slice.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < length {
+            return Err("truncated record".to_string());
+        }
+        let (head, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording() -> Vec<DroneState> {
+        (0..4)
+            .map(|i| DroneState {
+                timestamp: i as f64 * 0.01,
+                z: i as f32 * 0.1,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn string(value: &str) -> Vec<u8> {
+        let mut bytes = (value.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(value.as_bytes());
+        bytes
+    }
+
+    fn record(opcode: u8, body: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![opcode];
+        bytes.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    fn channel(id: u16, topic: &str) -> Vec<u8> {
+        let mut body = id.to_le_bytes().to_vec();
+        body.extend_from_slice(&0u16.to_le_bytes()); // No schema
+        body.extend(string(topic));
+        body.extend(string("cdr"));
+        body.extend_from_slice(&0u32.to_le_bytes()); // No metadata
+        record(MCAP_CHANNEL, &body)
+    }
+
+    fn message(channel: u16, state: &DroneState) -> Vec<u8> {
+        let mut body = channel.to_le_bytes().to_vec();
+        body.extend_from_slice(&[0; 4 + 8 + 8]);
+        let mut text = serde_json::to_vec(state).unwrap();
+        text.push(0);
+        body.extend_from_slice(&[0, 1, 0, 0]);
+        body.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        body.extend(text);
+        record(MCAP_MESSAGE, &body)
+    }
+
+    fn chunk(compression: &str, records: &[u8]) -> Vec<u8> {
+        let mut body = vec![0; 8 + 8];
+        body.extend_from_slice(&(records.len() as u64).to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend(string(compression));
+        body.extend_from_slice(&(records.len() as u64).to_le_bytes());
+        body.extend_from_slice(records);
+        record(MCAP_CHUNK, &body)
+    }
+
+    fn mcap(records: &[u8]) -> Vec<u8> {
+        [MCAP_MAGIC, records, MCAP_MAGIC].concat()
+    }
+
+    fn assert_recording(states: &[DroneState]) {
+        let expected = recording();
+        assert_eq!(states.len(), expected.len());
+        for (state, expected) in states.iter().zip(&expected) {
+            assert_eq!(state.timestamp, expected.timestamp);
+            assert_eq!(state.z, expected.z);
+        }
+    }
+
+    #[test]
+    fn mcap_round_trip() {
+        let states = recording();
+        let mut records = channel(1, "/cf/state");
+        records.extend(channel(2, "/cf/other"));
+        records.extend(message(1, &states[0]));
+        records.extend(message(2, &DroneState::default()));
+        let mut chunked = message(1, &states[1]);
+        chunked.extend(message(1, &states[2]));
+        records.extend(chunk("", &chunked));
+        records.extend(message(1, &states[3]));
+
+        assert_recording(&mcap_states(&mcap(&records)).unwrap());
+    }
+
+    #[test]
+    fn mcap_rejects_what_it_cant_read() {
+        assert!(mcap_states(b"not mcap").is_err());
+        assert!(mcap_states(&mcap(&channel(1, "/cf/other"))).is_err());
+
+        let mut records = channel(1, "/cf/state");
+        records.extend(chunk("zstd", &message(1, &recording()[0])));
+        assert!(mcap_states(&mcap(&records)).is_err());
+
+        let mut records = channel(1, "/cf/state");
+        let message = message(1, &recording()[0]);
+        records.extend_from_slice(&message[..message.len() - 4]);
+        assert!(mcap_states(&mcap(&records)).is_err());
+    }
+
+    #[test]
+    fn json_lines_round_trip() {
+        let mut text = String::new();
+        for state in recording() {
+            text += &serde_json::to_string(&state).unwrap();
+            text += "\n\n";
+        }
+        assert_recording(&json_states(text.as_bytes()).unwrap());
+        assert!(json_states(b"{}\nnot json").is_err());
+    }
+
+    #[tokio::test]
+    async fn replays_until_the_end() {
+        let driver = ReplayDriver::from_states(recording(), 10.0).await.unwrap();
+        let mut state = driver.subscribe_state();
+        let mut seen = vec![*state.borrow_and_update()];
+        while state.changed().await.is_ok() {
+            seen.push(*state.borrow_and_update());
+        }
+        assert_recording(&seen);
+        assert!(driver.get_state().await.is_err());
+
+        assert!(ReplayDriver::from_states(Vec::new(), 1.0).await.is_err());
+        assert!(ReplayDriver::from_states(recording(), 0.0).await.is_err());
+    }
+}
@@ -1,42 +1,129 @@
-use crate::types::{DroneInterface, DroneState, DroneCommand, RpytCommand};
+use crate::middleware::Validation;
+use crate::sim::constants::PHYSICS_DT;
+use crate::sim::log::{log_variables, LogSubscription, LOG_VARIABLES};
+use crate::sim::params::ParamRequest;
+use crate::sim::plugin::{DroneLink, SimTrajectories};
 use crate::sim::SimulationPlugin;
+use crate::trajectory::Trajectory;
+use crate::types::{
+    Capabilities, DroneCommand, DroneError, DroneInterface, DroneState, LogStream, LogVariable,
+    ParamInfo, ParamValue, RpytCommand, Sensors,
+};
 use async_trait::async_trait;
-use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex};
+use bevy::app::ScheduleRunnerPlugin;
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch};
 
 pub struct SimulationDriver {
-    state: Arc<Mutex<DroneState>>,
+    state: watch::Receiver<DroneState>,
     command_tx: mpsc::Sender<DroneCommand>,
+    param_tx: mpsc::Sender<ParamRequest>,
+    log_tx: mpsc::Sender<LogSubscription>,
+    trajectories: SimTrajectories,
+    capabilities: Capabilities,
+}
+
+/// How the sim app runs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimOptions {
+    pub headless: bool, // No window or rendering, e.g. on CI. Always set without the `render` feature
+    pub sensors: Option<Sensors>, // Fitted to every drone, the sim's defaults if None
 }
 
 impl SimulationDriver {
-    pub async fn new() -> anyhow::Result<Self> {
-        let (command_tx, command_rx) = mpsc::channel(32);
-        let state = Arc::new(Mutex::new(DroneState::default()));
-        let state_clone = state.clone();
+    pub async fn new() -> Result<Self, DroneError> {
+        Self::with_options(SimOptions::default()).await
+    }
+
+    pub async fn with_options(options: SimOptions) -> Result<Self, DroneError> {
+        let mut drivers = Self::swarm_with_options(&[Vec3::ZERO], options).await?;
+        Ok(drivers.remove(0))
+    }
+
+    /// Flies several drones in one sim world, one driver per drone, starting
+    /// at `positions` (meters, Z-up).
+    pub async fn swarm(positions: &[Vec3]) -> Result<Vec<Self>, DroneError> {
+        Self::swarm_with_options(positions, SimOptions::default()).await
+    }
+
+    pub async fn swarm_with_options(
+        positions: &[Vec3],
+        options: SimOptions,
+    ) -> Result<Vec<Self>, DroneError> {
+        let mut plugin = SimulationPlugin::swarm();
+        if let Some(sensors) = options.sensors {
+            plugin = plugin.with_sensors(sensors);
+        }
+        let capabilities = plugin.capabilities();
+        let mut drivers = Vec::with_capacity(positions.len());
+        for &position in positions {
+            let (command_tx, command_rx) = mpsc::channel(32);
+            let (param_tx, param_rx) = mpsc::channel(8);
+            let (log_tx, log_rx) = mpsc::channel(8);
+            let (state_tx, state) = watch::channel(DroneState {
+                x: position.x,
+                y: position.y,
+                z: position.z,
+                ..Default::default()
+            });
+            let trajectories = SimTrajectories::default();
+            let link = DroneLink::new(command_rx, Arc::new(state_tx))
+                .with_trajectories(trajectories.clone())
+                .with_params(param_rx)
+                .with_logs(log_rx);
+            plugin = plugin.with_drone(link, position);
+            drivers.push(Self {
+                state,
+                command_tx,
+                param_tx,
+                log_tx,
+                trajectories,
+                capabilities: capabilities.clone(),
+            });
+        }
 
         // Spawn Bevy app in separate thread
         std::thread::spawn(move || {
-            App::new()
-                .add_plugins(DefaultPlugins)
-                .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
-                .add_plugins(RapierDebugRenderPlugin::default()) // Optional: for visualization
-                .add_plugins(SimulationPlugin::new(command_rx, state_clone))
+            let mut app = App::new();
+            if options.headless || cfg!(not(feature = "render")) {
+                // Nothing to draw, so step at the physics rate without a window
+                app.add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(
+                    Duration::from_secs_f32(PHYSICS_DT),
+                )))
+                .add_plugins((TransformPlugin, HierarchyPlugin));
+            } else {
+                #[cfg(feature = "render")]
+                app.add_plugins(DefaultPlugins)
+                    .add_plugins(RapierDebugRenderPlugin::default()); // Optional: for visualization
+            }
+            app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default().in_fixed_schedule())
+                .add_plugins(plugin)
                 .run();
         });
 
-        Ok(Self {
-            state,
-            command_tx,
-        })
+        Ok(drivers)
+    }
+
+    /// Sends a parameter request to the sim and waits for its answer.
+    async fn param_request<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<T>) -> ParamRequest,
+    ) -> Result<T, DroneError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.param_tx
+            .send(request(reply_tx))
+            .await
+            .map_err(|_| DroneError::SimShutdown)?;
+        reply_rx.await.map_err(|_| DroneError::SimShutdown)
     }
 }
 
 #[async_trait]
 impl DroneInterface for SimulationDriver {
-    async fn init(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    async fn init(&mut self) -> Result<(), DroneError> {
         self.command_tx
             .send(DroneCommand::Rpyt(RpytCommand {
                 roll: 0.0,
@@ -44,16 +131,102 @@ impl DroneInterface for SimulationDriver {
                 yaw: 0.0,
                 thrust: 0,
             }))
-            .await?;
+            .await
+            .map_err(|_| DroneError::SimShutdown)?;
         Ok(())
     }
 
-    async fn get_state(&self) -> Result<DroneState, Box<dyn std::error::Error>> {
-        Ok(self.state.lock().await.clone())
+    async fn get_state(&self) -> Result<DroneState, DroneError> {
+        // The sender goes away with the Bevy app
+        if self.state.has_changed().is_err() {
+            return Err(DroneError::SimShutdown);
+        }
+        Ok(*self.state.borrow())
+    }
+
+    fn subscribe_state(&self) -> watch::Receiver<DroneState> {
+        self.state.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities.clone()
     }
 
-    async fn send_command(&mut self, cmd: DroneCommand) -> Result<(), Box<dyn std::error::Error>> {
-        self.command_tx.send(cmd).await?;
+    async fn send_command(&mut self, cmd: DroneCommand) -> Result<(), DroneError> {
+        // The sim would fly a NaN into the physics, refuse it like a bad argument
+        Validation::unlimited().check(&cmd)?;
+        self.command_tx
+            .send(cmd)
+            .await
+            .map_err(|_| DroneError::SimShutdown)?;
+        Ok(())
+    }
+
+    async fn upload_trajectory(
+        &mut self,
+        id: u8,
+        trajectory: &Trajectory,
+    ) -> Result<(), DroneError> {
+        // A bad duration would panic the sim thread on playback
+        trajectory
+            .validate()
+            .map_err(|err| DroneError::InvalidArgument(format!("trajectory {}: {}", id, err)))?;
+        self.trajectories
+            .0
+            .lock()
+            .await
+            .insert(id, trajectory.clone());
         Ok(())
     }
+
+    async fn get_param(&self, name: &str) -> Result<ParamValue, DroneError> {
+        self.param_request(|reply| ParamRequest::Get(name.to_string(), reply))
+            .await?
+    }
+
+    async fn set_param(&mut self, name: &str, value: ParamValue) -> Result<(), DroneError> {
+        self.param_request(|reply| ParamRequest::Set(name.to_string(), value, reply))
+            .await?
+    }
+
+    async fn list_params(&self) -> Result<Vec<ParamInfo>, DroneError> {
+        self.param_request(ParamRequest::List).await
+    }
+
+    async fn list_log_variables(&self) -> Result<Vec<LogVariable>, DroneError> {
+        Ok(log_variables())
+    }
+
+    async fn subscribe_log(
+        &self,
+        variables: &[&str],
+        period: Duration,
+    ) -> Result<LogStream, DroneError> {
+        if variables.is_empty() || period.is_zero() {
+            return Err(DroneError::InvalidArgument(
+                "a log subscription needs variables and a period".to_string(),
+            ));
+        }
+        if let Some(name) = variables
+            .iter()
+            .find(|name| !LOG_VARIABLES.iter().any(|(variable, _)| variable == *name))
+        {
+            return Err(DroneError::InvalidArgument(format!(
+                "unknown log variable {}",
+                name
+            )));
+        }
+
+        let variables: Vec<String> = variables.iter().map(|name| name.to_string()).collect();
+        let (samples, sample_rx) = mpsc::channel(32);
+        self.log_tx
+            .send(LogSubscription {
+                variables: variables.clone(),
+                period: period.as_secs_f64(),
+                samples,
+            })
+            .await
+            .map_err(|_| DroneError::SimShutdown)?;
+        Ok(LogStream::new(variables, sample_rx))
+    }
 }
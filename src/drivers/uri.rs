@@ -0,0 +1,85 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use crate::types::DroneError;
+
+/// `scheme://location?key=value&...`, naming a backend and what to open with
+/// it, e.g. `sim://default?headless=1` or `radio://0/80/2M/E7E7E7E7E7`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverUri {
+    pub scheme: String,
+    pub location: String,
+    pub query: HashMap<String, String>,
+}
+
+impl DriverUri {
+    pub fn parse(uri: &str) -> Result<Self, DroneError> {
+        let (scheme, rest) = uri
+            .split_once("://")
+            .ok_or_else(|| DroneError::InvalidArgument(format!("{} has no scheme", uri)))?;
+        if scheme.is_empty() {
+            return Err(DroneError::InvalidArgument(format!(
+                "{} has no scheme",
+                uri
+            )));
+        }
+        let (location, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let query = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (key.to_string(), value.to_string())
+            })
+            .collect();
+
+        Ok(Self {
+            scheme: scheme.to_ascii_lowercase(),
+            location: location.to_string(),
+            query,
+        })
+    }
+
+    /// Parses a query value, `None` if the key isn't there.
+    pub fn get<T: FromStr>(&self, key: &str) -> Result<Option<T>, DroneError> {
+        self.query
+            .get(key)
+            .map(|value| {
+                value.parse().map_err(|_| {
+                    DroneError::InvalidArgument(format!("{}: invalid {} {:?}", self, key, value))
+                })
+            })
+            .transpose()
+    }
+
+    /// A flag like `headless=1`. A bare key, `1`, `true` and `yes` are set.
+    pub fn flag(&self, key: &str) -> bool {
+        self.query
+            .get(key)
+            .is_some_and(|value| matches!(value.as_str(), "" | "1" | "true" | "yes"))
+    }
+}
+
+impl FromStr for DriverUri {
+    type Err = DroneError;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        Self::parse(uri)
+    }
+}
+
+impl fmt::Display for DriverUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}", self.scheme, self.location)?;
+        let mut query: Vec<_> = self.query.iter().collect();
+        query.sort();
+        for (i, (key, value)) in query.into_iter().enumerate() {
+            let separator = if i == 0 { '?' } else { '&' };
+            if value.is_empty() {
+                write!(f, "{}{}", separator, key)?;
+            } else {
+                write!(f, "{}{}={}", separator, key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
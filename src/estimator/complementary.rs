@@ -0,0 +1,87 @@
+use glam::{Quat, Vec3};
+
+use super::{Estimator, ImuSample, Measurement, GRAVITY};
+use crate::control::StateEstimate;
+
+/// Mahony-style attitude filter, as in the firmware's `sensfusion6`, with a
+/// fixed-gain position/velocity filter on top. The accelerometer pulls the
+/// gyro-integrated attitude towards gravity; position measurements correct the
+/// dead-reckoned position and velocity.
+pub struct ComplementaryEstimator {
+    pub kp: f32,            // Attitude correction gain
+    pub ki: f32,            // Gyro bias correction gain
+    pub position_gain: f32, // Fraction of a position error applied per update
+    pub velocity_gain: f32, // Velocity correction per meter of position error
+    attitude: Quat,
+    gyro_bias: Vec3,
+    angular_velocity: Vec3,
+    position: Vec3,
+    velocity: Vec3,
+}
+
+impl Default for ComplementaryEstimator {
+    fn default() -> Self {
+        Self {
+            kp: 0.8,
+            ki: 0.002,
+            position_gain: 0.1,
+            velocity_gain: 0.5,
+            attitude: Quat::IDENTITY,
+            gyro_bias: Vec3::ZERO,
+            angular_velocity: Vec3::ZERO,
+            position: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+        }
+    }
+}
+
+impl Estimator for ComplementaryEstimator {
+    fn predict(&mut self, imu: &ImuSample, dt: f32) {
+        let mut correction = Vec3::ZERO;
+        // Only trust the accelerometer as a gravity reference near 1 g
+        let accel_norm = imu.accel.length();
+        if accel_norm > 0.5 * GRAVITY && accel_norm < 1.5 * GRAVITY {
+            let measured_up = imu.accel / accel_norm;
+            let estimated_up = self.attitude.inverse() * Vec3::Z;
+            let error = measured_up.cross(estimated_up);
+            self.gyro_bias -= self.ki * error * dt;
+            correction = self.kp * error;
+        }
+
+        self.angular_velocity = imu.gyro - self.gyro_bias;
+        let rate = self.angular_velocity + correction;
+        self.attitude = (self.attitude * Quat::from_scaled_axis(rate * dt)).normalize();
+
+        let accel = self.attitude * imu.accel - Vec3::Z * GRAVITY;
+        self.position += self.velocity * dt + 0.5 * accel * dt * dt;
+        self.velocity += accel * dt;
+    }
+
+    fn update(&mut self, measurement: &Measurement) {
+        match *measurement {
+            Measurement::Position { position, .. } | Measurement::Pose { position, .. } => {
+                let error = position - self.position;
+                self.position += self.position_gain * error;
+                self.velocity += self.velocity_gain * error;
+            }
+            _ => {}
+        }
+    }
+
+    fn state(&self) -> StateEstimate {
+        StateEstimate {
+            position: self.position,
+            velocity: self.velocity,
+            attitude: self.attitude,
+            angular_velocity: self.angular_velocity,
+        }
+    }
+
+    fn reset(&mut self, state: &StateEstimate) {
+        self.attitude = state.attitude;
+        self.position = state.position;
+        self.velocity = state.velocity;
+        self.angular_velocity = state.angular_velocity;
+        self.gyro_bias = Vec3::ZERO;
+    }
+}
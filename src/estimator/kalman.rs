@@ -0,0 +1,688 @@
+//! Port of the Crazyflie error-state EKF (`kalman_core` and the `mm_*`
+//! measurement models). The state is world position, body-frame velocity and
+//! a small attitude error that is folded into the reference quaternion after
+//! every step.
+
+use glam::{Mat3, Quat, Vec2, Vec3};
+
+use super::{Estimator, ImuSample, Measurement, SweepAngle, GRAVITY};
+use crate::control::StateEstimate;
+
+pub const STATE_DIM: usize = 9;
+pub type Covariance = [[f32; STATE_DIM]; STATE_DIM];
+
+// State indices
+pub const X: usize = 0;
+pub const Y: usize = 1;
+pub const Z: usize = 2;
+pub const PX: usize = 3;
+pub const PY: usize = 4;
+pub const PZ: usize = 5;
+pub const D0: usize = 6;
+pub const D1: usize = 7;
+pub const D2: usize = 8;
+
+const MIN_COVARIANCE: f32 = 1e-6;
+const MAX_COVARIANCE: f32 = 100.0;
+
+// Flow deck (PMW3901) optics
+const FLOW_FOV: f32 = 4.2 * std::f32::consts::PI / 180.0;
+const FLOW_PIXELS: f32 = 35.0;
+// Half the ToF sensor's field of view
+const TOF_HALF_FOV: f32 = 7.5 * std::f32::consts::PI / 180.0;
+
+/// Noise parameters, named and defaulted as in the firmware.
+#[derive(Debug, Clone, Copy)]
+pub struct KalmanParams {
+    pub proc_noise_acc_xy: f32,
+    pub proc_noise_acc_z: f32,
+    pub proc_noise_vel: f32,
+    pub proc_noise_pos: f32,
+    pub proc_noise_att: f32,
+    pub meas_noise_gyro_roll_pitch: f32, // rad/s
+    pub meas_noise_gyro_yaw: f32,        // rad/s
+    pub std_dev_initial_position_xy: f32,
+    pub std_dev_initial_position_z: f32,
+    pub std_dev_initial_velocity: f32,
+    pub std_dev_initial_attitude_roll_pitch: f32,
+    pub std_dev_initial_attitude_yaw: f32,
+}
+
+impl Default for KalmanParams {
+    fn default() -> Self {
+        Self {
+            proc_noise_acc_xy: 0.5,
+            proc_noise_acc_z: 1.0,
+            proc_noise_vel: 0.0,
+            proc_noise_pos: 0.0,
+            proc_noise_att: 0.0,
+            meas_noise_gyro_roll_pitch: 0.1,
+            meas_noise_gyro_yaw: 0.1,
+            std_dev_initial_position_xy: 100.0,
+            std_dev_initial_position_z: 1.0,
+            std_dev_initial_velocity: 0.01,
+            std_dev_initial_attitude_roll_pitch: 0.01,
+            std_dev_initial_attitude_yaw: 0.01,
+        }
+    }
+}
+
+pub struct KalmanEstimator {
+    pub params: KalmanParams,
+    s: [f32; STATE_DIM],
+    q: Quat,
+    r: Mat3, // Body to world, from `q`
+    p: Covariance,
+    gyro: Vec3,
+}
+
+impl Default for KalmanEstimator {
+    fn default() -> Self {
+        Self::new(KalmanParams::default())
+    }
+}
+
+impl KalmanEstimator {
+    pub fn new(params: KalmanParams) -> Self {
+        let mut estimator = Self {
+            params,
+            s: [0.0; STATE_DIM],
+            q: Quat::IDENTITY,
+            r: Mat3::IDENTITY,
+            p: [[0.0; STATE_DIM]; STATE_DIM],
+            gyro: Vec3::ZERO,
+        };
+        estimator.reset(&StateEstimate::default());
+        estimator
+    }
+
+    /// Generic scalar measurement update with Jacobian `h`, innovation `error`
+    /// and measurement standard deviation `std_dev` (Joseph form).
+    pub fn scalar_update(&mut self, h: &[f32; STATE_DIM], error: f32, std_dev: f32) {
+        let r = std_dev * std_dev;
+        let pht = self
+            .p
+            .map(|row| row.iter().zip(h).map(|(p, h)| p * h).sum::<f32>());
+        let hphr = r + h.iter().zip(&pht).map(|(h, p)| h * p).sum::<f32>();
+        let gain = pht.map(|v| v / hphr);
+
+        for (s, k) in self.s.iter_mut().zip(&gain) {
+            *s += k * error;
+        }
+
+        // P = (I - KH) P (I - KH)' + K R K'
+        let mut a = [[0.0; STATE_DIM]; STATE_DIM];
+        for i in 0..STATE_DIM {
+            for j in 0..STATE_DIM {
+                a[i][j] = if i == j { 1.0 } else { 0.0 } - gain[i] * h[j];
+            }
+        }
+        self.p = transform_covariance(&a, &self.p);
+        for i in 0..STATE_DIM {
+            for j in 0..STATE_DIM {
+                self.p[i][j] += gain[i] * r * gain[j];
+            }
+        }
+        self.bound_covariance();
+    }
+
+    /// External position, e.g. motion capture (`mm_position`).
+    pub fn update_with_position(&mut self, position: Vec3, std_dev: f32) {
+        for axis in 0..3 {
+            let mut h = [0.0; STATE_DIM];
+            h[X + axis] = 1.0;
+            self.scalar_update(&h, position[axis] - self.s[X + axis], std_dev);
+        }
+    }
+
+    /// External position and orientation (`mm_pose`).
+    pub fn update_with_pose(
+        &mut self,
+        position: Vec3,
+        attitude: Quat,
+        std_dev_position: f32,
+        std_dev_attitude: f32,
+    ) {
+        self.update_with_position(position, std_dev_position);
+
+        // Small-angle attitude error between the estimate and the measurement
+        let residual = self.q.inverse() * attitude;
+        let error = Vec3::new(residual.x, residual.y, residual.z) * (2.0 / residual.w);
+        for axis in 0..3 {
+            let mut h = [0.0; STATE_DIM];
+            h[D0 + axis] = 1.0;
+            self.scalar_update(&h, error[axis], std_dev_attitude);
+        }
+    }
+
+    /// Downward range to the floor (`mm_tof`), in meters.
+    pub fn update_with_tof(&mut self, distance: f32, std_dev: f32) {
+        // Only trust it while the sensor points reasonably down
+        let r22 = self.r.z_axis.z;
+        if r22.abs() > 0.1 && r22 > 0.0 {
+            let angle = (r22.acos().abs() - TOF_HALF_FOV).max(0.0);
+            let predicted = self.s[Z] / angle.cos();
+            let mut h = [0.0; STATE_DIM];
+            h[Z] = 1.0 / angle.cos();
+            self.scalar_update(&h, distance - predicted, std_dev);
+        }
+    }
+
+    /// Optical flow (`mm_flow`): pixels accumulated over `dt` seconds.
+    pub fn update_with_flow(&mut self, dpixel: Vec2, dt: f32, std_dev: Vec2) {
+        let scale = FLOW_PIXELS * dt / FLOW_FOV;
+        let r22 = self.r.z_axis.z;
+        // Saturate height to avoid the singularity on the ground
+        let z = self.s[Z].max(0.1);
+
+        let predicted_x = scale * (self.s[PX] * r22 / z - self.gyro.y);
+        let mut hx = [0.0; STATE_DIM];
+        hx[Z] = scale * (r22 * self.s[PX]) / (-z * z);
+        hx[PX] = scale * r22 / z;
+        self.scalar_update(&hx, dpixel.x - predicted_x, std_dev.x);
+
+        let predicted_y = scale * (self.s[PY] * r22 / z + self.gyro.x);
+        let mut hy = [0.0; STATE_DIM];
+        hy[Z] = scale * (r22 * self.s[PY]) / (-z * z);
+        hy[PY] = scale * r22 / z;
+        self.scalar_update(&hy, dpixel.y - predicted_y, std_dev.y);
+    }
+
+    /// Range to a known anchor, e.g. UWB two-way ranging (`mm_distance`).
+    pub fn update_with_distance(&mut self, anchor: Vec3, distance: f32, std_dev: f32) {
+        let delta = self.position() - anchor;
+        let predicted = delta.length();
+        let mut h = [0.0; STATE_DIM];
+        if predicted != 0.0 {
+            h[X] = delta.x / predicted;
+            h[Y] = delta.y / predicted;
+            h[Z] = delta.z / predicted;
+        } else {
+            h[X] = 1.0;
+        }
+        self.scalar_update(&h, distance - predicted, std_dev);
+    }
+
+    /// Difference of ranges to two anchors, `|p - anchors[1]| - |p - anchors[0]|`,
+    /// as measured by UWB TDoA (`mm_tdoa`).
+    pub fn update_with_tdoa(&mut self, anchors: [Vec3; 2], distance_diff: f32, std_dev: f32) {
+        let delta0 = self.position() - anchors[0];
+        let delta1 = self.position() - anchors[1];
+        let (d0, d1) = (delta0.length(), delta1.length());
+        if d0 == 0.0 || d1 == 0.0 {
+            return;
+        }
+
+        let gradient = delta1 / d1 - delta0 / d0;
+        let mut h = [0.0; STATE_DIM];
+        h[X] = gradient.x;
+        h[Y] = gradient.y;
+        h[Z] = gradient.z;
+        self.scalar_update(&h, distance_diff - (d1 - d0), std_dev);
+    }
+
+    /// Lighthouse sweep angle (`mm_sweep_angles`), using the uncalibrated
+    /// model `atan2(y, x) + asin(z * tan(t) / r)` in the rotor frame.
+    pub fn update_with_sweep_angle(&mut self, sweep: &SweepAngle) {
+        let sensor = self.position() + self.r * sweep.sensor;
+        let local = sweep.rotor.transpose() * (sensor - sweep.rotor_position);
+        let (x, y, z) = (local.x, local.y, local.z);
+        let tan_t = sweep.tilt.tan();
+        let r2 = x * x + y * y;
+        let r = r2.sqrt();
+
+        let predicted = y.atan2(x) + (z * tan_t / r).asin();
+        let error = sweep.angle - predicted;
+
+        let z_tan_t = z * tan_t;
+        let q_num = r2 - z_tan_t * z_tan_t;
+        // Avoid the singularity
+        if q_num > 0.0001 && error.is_finite() {
+            let q = tan_t / q_num.sqrt();
+            let gradient = sweep.rotor * Vec3::new((-y - x * z * q) / r2, (x - y * z * q) / r2, q);
+            let mut h = [0.0; STATE_DIM];
+            h[X] = gradient.x;
+            h[Y] = gradient.y;
+            h[Z] = gradient.z;
+            self.scalar_update(&h, error, sweep.std_dev);
+        }
+    }
+
+    fn position(&self) -> Vec3 {
+        Vec3::new(self.s[X], self.s[Y], self.s[Z])
+    }
+
+    fn add_process_noise(&mut self, dt: f32) {
+        let p = &self.params;
+        let position_xy = p.proc_noise_acc_xy * dt * dt + p.proc_noise_vel * dt + p.proc_noise_pos;
+        let position_z = p.proc_noise_acc_z * dt * dt + p.proc_noise_vel * dt + p.proc_noise_pos;
+        let noise = [
+            position_xy,
+            position_xy,
+            position_z,
+            p.proc_noise_acc_xy * dt + p.proc_noise_vel,
+            p.proc_noise_acc_xy * dt + p.proc_noise_vel,
+            p.proc_noise_acc_z * dt + p.proc_noise_vel,
+            p.meas_noise_gyro_roll_pitch * dt + p.proc_noise_att,
+            p.meas_noise_gyro_roll_pitch * dt + p.proc_noise_att,
+            p.meas_noise_gyro_yaw * dt + p.proc_noise_att,
+        ];
+        for (i, n) in noise.iter().enumerate() {
+            self.p[i][i] += n * n;
+        }
+        self.bound_covariance();
+    }
+
+    /// Moves the attitude error into the reference quaternion and rotates the
+    /// covariance to match (`kalmanCoreFinalize`).
+    fn finalize(&mut self) {
+        let v = Vec3::new(self.s[D0], self.s[D1], self.s[D2]);
+        if v.abs().max_element() > 1e-4 && v.abs().max_element() < 10.0 {
+            self.q = (self.q * Quat::from_scaled_axis(v)).normalize();
+            let a = attitude_error_transition(v / 2.0);
+            self.p = transform_covariance(&a, &self.p);
+        }
+
+        self.r = Mat3::from_quat(self.q);
+        self.s[D0] = 0.0;
+        self.s[D1] = 0.0;
+        self.s[D2] = 0.0;
+        self.bound_covariance();
+    }
+
+    fn bound_covariance(&mut self) {
+        for i in 0..STATE_DIM {
+            for j in i..STATE_DIM {
+                let value = 0.5 * (self.p[i][j] + self.p[j][i]);
+                let value = if value.is_nan() || value > MAX_COVARIANCE {
+                    MAX_COVARIANCE
+                } else if i == j && value < MIN_COVARIANCE {
+                    MIN_COVARIANCE
+                } else {
+                    value
+                };
+                self.p[i][j] = value;
+                self.p[j][i] = value;
+            }
+        }
+    }
+}
+
+impl Estimator for KalmanEstimator {
+    fn predict(&mut self, imu: &ImuSample, dt: f32) {
+        let (acc, gyro) = (imu.accel, imu.gyro);
+        let r = |i: usize, j: usize| self.r.col(j)[i];
+        let s = self.s;
+        let mut a = [[0.0; STATE_DIM]; STATE_DIM];
+        for (i, row) in a.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+
+        for i in 0..3 {
+            // Position from body-frame velocity
+            a[X + i][PX] = r(i, 0) * dt;
+            a[X + i][PY] = r(i, 1) * dt;
+            a[X + i][PZ] = r(i, 2) * dt;
+            // Position from attitude error
+            a[X + i][D0] = (s[PY] * r(i, 2) - s[PZ] * r(i, 1)) * dt;
+            a[X + i][D1] = (-s[PX] * r(i, 2) + s[PZ] * r(i, 0)) * dt;
+            a[X + i][D2] = (s[PX] * r(i, 1) - s[PY] * r(i, 0)) * dt;
+        }
+
+        // Body-frame velocity from body-frame velocity
+        a[PY][PX] = -gyro.z * dt;
+        a[PZ][PX] = gyro.y * dt;
+        a[PX][PY] = gyro.z * dt;
+        a[PZ][PY] = -gyro.x * dt;
+        a[PX][PZ] = -gyro.y * dt;
+        a[PY][PZ] = gyro.x * dt;
+
+        // Body-frame velocity from attitude error
+        a[PY][D0] = -GRAVITY * r(2, 2) * dt;
+        a[PZ][D0] = GRAVITY * r(2, 1) * dt;
+        a[PX][D1] = GRAVITY * r(2, 2) * dt;
+        a[PZ][D1] = -GRAVITY * r(2, 0) * dt;
+        a[PX][D2] = -GRAVITY * r(2, 1) * dt;
+        a[PY][D2] = GRAVITY * r(2, 0) * dt;
+
+        // Attitude error from attitude error
+        let att = attitude_error_transition(gyro * dt / 2.0);
+        for i in 0..3 {
+            for j in 0..3 {
+                a[D0 + i][D0 + j] = att[D0 + i][D0 + j];
+            }
+        }
+
+        self.p = transform_covariance(&a, &self.p);
+
+        // The firmware drops the horizontal accelerometer terms in flight;
+        // here the full specific force is always used.
+        let dt2 = dt * dt;
+        let delta = Vec3::new(s[PX], s[PY], s[PZ]) * dt + acc * dt2 / 2.0;
+        let world = self.r * delta;
+        self.s[X] += world.x;
+        self.s[Y] += world.y;
+        self.s[Z] += world.z - GRAVITY * dt2 / 2.0;
+
+        self.s[PX] += dt * (acc.x + gyro.z * s[PY] - gyro.y * s[PZ] - GRAVITY * r(2, 0));
+        self.s[PY] += dt * (acc.y - gyro.z * s[PX] + gyro.x * s[PZ] - GRAVITY * r(2, 1));
+        self.s[PZ] += dt * (acc.z + gyro.y * s[PX] - gyro.x * s[PY] - GRAVITY * r(2, 2));
+
+        self.q = (self.q * Quat::from_scaled_axis(gyro * dt)).normalize();
+        self.gyro = gyro;
+
+        self.add_process_noise(dt);
+        self.finalize();
+    }
+
+    fn update(&mut self, measurement: &Measurement) {
+        match *measurement {
+            Measurement::Position { position, std_dev } => {
+                self.update_with_position(position, std_dev)
+            }
+            Measurement::Pose {
+                position,
+                attitude,
+                std_dev_position,
+                std_dev_attitude,
+            } => self.update_with_pose(position, attitude, std_dev_position, std_dev_attitude),
+            Measurement::Tof { distance, std_dev } => self.update_with_tof(distance, std_dev),
+            Measurement::Flow {
+                dpixel,
+                dt,
+                std_dev,
+            } => self.update_with_flow(dpixel, dt, std_dev),
+            Measurement::Distance {
+                anchor,
+                distance,
+                std_dev,
+            } => self.update_with_distance(anchor, distance, std_dev),
+            Measurement::Tdoa {
+                anchors,
+                distance_diff,
+                std_dev,
+            } => self.update_with_tdoa(anchors, distance_diff, std_dev),
+            Measurement::SweepAngle(sweep) => self.update_with_sweep_angle(&sweep),
+        }
+        self.finalize();
+    }
+
+    fn state(&self) -> StateEstimate {
+        StateEstimate {
+            position: self.position(),
+            velocity: self.r * Vec3::new(self.s[PX], self.s[PY], self.s[PZ]),
+            attitude: self.q,
+            angular_velocity: self.gyro,
+        }
+    }
+
+    fn reset(&mut self, state: &StateEstimate) {
+        let p = self.params;
+        self.q = state.attitude.normalize();
+        self.r = Mat3::from_quat(self.q);
+        let velocity = self.r.transpose() * state.velocity;
+        self.s = [
+            state.position.x,
+            state.position.y,
+            state.position.z,
+            velocity.x,
+            velocity.y,
+            velocity.z,
+            0.0,
+            0.0,
+            0.0,
+        ];
+        self.gyro = state.angular_velocity;
+
+        let std_devs = [
+            p.std_dev_initial_position_xy,
+            p.std_dev_initial_position_xy,
+            p.std_dev_initial_position_z,
+            p.std_dev_initial_velocity,
+            p.std_dev_initial_velocity,
+            p.std_dev_initial_velocity,
+            p.std_dev_initial_attitude_roll_pitch,
+            p.std_dev_initial_attitude_roll_pitch,
+            p.std_dev_initial_attitude_yaw,
+        ];
+        self.p = [[0.0; STATE_DIM]; STATE_DIM];
+        for (i, std_dev) in std_devs.iter().enumerate() {
+            self.p[i][i] = std_dev * std_dev;
+        }
+    }
+
+    fn covariance(&self) -> Option<Covariance> {
+        Some(self.p)
+    }
+}
+
+/// Linearised effect of rotating the body frame by `2 * d` on the attitude
+/// error, shared by the prediction and the finalize step.
+fn attitude_error_transition(d: Vec3) -> Covariance {
+    let (d0, d1, d2) = (d.x, d.y, d.z);
+    let mut a = [[0.0; STATE_DIM]; STATE_DIM];
+    for (i, row) in a.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    a[D0][D0] = 1.0 - d1 * d1 / 2.0 - d2 * d2 / 2.0;
+    a[D0][D1] = d2 + d0 * d1 / 2.0;
+    a[D0][D2] = -d1 + d0 * d2 / 2.0;
+    a[D1][D0] = -d2 + d0 * d1 / 2.0;
+    a[D1][D1] = 1.0 - d0 * d0 / 2.0 - d2 * d2 / 2.0;
+    a[D1][D2] = d0 + d1 * d2 / 2.0;
+    a[D2][D0] = d1 + d0 * d2 / 2.0;
+    a[D2][D1] = -d0 + d1 * d2 / 2.0;
+    a[D2][D2] = 1.0 - d0 * d0 / 2.0 - d1 * d1 / 2.0;
+    a
+}
+
+/// A P A'
+fn transform_covariance(a: &Covariance, p: &Covariance) -> Covariance {
+    let mut ap = [[0.0; STATE_DIM]; STATE_DIM];
+    for i in 0..STATE_DIM {
+        for j in 0..STATE_DIM {
+            ap[i][j] = (0..STATE_DIM).map(|k| a[i][k] * p[k][j]).sum();
+        }
+    }
+    let mut out = [[0.0; STATE_DIM]; STATE_DIM];
+    for i in 0..STATE_DIM {
+        for j in 0..STATE_DIM {
+            out[i][j] = (0..STATE_DIM).map(|k| ap[i][k] * a[j][k]).sum();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_6};
+
+    const AT_REST: ImuSample = ImuSample {
+        gyro: Vec3::ZERO,
+        accel: Vec3::new(0.0, 0.0, GRAVITY),
+    };
+
+    fn estimator_at(position: Vec3) -> KalmanEstimator {
+        let mut estimator = KalmanEstimator::new(KalmanParams {
+            std_dev_initial_position_xy: 1.0,
+            std_dev_initial_velocity: 1.0,
+            ..Default::default()
+        });
+        estimator.reset(&StateEstimate {
+            position,
+            ..Default::default()
+        });
+        estimator
+    }
+
+    fn variance(estimator: &KalmanEstimator, index: usize) -> f32 {
+        estimator.p[index][index]
+    }
+
+    fn position_variance(estimator: &KalmanEstimator) -> f32 {
+        (X..=Z).map(|i| variance(estimator, i)).sum()
+    }
+
+    /// Runs `update` until it settles, checking each step shrinks `index`'s variance.
+    fn converge(
+        estimator: &mut KalmanEstimator,
+        index: usize,
+        mut update: impl FnMut(&mut KalmanEstimator),
+    ) {
+        for _ in 0..50 {
+            let before = variance(estimator, index);
+            update(estimator);
+            estimator.finalize();
+            assert!(variance(estimator, index) <= before);
+        }
+    }
+
+    #[test]
+    fn predict_at_rest() {
+        let mut estimator = estimator_at(Vec3::new(1.0, 2.0, 0.5));
+        let before = position_variance(&estimator);
+        for _ in 0..100 {
+            estimator.predict(&AT_REST, 0.01);
+        }
+        let state = estimator.state();
+        assert!(state.position.distance(Vec3::new(1.0, 2.0, 0.5)) < 1e-3);
+        assert!(state.velocity.length() < 1e-3);
+        assert!(position_variance(&estimator) > before);
+    }
+
+    #[test]
+    fn predict_integrates_acceleration() {
+        let mut estimator = estimator_at(Vec3::ZERO);
+        let imu = ImuSample {
+            accel: Vec3::new(1.0, 0.0, GRAVITY),
+            ..AT_REST
+        };
+        for _ in 0..100 {
+            estimator.predict(&imu, 0.01);
+        }
+        let state = estimator.state();
+        assert!((state.velocity.x - 1.0).abs() < 1e-3);
+        assert!((state.position.x - 0.5).abs() < 1e-2);
+        assert!(state.position.z.abs() < 1e-3);
+    }
+
+    #[test]
+    fn position_update() {
+        let target = Vec3::new(1.0, -1.0, 0.5);
+        let mut estimator = estimator_at(Vec3::ZERO);
+        converge(&mut estimator, X, |e| e.update_with_position(target, 0.01));
+        assert!(estimator.state().position.distance(target) < 1e-2);
+    }
+
+    #[test]
+    fn pose_update() {
+        let attitude = Quat::from_rotation_z(0.2) * Quat::from_rotation_x(0.1);
+        let mut estimator = estimator_at(Vec3::ZERO);
+        estimator.p[D2][D2] = 0.1;
+        estimator.p[D0][D0] = 0.1;
+        converge(&mut estimator, D2, |e| {
+            e.update_with_pose(Vec3::ONE, attitude, 0.01, 0.01)
+        });
+        let state = estimator.state();
+        assert!(state.position.distance(Vec3::ONE) < 1e-2);
+        assert!(state.attitude.angle_between(attitude) < 1e-2);
+    }
+
+    #[test]
+    fn tof_update() {
+        let mut estimator = estimator_at(Vec3::new(0.0, 0.0, 0.2));
+        converge(&mut estimator, Z, |e| e.update_with_tof(1.0, 0.01));
+        assert!((estimator.state().position.z - 1.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn flow_update() {
+        let mut estimator = estimator_at(Vec3::new(0.0, 0.0, 1.0));
+        let dt = 0.01;
+        // Flying at 0.5 m/s forward and 0.2 m/s left, 1 m up
+        let scale = FLOW_PIXELS * dt / FLOW_FOV;
+        let dpixel = Vec2::new(0.5, 0.2) * scale;
+        converge(&mut estimator, PX, |e| {
+            e.update_with_flow(dpixel, dt, Vec2::splat(0.01));
+            e.update_with_tof(1.0, 0.01);
+        });
+        let velocity = estimator.state().velocity;
+        assert!((velocity.x - 0.5).abs() < 1e-2);
+        assert!((velocity.y - 0.2).abs() < 1e-2);
+    }
+
+    const ANCHORS: [Vec3; 4] = [
+        Vec3::new(-2.0, -2.0, 0.0),
+        Vec3::new(2.0, -2.0, 3.0),
+        Vec3::new(2.0, 2.0, 0.0),
+        Vec3::new(-2.0, 2.0, 3.0),
+    ];
+
+    #[test]
+    fn distance_update() {
+        let target = Vec3::new(0.5, -0.5, 1.0);
+        let mut estimator = estimator_at(Vec3::new(0.0, 0.0, 1.5));
+        let before = position_variance(&estimator);
+        converge(&mut estimator, X, |e| {
+            for anchor in ANCHORS {
+                e.update_with_distance(anchor, target.distance(anchor), 0.01);
+            }
+        });
+        assert!(estimator.state().position.distance(target) < 1e-2);
+        assert!(position_variance(&estimator) < before);
+    }
+
+    #[test]
+    fn tdoa_update() {
+        let target = Vec3::new(0.5, -0.5, 1.0);
+        let mut estimator = estimator_at(Vec3::new(0.0, 0.0, 1.5));
+        let before = position_variance(&estimator);
+        converge(&mut estimator, X, |e| {
+            for i in 0..ANCHORS.len() {
+                let anchors = [ANCHORS[i], ANCHORS[(i + 1) % ANCHORS.len()]];
+                let diff = target.distance(anchors[1]) - target.distance(anchors[0]);
+                e.update_with_tdoa(anchors, diff, 0.01);
+            }
+        });
+        assert!(estimator.state().position.distance(target) < 1e-2);
+        assert!(position_variance(&estimator) < before);
+    }
+
+    #[test]
+    fn sweep_angle_update() {
+        let target = Vec3::new(0.5, -0.5, 1.0);
+        // Two base stations, each sweeping two tilted planes
+        let rotors = [
+            (Vec3::new(-2.0, 0.0, 2.0), Mat3::IDENTITY),
+            (Vec3::new(0.0, -2.0, 2.0), Mat3::from_rotation_z(FRAC_PI_2)),
+        ];
+        let sweeps: Vec<SweepAngle> = rotors
+            .iter()
+            .flat_map(|&(rotor_position, rotor)| {
+                [-FRAC_PI_6, FRAC_PI_6].map(|tilt| {
+                    let local = rotor.transpose() * (target - rotor_position);
+                    let r = local.truncate().length();
+                    SweepAngle {
+                        sensor: Vec3::ZERO,
+                        rotor_position,
+                        rotor,
+                        tilt,
+                        angle: local.y.atan2(local.x) + (local.z * tilt.tan() / r).asin(),
+                        std_dev: 0.001,
+                    }
+                })
+            })
+            .collect();
+
+        let mut estimator = estimator_at(Vec3::new(0.3, -0.3, 1.2));
+        let before = position_variance(&estimator);
+        converge(&mut estimator, X, |e| {
+            for sweep in &sweeps {
+                e.update_with_sweep_angle(sweep);
+            }
+        });
+        assert!(estimator.state().position.distance(target) < 1e-2);
+        assert!(position_variance(&estimator) < before);
+    }
+}
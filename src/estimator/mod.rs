@@ -0,0 +1,84 @@
+pub mod complementary;
+pub mod kalman;
+
+use glam::{Mat3, Quat, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::control::StateEstimate;
+
+pub use complementary::ComplementaryEstimator;
+pub use kalman::KalmanEstimator;
+
+pub const GRAVITY: f32 = 9.81;
+
+/// One IMU reading in the body frame (x forward, y left, z up).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ImuSample {
+    pub gyro: Vec3,  // rad/s
+    pub accel: Vec3, // m/s², specific force: reads +g on z at rest
+}
+
+/// Measurements an estimator can fuse on top of the IMU.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Measurement {
+    /// World-frame position, e.g. from motion capture.
+    Position { position: Vec3, std_dev: f32 },
+    /// World-frame position and attitude.
+    Pose {
+        position: Vec3,
+        attitude: Quat,
+        std_dev_position: f32,
+        std_dev_attitude: f32,
+    },
+    /// Downward range from a time-of-flight sensor, meters.
+    Tof { distance: f32, std_dev: f32 },
+    /// Optical flow in pixels accumulated over `dt` seconds.
+    Flow {
+        dpixel: Vec2,
+        dt: f32,
+        std_dev: Vec2,
+    },
+    /// UWB two-way ranging distance to an anchor.
+    Distance {
+        anchor: Vec3,
+        distance: f32,
+        std_dev: f32,
+    },
+    /// UWB TDoA: distance to `anchors[1]` minus distance to `anchors[0]`.
+    Tdoa {
+        anchors: [Vec3; 2],
+        distance_diff: f32,
+        std_dev: f32,
+    },
+    /// Lighthouse sweep angle.
+    SweepAngle(SweepAngle),
+}
+
+/// One Lighthouse sweep seen by one photodiode.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SweepAngle {
+    pub sensor: Vec3,         // Photodiode position, body frame
+    pub rotor_position: Vec3, // World frame
+    pub rotor: Mat3,          // Rotor frame to world
+    pub tilt: f32,            // Light plane tilt, radians
+    pub angle: f32,           // Measured sweep angle, radians
+    pub std_dev: f32,
+}
+
+pub trait Estimator: Send + Sync {
+    /// Propagates the state with an IMU sample taken `dt` seconds after the last.
+    fn predict(&mut self, imu: &ImuSample, dt: f32);
+
+    /// Fuses a measurement. Kinds the estimator doesn't use are ignored.
+    fn update(&mut self, measurement: &Measurement);
+
+    fn state(&self) -> StateEstimate;
+
+    /// Restarts the filter from a known state.
+    fn reset(&mut self, state: &StateEstimate);
+
+    /// Error covariance, for estimators that track one.
+    fn covariance(&self) -> Option<kalman::Covariance> {
+        None
+    }
+}
@@ -0,0 +1,798 @@
+//! Geofencing for any [`DroneInterface`]. Position targets are clamped into
+//! the fence, and leaving it triggers a [`BreachAction`]. The fence is checked
+//! on every state the driver reports, and again whenever a command is sent,
+//! so the same rules apply to the sim and the real Crazyflie.
+
+use async_trait::async_trait;
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{
+    sync::{watch, Mutex},
+    task::JoinHandle,
+};
+
+use crate::{
+    trajectory::Trajectory,
+    types::{
+        Capabilities, DroneCommand, DroneError, DroneInterface, DroneState, FullStateCommand,
+        HighLevelCommand, HoverCommand, LogStream, LogVariable, ParamInfo, ParamValue,
+        PositionCommand, SupervisorState, ZDistanceCommand,
+    },
+};
+
+/// Horizontal outline of the fence, world frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FenceShape {
+    Box {
+        min: Vec2,
+        max: Vec2,
+    },
+    /// Simple polygon, vertices in order.
+    Polygon(Vec<Vec2>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BreachAction {
+    /// Fly back to the nearest point inside the fence and hold.
+    #[default]
+    Hover,
+    Land,
+    /// Emergency stop.
+    Kill,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Geofence {
+    pub shape: FenceShape,
+    pub min_altitude: f32, // meters
+    pub max_altitude: f32, // meters
+    pub action: BreachAction,
+    pub land_velocity: f32, // m/s, for BreachAction::Land
+}
+
+impl Geofence {
+    /// Axis-aligned box from `min` to `max`.
+    pub fn cuboid(min: Vec3, max: Vec3) -> Result<Self, DroneError> {
+        let fence = Self {
+            shape: FenceShape::Box {
+                min: min.truncate(),
+                max: max.truncate(),
+            },
+            min_altitude: min.z,
+            max_altitude: max.z,
+            action: BreachAction::default(),
+            land_velocity: 0.3,
+        };
+        fence.validate()?;
+        Ok(fence)
+    }
+
+    pub fn polygon(
+        vertices: Vec<Vec2>,
+        min_altitude: f32,
+        max_altitude: f32,
+    ) -> Result<Self, DroneError> {
+        let fence = Self {
+            shape: FenceShape::Polygon(vertices),
+            min_altitude,
+            max_altitude,
+            action: BreachAction::default(),
+            land_velocity: 0.3,
+        };
+        fence.validate()?;
+        Ok(fence)
+    }
+
+    /// Checks the limits are finite and ordered, which [`Geofence::clamp`]
+    /// relies on. Fences built from their fields, e.g. loaded with serde,
+    /// are checked by [`GeofencedDrone::new`].
+    pub fn validate(&self) -> Result<(), DroneError> {
+        let invalid = |what: &str| Err(DroneError::InvalidArgument(format!("geofence {}", what)));
+        let ordered = |min: f32, max: f32| min.is_finite() && max.is_finite() && min <= max;
+
+        if !ordered(self.min_altitude, self.max_altitude) {
+            return invalid("altitudes must be finite with the minimum below the maximum");
+        }
+        match &self.shape {
+            FenceShape::Box { min, max } => {
+                if !(ordered(min.x, max.x) && ordered(min.y, max.y)) {
+                    return invalid("box corners must be finite with min below max");
+                }
+            }
+            FenceShape::Polygon(vertices) => {
+                if vertices.len() < 3 || !vertices.iter().all(|vertex| vertex.is_finite()) {
+                    return invalid("polygon needs at least 3 finite vertices");
+                }
+            }
+        }
+        if !(self.land_velocity.is_finite() && self.land_velocity > 0.0) {
+            return invalid("land velocity must be positive");
+        }
+        Ok(())
+    }
+
+    pub fn with_action(mut self, action: BreachAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    pub fn contains(&self, position: Vec3) -> bool {
+        let inside = match &self.shape {
+            FenceShape::Box { min, max } => {
+                position.truncate().cmpge(*min).all() && position.truncate().cmple(*max).all()
+            }
+            FenceShape::Polygon(vertices) => polygon_contains(vertices, position.truncate()),
+        };
+        inside && position.z >= self.min_altitude && position.z <= self.max_altitude
+    }
+
+    /// Nearest point inside the fence.
+    pub fn clamp(&self, position: Vec3) -> Vec3 {
+        let horizontal = match &self.shape {
+            FenceShape::Box { min, max } => position.truncate().clamp(*min, *max),
+            FenceShape::Polygon(vertices) => {
+                let point = position.truncate();
+                if polygon_contains(vertices, point) {
+                    point
+                } else {
+                    nearest_on_outline(vertices, point)
+                }
+            }
+        };
+        horizontal.extend(position.z.clamp(self.min_altitude, self.max_altitude))
+    }
+}
+
+/// Even-odd rule.
+fn polygon_contains(vertices: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    for (i, a) in vertices.iter().enumerate() {
+        let b = vertices[(i + 1) % vertices.len()];
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+fn nearest_on_outline(vertices: &[Vec2], point: Vec2) -> Vec2 {
+    let mut nearest = vertices.first().copied().unwrap_or(point);
+    for (i, &a) in vertices.iter().enumerate() {
+        let b = vertices[(i + 1) % vertices.len()];
+        let edge = b - a;
+        let t = ((point - a).dot(edge) / edge.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+        let candidate = a + edge * t;
+        if candidate.distance_squared(point) < nearest.distance_squared(point) {
+            nearest = candidate;
+        }
+    }
+    nearest
+}
+
+#[derive(Debug, Default)]
+struct Breach {
+    active: bool,
+    hold: Option<PositionCommand>, // Re-sent on every check, for BreachAction::Hover
+}
+
+/// Fence and breach state, shared with the monitor task.
+struct Enforcer {
+    fence: Geofence,
+    breach: Mutex<Breach>,
+}
+
+impl Enforcer {
+    /// Runs the breach action if the drone is flying outside the fence.
+    async fn enforce<D: DroneInterface>(
+        &self,
+        inner: &mut D,
+        state: &DroneState,
+    ) -> Result<(), DroneError> {
+        let position = state.position();
+        let mut breach = self.breach.lock().await;
+
+        let flying = state.supervisor == SupervisorState::Flying;
+        if flying && !breach.active && !self.fence.contains(position) {
+            breach.active = true;
+            tracing::warn!("Geofence breached at {:?}", position);
+            match self.fence.action {
+                BreachAction::Hover => {
+                    let target = self.fence.clamp(position);
+                    breach.hold = Some(PositionCommand {
+                        x: target.x,
+                        y: target.y,
+                        z: target.z,
+                        yaw: state.yaw,
+                    });
+                }
+                BreachAction::Land => {
+                    // Streamed setpoints take priority over the high-level
+                    // commander, so hand over before landing
+                    let stop = DroneCommand::NotifySetpointStop { remain_valid_ms: 0 };
+                    match inner.send_command(stop).await {
+                        Ok(()) | Err(DroneError::UnsupportedCommand(_)) => {}
+                        Err(err) => return Err(err),
+                    }
+                    let duration = (position.z.max(0.0) / self.fence.land_velocity).max(1.0);
+                    inner
+                        .send_command(DroneCommand::HighLevel(HighLevelCommand::Land {
+                            height: 0.0,
+                            duration,
+                        }))
+                        .await?;
+                }
+                BreachAction::Kill => inner.send_command(DroneCommand::EmergencyStop).await?,
+            }
+        }
+
+        // Keep streaming the hold point so the setpoint watchdog doesn't fire
+        if let Some(hold) = breach.hold {
+            inner.send_command(DroneCommand::Position(hold)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the fence on every state the driver reports and passes the state on
+/// to subscribers, until the driver goes away or the wrapper is dropped.
+async fn monitor<D: DroneInterface>(
+    mut states: watch::Receiver<DroneState>,
+    enforced: watch::Sender<DroneState>,
+    inner: Arc<Mutex<D>>,
+    enforcer: Arc<Enforcer>,
+) {
+    while states.changed().await.is_ok() {
+        let state = *states.borrow_and_update();
+        let mut inner = inner.lock().await;
+        if let Err(err) = enforcer.enforce(&mut *inner, &state).await {
+            tracing::warn!("Geofence action failed: {}", err);
+        }
+        drop(inner);
+        enforced.send_replace(state);
+    }
+}
+
+/// Wraps a driver and enforces a [`Geofence`] on it. After a breach only
+/// commands that can't leave the fence go through. A hover holds the nearest
+/// point inside until the client sends a new position target; landing and
+/// kill last until [`GeofencedDrone::clear_breach`]. Subscribed states are
+/// only passed on once the fence has been checked against them.
+///
+/// Unlike [`ObstacleAvoidance`](crate::avoidance::ObstacleAvoidance) this
+/// isn't a [`Middleware`](crate::middleware::Middleware): it watches the state
+/// stream and gates `subscribe_state`, which middleware don't see.
+pub struct GeofencedDrone<D: DroneInterface> {
+    inner: Arc<Mutex<D>>,
+    state: watch::Receiver<DroneState>,
+    capabilities: Capabilities,
+    enforcer: Arc<Enforcer>,
+    monitor: JoinHandle<()>,
+    trajectories: HashMap<u8, Trajectory>, // Checked when started, once the offset is known
+}
+
+impl<D: DroneInterface + 'static> GeofencedDrone<D> {
+    /// Starts watching the driver's states, so it must be called from within
+    /// a Tokio runtime. Fails if the fence doesn't [validate](Geofence::validate).
+    pub fn new(inner: D, fence: Geofence) -> Result<Self, DroneError> {
+        fence.validate()?;
+        let states = inner.subscribe_state();
+        let (enforced, state) = watch::channel(*states.borrow());
+        let capabilities = inner.capabilities();
+        let inner = Arc::new(Mutex::new(inner));
+        let enforcer = Arc::new(Enforcer {
+            fence,
+            breach: Mutex::new(Breach::default()),
+        });
+        let monitor = tokio::spawn(monitor(states, enforced, inner.clone(), enforcer.clone()));
+        Ok(Self {
+            inner,
+            state,
+            capabilities,
+            enforcer,
+            monitor,
+            trajectories: HashMap::new(),
+        })
+    }
+}
+
+impl<D: DroneInterface> GeofencedDrone<D> {
+    pub fn fence(&self) -> &Geofence {
+        &self.enforcer.fence
+    }
+
+    pub async fn is_breached(&self) -> bool {
+        self.enforcer.breach.lock().await.active
+    }
+
+    pub async fn clear_breach(&self) {
+        *self.enforcer.breach.lock().await = Breach::default();
+    }
+
+    /// Stops enforcing the fence and hands the driver back.
+    pub async fn into_inner(mut self) -> D {
+        self.monitor.abort();
+        // The cancelled monitor releases its handle on the driver
+        let _ = (&mut self.monitor).await;
+        let inner = self.inner.clone();
+        drop(self);
+        match Arc::try_unwrap(inner) {
+            Ok(inner) => inner.into_inner(),
+            Err(_) => unreachable!("the monitor has stopped"),
+        }
+    }
+
+    /// Reads the state and enforces the fence on it.
+    async fn check(&self, inner: &mut D) -> Result<DroneState, DroneError> {
+        let state = inner.get_state().await?;
+        self.enforcer.enforce(inner, &state).await?;
+        Ok(state)
+    }
+
+    /// Rejects starting a trajectory that would leave the fence. Relative
+    /// playback starts from `position`, like the firmware shifts it.
+    fn check_trajectory(
+        &self,
+        id: u8,
+        relative: bool,
+        reversed: bool,
+        position: Vec3,
+    ) -> Result<(), DroneError> {
+        let trajectory = self.trajectories.get(&id).ok_or_else(|| {
+            DroneError::SafetyRejected(format!(
+                "trajectory {} wasn't uploaded through the geofence",
+                id
+            ))
+        })?;
+        let offset = if relative {
+            let start = if reversed { trajectory.duration() } else { 0.0 };
+            position - trajectory.eval(start).position
+        } else {
+            Vec3::ZERO
+        };
+
+        const SAMPLES: usize = 20;
+        for piece in &trajectory.pieces {
+            for i in 0..=SAMPLES {
+                let point = piece
+                    .eval(piece.duration * i as f32 / SAMPLES as f32)
+                    .position
+                    + offset;
+                if !self.enforcer.fence.contains(point) {
+                    return Err(DroneError::SafetyRejected(format!(
+                        "trajectory {} leaves the geofence at {:?}",
+                        id, point
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn clamp_altitude(&self, height: f32) -> f32 {
+        height.clamp(
+            self.enforcer.fence.min_altitude,
+            self.enforcer.fence.max_altitude,
+        )
+    }
+
+    /// Clamps position targets into the fence. Returns `None` for commands
+    /// that aren't allowed while breached.
+    fn filter(
+        &self,
+        cmd: DroneCommand,
+        state: &DroneState,
+        breached: bool,
+    ) -> Option<DroneCommand> {
+        let current = state.position();
+        let clamped = match cmd {
+            DroneCommand::Position(PositionCommand { x, y, z, yaw }) => {
+                let target = self.enforcer.fence.clamp(Vec3::new(x, y, z));
+                DroneCommand::Position(PositionCommand {
+                    x: target.x,
+                    y: target.y,
+                    z: target.z,
+                    yaw,
+                })
+            }
+            DroneCommand::ZDistance(cmd) => DroneCommand::ZDistance(ZDistanceCommand {
+                z_distance: self.clamp_altitude(cmd.z_distance),
+                ..cmd
+            }),
+            DroneCommand::Hover(cmd) => DroneCommand::Hover(HoverCommand {
+                z_distance: self.clamp_altitude(cmd.z_distance),
+                ..cmd
+            }),
+            DroneCommand::FullState(cmd) => DroneCommand::FullState(FullStateCommand {
+                position: self.enforcer.fence.clamp(cmd.position),
+                ..cmd
+            }),
+            DroneCommand::HighLevel(HighLevelCommand::Takeoff { height, duration }) => {
+                DroneCommand::HighLevel(HighLevelCommand::Takeoff {
+                    height: self.clamp_altitude(height),
+                    duration,
+                })
+            }
+            DroneCommand::HighLevel(HighLevelCommand::GoTo {
+                x,
+                y,
+                z,
+                yaw,
+                duration,
+                relative,
+            }) => {
+                // Relative moves are resolved against the current position
+                let target = if relative {
+                    current + Vec3::new(x, y, z)
+                } else {
+                    Vec3::new(x, y, z)
+                };
+                let target = self.enforcer.fence.clamp(target);
+                let target = if relative { target - current } else { target };
+                DroneCommand::HighLevel(HighLevelCommand::GoTo {
+                    x: target.x,
+                    y: target.y,
+                    z: target.z,
+                    yaw,
+                    duration,
+                    relative,
+                })
+            }
+            other => other,
+        };
+
+        if !breached {
+            return Some(clamped);
+        }
+        match clamped {
+            DroneCommand::Position(_) if self.enforcer.fence.action == BreachAction::Hover => {
+                Some(clamped)
+            }
+            DroneCommand::HighLevel(HighLevelCommand::Land { .. })
+            | DroneCommand::HighLevel(HighLevelCommand::Stop)
+            | DroneCommand::Assist(_)
+            | DroneCommand::Stop
+            | DroneCommand::Disarm
+            | DroneCommand::EmergencyStop => Some(clamped),
+            _ => None,
+        }
+    }
+}
+
+impl<D: DroneInterface> Drop for GeofencedDrone<D> {
+    fn drop(&mut self) {
+        self.monitor.abort();
+    }
+}
+
+#[async_trait]
+impl<D: DroneInterface> DroneInterface for GeofencedDrone<D> {
+    async fn init(&mut self) -> Result<(), DroneError> {
+        self.inner.lock().await.init().await
+    }
+
+    async fn get_state(&self) -> Result<DroneState, DroneError> {
+        let mut inner = self.inner.lock().await;
+        self.check(&mut inner).await
+    }
+
+    fn subscribe_state(&self) -> watch::Receiver<DroneState> {
+        self.state.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities.clone()
+    }
+
+    async fn send_command(&mut self, cmd: DroneCommand) -> Result<(), DroneError> {
+        let mut inner = self.inner.lock().await;
+        let state = self.check(&mut inner).await?;
+        let mut breach = self.enforcer.breach.lock().await;
+        let name = cmd.name();
+        let cmd = self.filter(cmd, &state, breach.active).ok_or_else(|| {
+            DroneError::SafetyRejected(format!("{} while the geofence is breached", name))
+        })?;
+
+        if let DroneCommand::HighLevel(HighLevelCommand::StartTrajectory {
+            id,
+            relative,
+            reversed,
+            ..
+        }) = cmd
+        {
+            self.check_trajectory(id, relative, reversed, state.position())?;
+        }
+
+        // A new position target takes over from the hover
+        if matches!(cmd, DroneCommand::Position(_)) && breach.hold.is_some() {
+            *breach = Breach::default();
+        }
+        inner.send_command(cmd).await
+    }
+
+    async fn upload_trajectory(
+        &mut self,
+        id: u8,
+        trajectory: &Trajectory,
+    ) -> Result<(), DroneError> {
+        trajectory
+            .validate()
+            .map_err(|err| DroneError::InvalidArgument(format!("trajectory {}: {}", id, err)))?;
+        self.inner
+            .lock()
+            .await
+            .upload_trajectory(id, trajectory)
+            .await?;
+        self.trajectories.insert(id, trajectory.clone());
+        Ok(())
+    }
+
+    async fn get_param(&self, name: &str) -> Result<ParamValue, DroneError> {
+        self.inner.lock().await.get_param(name).await
+    }
+
+    async fn set_param(&mut self, name: &str, value: ParamValue) -> Result<(), DroneError> {
+        self.inner.lock().await.set_param(name, value).await
+    }
+
+    async fn list_params(&self) -> Result<Vec<ParamInfo>, DroneError> {
+        self.inner.lock().await.list_params().await
+    }
+
+    async fn list_log_variables(&self) -> Result<Vec<LogVariable>, DroneError> {
+        self.inner.lock().await.list_log_variables().await
+    }
+
+    async fn subscribe_log(
+        &self,
+        variables: &[&str],
+        period: Duration,
+    ) -> Result<LogStream, DroneError> {
+        self.inner
+            .lock()
+            .await
+            .subscribe_log(variables, period)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trajectory::{Poly4d, TrajectoryPoint};
+    use std::sync::Mutex as SyncMutex;
+
+    /// Records commands; states are published by the test.
+    struct Recorder {
+        state: watch::Receiver<DroneState>,
+        sent: Arc<SyncMutex<Vec<DroneCommand>>>,
+    }
+
+    #[async_trait]
+    impl DroneInterface for Recorder {
+        async fn init(&mut self) -> Result<(), DroneError> {
+            Ok(())
+        }
+
+        async fn get_state(&self) -> Result<DroneState, DroneError> {
+            Ok(*self.state.borrow())
+        }
+
+        fn subscribe_state(&self) -> watch::Receiver<DroneState> {
+            self.state.clone()
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::default()
+        }
+
+        async fn send_command(&mut self, cmd: DroneCommand) -> Result<(), DroneError> {
+            self.sent.lock().unwrap().push(cmd);
+            Ok(())
+        }
+
+        async fn upload_trajectory(&mut self, _: u8, _: &Trajectory) -> Result<(), DroneError> {
+            Ok(())
+        }
+
+        async fn get_param(&self, name: &str) -> Result<ParamValue, DroneError> {
+            Err(DroneError::InvalidArgument(name.to_string()))
+        }
+
+        async fn set_param(&mut self, name: &str, _: ParamValue) -> Result<(), DroneError> {
+            Err(DroneError::InvalidArgument(name.to_string()))
+        }
+
+        async fn list_params(&self) -> Result<Vec<ParamInfo>, DroneError> {
+            Ok(Vec::new())
+        }
+
+        async fn list_log_variables(&self) -> Result<Vec<LogVariable>, DroneError> {
+            Ok(Vec::new())
+        }
+
+        async fn subscribe_log(&self, _: &[&str], _: Duration) -> Result<LogStream, DroneError> {
+            Err(DroneError::UnsupportedCommand("log subscription"))
+        }
+    }
+
+    fn flying_at(x: f32) -> DroneState {
+        DroneState {
+            x,
+            y: 1.0,
+            z: 1.0,
+            supervisor: SupervisorState::Flying,
+            ..Default::default()
+        }
+    }
+
+    fn fenced(
+        action: BreachAction,
+    ) -> (
+        GeofencedDrone<Recorder>,
+        watch::Sender<DroneState>,
+        Arc<SyncMutex<Vec<DroneCommand>>>,
+    ) {
+        let (state_tx, state) = watch::channel(flying_at(1.0));
+        let sent = Arc::new(SyncMutex::new(Vec::new()));
+        let recorder = Recorder {
+            state,
+            sent: sent.clone(),
+        };
+        let fence = Geofence::cuboid(Vec3::ZERO, Vec3::splat(2.0))
+            .unwrap()
+            .with_action(action);
+        (
+            GeofencedDrone::new(recorder, fence).unwrap(),
+            state_tx,
+            sent,
+        )
+    }
+
+    /// Publishes `state` and waits for the monitor to have enforced it.
+    async fn publish(
+        drone: &GeofencedDrone<Recorder>,
+        state_tx: &watch::Sender<DroneState>,
+        state: DroneState,
+    ) {
+        let mut enforced = drone.subscribe_state();
+        enforced.borrow_and_update();
+        state_tx.send_replace(state);
+        enforced.changed().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn lands_on_breach_without_client_calls() {
+        let (drone, state_tx, sent) = fenced(BreachAction::Land);
+        publish(&drone, &state_tx, flying_at(2.5)).await;
+
+        assert!(drone.is_breached().await);
+        let sent = sent.lock().unwrap().clone();
+        assert!(matches!(
+            sent[..],
+            [
+                DroneCommand::NotifySetpointStop { .. },
+                DroneCommand::HighLevel(HighLevelCommand::Land { .. })
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn subscribers_see_enforced_states() {
+        let (drone, state_tx, sent) = fenced(BreachAction::Kill);
+        let mut states = drone.subscribe_state();
+        state_tx.send_replace(flying_at(2.5));
+
+        states.changed().await.unwrap();
+        assert_eq!(states.borrow().x, 2.5);
+        assert!(matches!(
+            sent.lock().unwrap()[..],
+            [DroneCommand::EmergencyStop]
+        ));
+    }
+
+    #[tokio::test]
+    async fn streams_hover_hold_on_every_state() {
+        let (drone, state_tx, sent) = fenced(BreachAction::Hover);
+        for x in [2.5, 2.6, 2.7] {
+            publish(&drone, &state_tx, flying_at(x)).await;
+        }
+
+        assert!(drone.is_breached().await);
+        let sent = sent.lock().unwrap().clone();
+        assert_eq!(sent.len(), 3);
+        for cmd in sent {
+            let DroneCommand::Position(hold) = cmd else {
+                panic!("expected the hold point, got {:?}", cmd);
+            };
+            assert_eq!((hold.x, hold.y, hold.z), (2.0, 1.0, 1.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn ignores_states_inside() {
+        let (drone, state_tx, sent) = fenced(BreachAction::Kill);
+        publish(&drone, &state_tx, flying_at(1.5)).await;
+
+        assert!(!drone.is_breached().await);
+        assert!(sent.lock().unwrap().is_empty());
+        drop(drone.into_inner().await);
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_trajectory_durations() {
+        let (mut drone, _state_tx, _sent) = fenced(BreachAction::Kill);
+        for duration in [-1.0, f32::NAN] {
+            let trajectory = Trajectory::from(Poly4d {
+                duration,
+                ..Default::default()
+            });
+            assert!(matches!(
+                drone.upload_trajectory(1, &trajectory).await,
+                Err(DroneError::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_misordered_limits() {
+        assert!(Geofence::cuboid(Vec3::ZERO, Vec3::new(2.0, 2.0, -1.0)).is_err());
+        assert!(Geofence::cuboid(Vec3::new(3.0, 0.0, 0.0), Vec3::splat(2.0)).is_err());
+        assert!(Geofence::polygon(vec![Vec2::ZERO, Vec2::X, Vec2::Y], f32::NAN, 1.0).is_err());
+        assert!(Geofence::polygon(vec![Vec2::ZERO, Vec2::X], 0.0, 1.0).is_err());
+
+        let mut fence = Geofence::cuboid(Vec3::ZERO, Vec3::splat(2.0)).unwrap();
+        fence.min_altitude = 3.0;
+        let (state_tx, state) = watch::channel(flying_at(1.0));
+        let recorder = Recorder {
+            state,
+            sent: Arc::default(),
+        };
+        assert!(GeofencedDrone::new(recorder, fence).is_err());
+        drop(state_tx);
+    }
+
+    #[tokio::test]
+    async fn checks_trajectories_where_they_start() {
+        let (state_tx, state) = watch::channel(flying_at(1.0));
+        let recorder = Recorder {
+            state,
+            sent: Arc::default(),
+        };
+        let fence = Geofence::cuboid(Vec3::new(0.0, 0.0, 0.5), Vec3::splat(2.0)).unwrap();
+        let mut drone = GeofencedDrone::new(recorder, fence).unwrap();
+        let start = |id, relative| {
+            DroneCommand::HighLevel(HighLevelCommand::StartTrajectory {
+                id,
+                time_scale: 1.0,
+                relative,
+                reversed: false,
+            })
+        };
+
+        // Recorded from the origin, below the fence's floor
+        let origin = TrajectoryPoint::default();
+        let short = TrajectoryPoint::at_rest(Vec3::new(0.5, 0.0, 0.0), 0.0);
+        let long = TrajectoryPoint::at_rest(Vec3::new(1.5, 0.0, 0.0), 0.0);
+        let plan = |end| Trajectory::from(Poly4d::plan(&origin, end, 1.0));
+        drone.upload_trajectory(1, &plan(&short)).await.unwrap();
+        drone.upload_trajectory(2, &plan(&long)).await.unwrap();
+
+        assert!(drone.send_command(start(1, true)).await.is_ok());
+        assert!(matches!(
+            drone.send_command(start(1, false)).await,
+            Err(DroneError::SafetyRejected(_))
+        ));
+        assert!(matches!(
+            drone.send_command(start(2, true)).await,
+            Err(DroneError::SafetyRejected(_))
+        ));
+        assert!(matches!(
+            drone.send_command(start(3, true)).await,
+            Err(DroneError::SafetyRejected(_))
+        ));
+        drop(state_tx);
+    }
+}
@@ -1,3 +1,14 @@
+pub mod avoidance;
+pub mod control;
+pub mod drivers;
+pub mod estimator;
+pub mod geofence;
+pub mod middleware;
+pub mod planning;
+#[cfg(feature = "ros")]
 pub mod ros;
+#[cfg(feature = "sim")]
 pub mod sim;
+pub mod swarm;
+pub mod trajectory;
 pub mod types;
@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+
+use super::Middleware;
+use crate::{
+    trajectory::Trajectory,
+    types::{DroneCommand, DroneError, DroneInterface, ParamValue},
+};
+
+/// Traces every command, upload and parameter change, and warns when one
+/// fails. Setpoints are logged at trace level, as they stream.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Logging;
+
+#[async_trait]
+impl Middleware for Logging {
+    async fn send_command(
+        &mut self,
+        inner: &mut dyn DroneInterface,
+        cmd: DroneCommand,
+    ) -> Result<(), DroneError> {
+        if cmd.is_setpoint() {
+            tracing::trace!("Sending {:?}", cmd);
+        } else {
+            tracing::debug!("Sending {:?}", cmd);
+        }
+        let name = cmd.name();
+        let result = inner.send_command(cmd).await;
+        if let Err(err) = &result {
+            tracing::warn!("{} command failed: {}", name, err);
+        }
+        result
+    }
+
+    async fn upload_trajectory(
+        &mut self,
+        inner: &mut dyn DroneInterface,
+        id: u8,
+        trajectory: &Trajectory,
+    ) -> Result<(), DroneError> {
+        tracing::debug!(
+            "Uploading trajectory {} ({} pieces, {:.1}s)",
+            id,
+            trajectory.pieces.len(),
+            trajectory.duration()
+        );
+        let result = inner.upload_trajectory(id, trajectory).await;
+        if let Err(err) = &result {
+            tracing::warn!("Uploading trajectory {} failed: {}", id, err);
+        }
+        result
+    }
+
+    async fn set_param(
+        &mut self,
+        inner: &mut dyn DroneInterface,
+        name: &str,
+        value: ParamValue,
+    ) -> Result<(), DroneError> {
+        tracing::debug!("Setting {} to {}", name, value);
+        let result = inner.set_param(name, value).await;
+        if let Err(err) = &result {
+            tracing::warn!("Setting {} failed: {}", name, err);
+        }
+        result
+    }
+}
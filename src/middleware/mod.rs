@@ -0,0 +1,166 @@
+//! Composable wrappers around any boxed [`DroneInterface`]. Each [`Layer`]
+//! hands commands to its [`Middleware`] on the way down and is itself a
+//! `DroneInterface`, so layers stack with [`MiddlewareStack`]:
+//!
+//! ```ignore
+//! let drone = MiddlewareStack::new(driver)
+//!     .layer(RateLimit::new(100.0))
+//!     .layer(Validation::default())
+//!     .layer(Logging)
+//!     .build();
+//! ```
+//!
+//! [`ObstacleAvoidance`](crate::avoidance::ObstacleAvoidance) is a middleware
+//! too.
+
+mod logging;
+mod rate_limit;
+mod validation;
+
+pub use logging::Logging;
+pub use rate_limit::RateLimit;
+pub use validation::Validation;
+
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::sync::watch;
+
+use crate::{
+    trajectory::Trajectory,
+    types::{
+        Capabilities, DroneCommand, DroneError, DroneInterface, DroneState, LogStream, LogVariable,
+        ParamInfo, ParamValue,
+    },
+};
+
+/// What a [`Layer`] does to the calls passing through it. Every hook forwards
+/// to `inner` unchanged by default.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    async fn send_command(
+        &mut self,
+        inner: &mut dyn DroneInterface,
+        cmd: DroneCommand,
+    ) -> Result<(), DroneError> {
+        inner.send_command(cmd).await
+    }
+
+    async fn upload_trajectory(
+        &mut self,
+        inner: &mut dyn DroneInterface,
+        id: u8,
+        trajectory: &Trajectory,
+    ) -> Result<(), DroneError> {
+        inner.upload_trajectory(id, trajectory).await
+    }
+
+    async fn set_param(
+        &mut self,
+        inner: &mut dyn DroneInterface,
+        name: &str,
+        value: ParamValue,
+    ) -> Result<(), DroneError> {
+        inner.set_param(name, value).await
+    }
+}
+
+/// A boxed drone behind one [`Middleware`].
+pub struct Layer<M: Middleware> {
+    inner: Box<dyn DroneInterface>,
+    pub middleware: M,
+}
+
+impl<M: Middleware> Layer<M> {
+    pub fn new(inner: Box<dyn DroneInterface>, middleware: M) -> Self {
+        Self { inner, middleware }
+    }
+
+    pub fn into_inner(self) -> Box<dyn DroneInterface> {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<M: Middleware> DroneInterface for Layer<M> {
+    async fn init(&mut self) -> Result<(), DroneError> {
+        self.inner.init().await
+    }
+
+    async fn get_state(&self) -> Result<DroneState, DroneError> {
+        self.inner.get_state().await
+    }
+
+    fn subscribe_state(&self) -> watch::Receiver<DroneState> {
+        self.inner.subscribe_state()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    async fn send_command(&mut self, cmd: DroneCommand) -> Result<(), DroneError> {
+        self.middleware.send_command(self.inner.as_mut(), cmd).await
+    }
+
+    async fn upload_trajectory(
+        &mut self,
+        id: u8,
+        trajectory: &Trajectory,
+    ) -> Result<(), DroneError> {
+        self.middleware
+            .upload_trajectory(self.inner.as_mut(), id, trajectory)
+            .await
+    }
+
+    async fn get_param(&self, name: &str) -> Result<ParamValue, DroneError> {
+        self.inner.get_param(name).await
+    }
+
+    async fn set_param(&mut self, name: &str, value: ParamValue) -> Result<(), DroneError> {
+        self.middleware
+            .set_param(self.inner.as_mut(), name, value)
+            .await
+    }
+
+    async fn list_params(&self) -> Result<Vec<ParamInfo>, DroneError> {
+        self.inner.list_params().await
+    }
+
+    async fn list_log_variables(&self) -> Result<Vec<LogVariable>, DroneError> {
+        self.inner.list_log_variables().await
+    }
+
+    async fn subscribe_log(
+        &self,
+        variables: &[&str],
+        period: Duration,
+    ) -> Result<LogStream, DroneError> {
+        self.inner.subscribe_log(variables, period).await
+    }
+}
+
+/// Builds a stack of layers around a driver. Layers added later wrap the
+/// earlier ones, so they see each command first.
+pub struct MiddlewareStack {
+    drone: Box<dyn DroneInterface>,
+}
+
+impl MiddlewareStack {
+    pub fn new(drone: impl DroneInterface + 'static) -> Self {
+        Self::from_boxed(Box::new(drone))
+    }
+
+    pub fn from_boxed(drone: Box<dyn DroneInterface>) -> Self {
+        Self { drone }
+    }
+
+    pub fn layer(self, middleware: impl Middleware + 'static) -> Self {
+        Self {
+            drone: Box::new(Layer::new(self.drone, middleware)),
+        }
+    }
+
+    pub fn build(self) -> Box<dyn DroneInterface> {
+        self.drone
+    }
+}
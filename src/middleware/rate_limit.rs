@@ -0,0 +1,116 @@
+use async_trait::async_trait;
+use std::time::{Duration, Instant};
+
+use super::Middleware;
+use crate::types::{Capabilities, DroneCommand, DroneError, DroneInterface};
+
+/// Refuses setpoints that arrive faster than `max_rate` with
+/// [`DroneError::RateLimited`], so a fast control loop doesn't flood the radio
+/// and a client can tell its target didn't go out. Only streamed setpoints are
+/// limited. Everything else, including emergency stops, always goes through.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    pub min_interval: Duration,
+    pub dropped: u64, // Setpoints refused so far
+    last_sent: Option<Instant>,
+}
+
+impl RateLimit {
+    /// A `max_rate` that isn't positive and finite means no limit.
+    pub fn new(max_rate: f32) -> Self {
+        let min_interval = if max_rate > 0.0 && max_rate.is_finite() {
+            Duration::from_secs_f32(1.0 / max_rate)
+        } else {
+            Duration::ZERO
+        };
+        Self {
+            min_interval,
+            dropped: 0,
+            last_sent: None,
+        }
+    }
+
+    /// Limits to the rate the drone says it can take, if it gives one.
+    pub fn from_capabilities(capabilities: &Capabilities) -> Self {
+        Self::new(capabilities.max_setpoint_rate)
+    }
+}
+
+#[async_trait]
+impl Middleware for RateLimit {
+    async fn send_command(
+        &mut self,
+        inner: &mut dyn DroneInterface,
+        cmd: DroneCommand,
+    ) -> Result<(), DroneError> {
+        if cmd.is_setpoint() {
+            let now = Instant::now();
+            if self
+                .last_sent
+                .is_some_and(|last| now.duration_since(last) < self.min_interval)
+            {
+                self.dropped += 1;
+                return Err(DroneError::RateLimited);
+            }
+            self.last_sent = Some(now);
+        }
+        inner.send_command(cmd).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        drivers::mock::MockDriver,
+        types::{HoverCommand, RpytCommand},
+    };
+
+    fn setpoint() -> DroneCommand {
+        DroneCommand::Rpyt(RpytCommand {
+            roll: 0.0,
+            pitch: 0.0,
+            yaw: 0.0,
+            thrust: 10000,
+        })
+    }
+
+    #[tokio::test]
+    async fn refuses_early_setpoints() {
+        let mut drone = MockDriver::new();
+        let mut limit = RateLimit::new(10.0);
+        limit.send_command(&mut drone, setpoint()).await.unwrap();
+        assert_eq!(
+            limit.send_command(&mut drone, setpoint()).await,
+            Err(DroneError::RateLimited)
+        );
+        limit
+            .send_command(&mut drone, DroneCommand::EmergencyStop)
+            .await
+            .unwrap();
+        assert_eq!(limit.dropped, 1);
+        assert_eq!(drone.sent.len(), 2);
+
+        tokio::time::sleep(limit.min_interval).await;
+        let hover = DroneCommand::Hover(HoverCommand {
+            vx: 0.0,
+            vy: 0.0,
+            yaw_rate: 0.0,
+            z_distance: 0.5,
+        });
+        limit.send_command(&mut drone, hover).await.unwrap();
+        assert!(matches!(drone.sent.last(), Some(DroneCommand::Hover(_))));
+    }
+
+    #[tokio::test]
+    async fn unlimited_without_a_rate() {
+        let mut drone = MockDriver::new();
+        // The mock doesn't report a setpoint rate
+        let mut limit = RateLimit::from_capabilities(&drone.capabilities());
+        for _ in 0..10 {
+            limit.send_command(&mut drone, setpoint()).await.unwrap();
+        }
+        assert_eq!(drone.sent.len(), 10);
+        assert_eq!(RateLimit::new(f32::NAN).min_interval, Duration::ZERO);
+    }
+}
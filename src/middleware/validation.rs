@@ -0,0 +1,184 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::Middleware;
+use crate::types::{DroneCommand, DroneError, DroneInterface, HighLevelCommand};
+
+/// Rejects commands with NaN or infinite values, or outside these limits,
+/// with [`DroneError::InvalidArgument`] before they reach the drone.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Validation {
+    pub max_tilt: f32,     // degrees, roll and pitch
+    pub max_yaw_rate: f32, // degrees/s
+    pub max_velocity: f32, // m/s, per axis
+    pub max_height: f32,   // meters, absolute heights only
+}
+
+impl Default for Validation {
+    fn default() -> Self {
+        Self {
+            max_tilt: 30.0,
+            max_yaw_rate: 400.0,
+            max_velocity: 3.0,
+            max_height: 10.0,
+        }
+    }
+}
+
+impl Validation {
+    /// Only rejects values no drone can fly: NaN or infinite values, heights
+    /// below the ground and durations that aren't positive.
+    pub fn unlimited() -> Self {
+        Self {
+            max_tilt: f32::INFINITY,
+            max_yaw_rate: f32::INFINITY,
+            max_velocity: f32::INFINITY,
+            max_height: f32::INFINITY,
+        }
+    }
+
+    pub fn check(&self, cmd: &DroneCommand) -> Result<(), DroneError> {
+        let name = cmd.name();
+        let check = |what: &str, value: f32, limit: f32| {
+            if !value.is_finite() {
+                return Err(DroneError::InvalidArgument(format!(
+                    "{} {} is {}",
+                    name, what, value
+                )));
+            }
+            if value.abs() > limit {
+                return Err(DroneError::InvalidArgument(format!(
+                    "{} {} {} is beyond {}",
+                    name, what, value, limit
+                )));
+            }
+            Ok(())
+        };
+        let finite = |what: &str, value: f32| check(what, value, f32::INFINITY);
+        let height = |value: f32| {
+            check("height", value, self.max_height)?;
+            if value < 0.0 {
+                return Err(DroneError::InvalidArgument(format!(
+                    "{} height {} is below the ground",
+                    name, value
+                )));
+            }
+            Ok(())
+        };
+        let duration = |value: f32| {
+            finite("duration", value)?;
+            if value <= 0.0 {
+                return Err(DroneError::InvalidArgument(format!(
+                    "{} duration must be positive",
+                    name
+                )));
+            }
+            Ok(())
+        };
+
+        match cmd {
+            DroneCommand::Rpyt(cmd) => {
+                check("roll", cmd.roll, self.max_tilt)?;
+                check("pitch", cmd.pitch, self.max_tilt)?;
+                check("yaw rate", cmd.yaw, self.max_yaw_rate)
+            }
+            DroneCommand::Position(cmd) => {
+                finite("x", cmd.x)?;
+                finite("y", cmd.y)?;
+                height(cmd.z)?;
+                finite("yaw", cmd.yaw)
+            }
+            DroneCommand::Velocity(cmd) => {
+                check("vx", cmd.vx, self.max_velocity)?;
+                check("vy", cmd.vy, self.max_velocity)?;
+                check("vz", cmd.vz, self.max_velocity)?;
+                check("yaw rate", cmd.yaw_rate, self.max_yaw_rate)
+            }
+            DroneCommand::ZDistance(cmd) => {
+                check("roll", cmd.roll, self.max_tilt)?;
+                check("pitch", cmd.pitch, self.max_tilt)?;
+                check("yaw rate", cmd.yaw_rate, self.max_yaw_rate)?;
+                height(cmd.z_distance)
+            }
+            DroneCommand::Hover(cmd) => {
+                check("vx", cmd.vx, self.max_velocity)?;
+                check("vy", cmd.vy, self.max_velocity)?;
+                check("yaw rate", cmd.yaw_rate, self.max_yaw_rate)?;
+                height(cmd.z_distance)
+            }
+            DroneCommand::FullState(cmd) => {
+                finite("x", cmd.position.x)?;
+                finite("y", cmd.position.y)?;
+                height(cmd.position.z)?;
+                for value in cmd.velocity.to_array() {
+                    check("velocity", value, self.max_velocity)?;
+                }
+                for value in cmd.acceleration.to_array() {
+                    finite("acceleration", value)?;
+                }
+                if !cmd.attitude.is_finite() || !cmd.attitude.is_normalized() {
+                    return Err(DroneError::InvalidArgument(format!(
+                        "{} attitude isn't a unit quaternion",
+                        name
+                    )));
+                }
+                for value in cmd.rates.to_array() {
+                    finite("rate", value)?;
+                }
+                Ok(())
+            }
+            DroneCommand::HighLevel(HighLevelCommand::Takeoff {
+                height: h,
+                duration: d,
+            })
+            | DroneCommand::HighLevel(HighLevelCommand::Land {
+                height: h,
+                duration: d,
+            }) => {
+                height(*h)?;
+                duration(*d)
+            }
+            DroneCommand::HighLevel(HighLevelCommand::GoTo {
+                x,
+                y,
+                z,
+                yaw,
+                duration: d,
+                relative,
+            }) => {
+                finite("x", *x)?;
+                finite("y", *y)?;
+                if *relative {
+                    check("z", *z, self.max_height)?;
+                } else {
+                    height(*z)?;
+                }
+                finite("yaw", *yaw)?;
+                duration(*d)
+            }
+            DroneCommand::HighLevel(HighLevelCommand::StartTrajectory { time_scale, .. }) => {
+                finite("time scale", *time_scale)?;
+                if *time_scale <= 0.0 {
+                    return Err(DroneError::InvalidArgument(format!(
+                        "{} time scale must be positive",
+                        name
+                    )));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for Validation {
+    async fn send_command(
+        &mut self,
+        inner: &mut dyn DroneInterface,
+        cmd: DroneCommand,
+    ) -> Result<(), DroneError> {
+        self.check(&cmd)?;
+        inner.send_command(cmd).await
+    }
+}
@@ -0,0 +1,137 @@
+//! A* search over a [`VoxelGrid`], 26-connected.
+
+use anyhow::{bail, Result};
+use glam::{IVec3, Vec3};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use super::grid::VoxelGrid;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Open {
+    estimate: f32, // cost so far plus the heuristic
+    cell: IVec3,
+}
+
+impl Eq for Open {}
+
+// Reversed, so the heap pops the lowest estimate first
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Shortest path through free cells from `start` to `goal`. The path runs
+/// through cell centers, with the exact start and goal at its ends, and
+/// diagonal steps never squeeze between occupied cells.
+pub fn astar(grid: &VoxelGrid, start: Vec3, goal: Vec3) -> Result<Vec<Vec3>> {
+    let (Some(start_cell), Some(goal_cell)) = (grid.cell(start), grid.cell(goal)) else {
+        bail!("start or goal is outside the grid");
+    };
+    if grid.is_occupied(start_cell) || grid.is_occupied(goal_cell) {
+        bail!("start or goal is occupied");
+    }
+
+    let neighbors: Vec<IVec3> = (-1..=1)
+        .flat_map(|z| (-1..=1).flat_map(move |y| (-1..=1).map(move |x| IVec3::new(x, y, z))))
+        .filter(|offset| *offset != IVec3::ZERO)
+        .collect();
+    let heuristic = |cell: IVec3| (goal_cell - cell).as_vec3().length();
+
+    let mut open = BinaryHeap::new();
+    let mut costs = HashMap::from([(start_cell, 0.0f32)]);
+    let mut parents: HashMap<IVec3, IVec3> = HashMap::new();
+    open.push(Open {
+        estimate: heuristic(start_cell),
+        cell: start_cell,
+    });
+
+    while let Some(Open { estimate, cell }) = open.pop() {
+        let cost = costs[&cell];
+        if estimate > cost + heuristic(cell) + 1e-4 {
+            continue; // Stale entry
+        }
+        if cell == goal_cell {
+            let mut cells = vec![cell];
+            while let Some(parent) = parents.get(cells.last().unwrap()) {
+                cells.push(*parent);
+            }
+            cells.reverse();
+
+            let mut path = vec![start];
+            path.extend(
+                cells[1..cells.len().saturating_sub(1)]
+                    .iter()
+                    .map(|&cell| grid.center(cell)),
+            );
+            path.push(goal);
+            return Ok(path);
+        }
+
+        for offset in &neighbors {
+            let next = cell + *offset;
+            if cuts_corner(grid, cell, *offset) {
+                continue;
+            }
+            let next_cost = cost + offset.as_vec3().length();
+            if costs.get(&next).is_none_or(|&known| next_cost < known) {
+                costs.insert(next, next_cost);
+                parents.insert(next, cell);
+                open.push(Open {
+                    estimate: next_cost + heuristic(next),
+                    cell: next,
+                });
+            }
+        }
+    }
+    bail!("no path to the goal")
+}
+
+/// Whether the move from `cell` by `offset` touches an occupied cell,
+/// including the ones a diagonal passes between.
+fn cuts_corner(grid: &VoxelGrid, cell: IVec3, offset: IVec3) -> bool {
+    (1..8).any(|mask: i32| {
+        let corner = IVec3::new(mask & 1, (mask >> 1) & 1, (mask >> 2) & 1);
+        grid.is_occupied(cell + offset * corner)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planning::{
+        tests::{wall_with_gap, GAP, GOAL, START},
+        Occupancy,
+    };
+
+    #[test]
+    fn finds_the_gap_in_the_wall() {
+        let grid = wall_with_gap();
+        let path = astar(&grid, START, GOAL).unwrap();
+        assert_eq!(path.first(), Some(&START));
+        assert_eq!(path.last(), Some(&GOAL));
+        assert!(path.iter().all(|&point| grid.is_free(point)));
+        let crossing = path
+            .iter()
+            .find(|point| (point.x - 2.0).abs() < 0.1)
+            .unwrap();
+        assert!(GAP.contains(&crossing.y), "crossed at {}", crossing);
+    }
+
+    #[test]
+    fn fails_without_a_path() {
+        let mut grid = wall_with_gap();
+        grid.mark_box(Vec3::new(2.0, 3.0, 1.0), Vec3::new(0.05, 0.5, 1.0));
+        assert!(astar(&grid, START, GOAL).is_err());
+        assert!(astar(&grid, START, Vec3::new(2.0, 1.0, 1.0)).is_err());
+    }
+}
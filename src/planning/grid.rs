@@ -0,0 +1,192 @@
+//! Voxel occupancy grid over an axis-aligned region.
+
+use glam::{IVec3, UVec3, Vec3};
+
+use super::Occupancy;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoxelGrid {
+    origin: Vec3, // corner of the first cell, meters
+    resolution: f32,
+    size: UVec3, // cells per axis
+    occupied: Vec<bool>,
+}
+
+impl VoxelGrid {
+    /// Empty grid covering `min` to `max` with cubic cells of `resolution`
+    /// meters.
+    pub fn new(min: Vec3, max: Vec3, resolution: f32) -> Self {
+        let size = ((max - min) / resolution).ceil().max(Vec3::ONE).as_uvec3();
+        Self {
+            origin: min,
+            resolution,
+            size,
+            occupied: vec![false; (size.x * size.y * size.z) as usize],
+        }
+    }
+
+    pub fn size(&self) -> UVec3 {
+        self.size
+    }
+
+    /// Cell containing `point`, if it's inside the grid.
+    pub fn cell(&self, point: Vec3) -> Option<IVec3> {
+        let cell = ((point - self.origin) / self.resolution).floor().as_ivec3();
+        self.index(cell).map(|_| cell)
+    }
+
+    pub fn center(&self, cell: IVec3) -> Vec3 {
+        self.origin + (cell.as_vec3() + 0.5) * self.resolution
+    }
+
+    fn index(&self, cell: IVec3) -> Option<usize> {
+        let size = self.size.as_ivec3();
+        if cell.cmplt(IVec3::ZERO).any() || cell.cmpge(size).any() {
+            return None;
+        }
+        Some((cell.x + size.x * (cell.y + size.y * cell.z)) as usize)
+    }
+
+    /// Cells outside the grid count as occupied.
+    pub fn is_occupied(&self, cell: IVec3) -> bool {
+        self.index(cell).is_none_or(|i| self.occupied[i])
+    }
+
+    pub fn set(&mut self, cell: IVec3, occupied: bool) {
+        if let Some(i) = self.index(cell) {
+            self.occupied[i] = occupied;
+        }
+    }
+
+    /// Marks every cell that overlaps the box.
+    pub fn mark_box(&mut self, center: Vec3, half_extents: Vec3) {
+        let min = ((center - half_extents - self.origin) / self.resolution).floor();
+        let max = ((center + half_extents - self.origin) / self.resolution).ceil();
+        let min = min.max(Vec3::ZERO).as_ivec3();
+        let max = max.min(self.size.as_vec3()).as_ivec3();
+        for z in min.z..max.z {
+            for y in min.y..max.y {
+                for x in min.x..max.x {
+                    self.set(IVec3::new(x, y, z), true);
+                }
+            }
+        }
+    }
+
+    /// Marks the cells whose centers `occupied` reports, e.g. from a
+    /// collider query.
+    pub fn fill(&mut self, occupied: impl Fn(Vec3) -> bool) {
+        let size = self.size.as_ivec3();
+        for z in 0..size.z {
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    let cell = IVec3::new(x, y, z);
+                    if occupied(self.center(cell)) {
+                        self.set(cell, true);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Grows occupied cells by `radius` meters, for the drone's size.
+    pub fn inflate(&mut self, radius: f32) {
+        let reach = (radius / self.resolution).ceil() as i32;
+        let source = self.clone();
+        let size = self.size.as_ivec3();
+        for z in 0..size.z {
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    let cell = IVec3::new(x, y, z);
+                    if !source.is_occupied(cell) {
+                        continue;
+                    }
+                    for dz in -reach..=reach {
+                        for dy in -reach..=reach {
+                            for dx in -reach..=reach {
+                                let offset = IVec3::new(dx, dy, dz);
+                                if offset.as_vec3().length() * self.resolution <= radius {
+                                    self.set(cell + offset, true);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Occupancy for VoxelGrid {
+    fn is_free(&self, point: Vec3) -> bool {
+        self.cell(point).is_some_and(|cell| !self.is_occupied(cell))
+    }
+
+    fn resolution(&self) -> f32 {
+        self.resolution / 2.0
+    }
+
+    /// Walks every cell the segment touches (Amanatides & Woo), so corners
+    /// can't be cut between samples.
+    fn segment_free(&self, from: Vec3, to: Vec3) -> bool {
+        let (Some(mut cell), Some(last)) = (self.cell(from), self.cell(to)) else {
+            return false;
+        };
+        let start = (from - self.origin) / self.resolution;
+        let direction = (to - self.origin) / self.resolution - start;
+        let step = direction.signum().as_ivec3();
+        let next_boundary = cell.as_vec3() + step.max(IVec3::ZERO).as_vec3();
+        let mut t_max = ((next_boundary - start) / direction).to_array();
+        let t_delta = direction.recip().abs().to_array();
+        for axis in 0..3 {
+            if direction[axis] == 0.0 {
+                t_max[axis] = f32::INFINITY;
+            }
+        }
+
+        loop {
+            if self.is_occupied(cell) {
+                return false;
+            }
+            if cell == last {
+                return true;
+            }
+            let axis = (0..3)
+                .min_by(|&a, &b| t_max[a].total_cmp(&t_max[b]))
+                .unwrap();
+            if t_max[axis] > 1.0 {
+                // Rounding kept us short of the last cell
+                return !self.is_occupied(last);
+            }
+            cell[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segment_free_rejects_cutting_a_corner() {
+        let mut grid = VoxelGrid::new(Vec3::ZERO, Vec3::new(3.0, 3.0, 1.0), 1.0);
+        grid.set(IVec3::new(1, 0, 0), true);
+        grid.set(IVec3::new(0, 1, 0), true);
+        let from = grid.center(IVec3::ZERO);
+        let to = grid.center(IVec3::new(1, 1, 0));
+        // Both ends are free, and the diagonal only touches the corner
+        assert!(grid.is_free(from) && grid.is_free(to));
+        assert!(!grid.segment_free(from, to));
+    }
+
+    #[test]
+    fn segment_free_walks_every_cell() {
+        let mut grid = VoxelGrid::new(Vec3::ZERO, Vec3::new(4.0, 4.0, 1.0), 1.0);
+        grid.set(IVec3::new(2, 1, 0), true);
+        let from = Vec3::new(0.5, 0.5, 0.5);
+        assert!(!grid.segment_free(from, Vec3::new(3.5, 2.0, 0.5)));
+        assert!(grid.segment_free(from, Vec3::new(3.5, 0.5, 0.5)));
+        assert!(!grid.segment_free(from, Vec3::new(4.5, 0.5, 0.5)));
+    }
+}
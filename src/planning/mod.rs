@@ -0,0 +1,163 @@
+//! Collision-free path planning around known obstacles. Paths are lists of
+//! points in the world frame (meters, Z-up) from the start to the goal, and
+//! [`to_trajectory`] turns one into a minimum-snap [`Trajectory`] that stays
+//! clear of the obstacles too.
+
+pub mod astar;
+pub mod grid;
+pub mod rrt;
+
+pub use astar::astar;
+pub use grid::VoxelGrid;
+pub use rrt::RrtStar;
+
+use anyhow::{bail, Result};
+use glam::Vec3;
+
+use crate::trajectory::{
+    min_snap::{self, Limits, Waypoint},
+    Poly4d, Trajectory, TrajectoryPoint,
+};
+
+/// Where the drone can be, already inflated by its size and a safety margin.
+pub trait Occupancy {
+    fn is_free(&self, point: Vec3) -> bool;
+
+    /// Spacing of the checks along a segment, meters.
+    fn resolution(&self) -> f32;
+
+    fn segment_free(&self, from: Vec3, to: Vec3) -> bool {
+        let steps = (from.distance(to) / self.resolution()).ceil().max(1.0) as usize;
+        (0..=steps).all(|i| self.is_free(from.lerp(to, i as f32 / steps as f32)))
+    }
+}
+
+/// Drops every point that can be skipped with a straight, free segment.
+pub fn shortcut(world: &impl Occupancy, path: &[Vec3]) -> Vec<Vec3> {
+    let Some(&first) = path.first() else {
+        return Vec::new();
+    };
+    let mut shortened = vec![first];
+    let mut i = 0;
+    while i + 1 < path.len() {
+        let next = (i + 1..path.len())
+            .rev()
+            .find(|&j| world.segment_free(path[i], path[j]))
+            .unwrap_or(i + 1);
+        shortened.push(path[next]);
+        i = next;
+    }
+    shortened
+}
+
+/// Smooths `path` into a minimum-snap trajectory. The polynomial can bulge
+/// off the straight segments, so wherever it hits an obstacle the segment's
+/// midpoint is added as a waypoint and the trajectory is solved again. Paths
+/// that hug obstacles too closely for that fall back to [`stop_and_go`].
+pub fn to_trajectory(world: &impl Occupancy, path: &[Vec3], limits: &Limits) -> Result<Trajectory> {
+    const MAX_REFINEMENTS: usize = 20;
+
+    let mut points = shortcut(world, path);
+    if points.len() < 2 {
+        bail!("need at least two path points");
+    }
+    for _ in 0..MAX_REFINEMENTS {
+        let waypoints: Vec<Waypoint> = points.iter().map(|&p| Waypoint::new(p)).collect();
+        let trajectory = min_snap::generate(&waypoints, limits)?;
+
+        let colliding = trajectory.pieces.iter().position(|piece| {
+            let steps = (piece.duration * limits.max_velocity / world.resolution())
+                .ceil()
+                .max(1.0) as usize;
+            (0..=steps).any(|i| {
+                let t = piece.duration * i as f32 / steps as f32;
+                !world.is_free(piece.eval(t).position)
+            })
+        });
+        match colliding {
+            Some(i) => points.insert(i + 1, points[i].lerp(points[i + 1], 0.5)),
+            None => return Ok(trajectory),
+        }
+    }
+    tracing::warn!("couldn't smooth the path clear of obstacles, stopping at each point");
+    stop_and_go(&shortcut(world, path), limits)
+}
+
+/// Straight pieces that come to rest at every point, so the drone never
+/// leaves the path's segments.
+pub fn stop_and_go(path: &[Vec3], limits: &Limits) -> Result<Trajectory> {
+    // Peak speed and acceleration of a 7th-order rest-to-rest move over
+    // distance d in time T are 35/16 d/T and about 7.51 d/T²
+    const PEAK_VELOCITY: f32 = 35.0 / 16.0;
+    const PEAK_ACCELERATION: f32 = 7.51;
+
+    if path.len() < 2 {
+        bail!("need at least two path points");
+    }
+    if limits.max_velocity <= 0.0 || limits.max_acceleration <= 0.0 {
+        bail!("limits must be positive");
+    }
+    let pieces = path
+        .windows(2)
+        .filter(|pair| pair[0] != pair[1])
+        .map(|pair| {
+            let distance = pair[0].distance(pair[1]);
+            let duration = (PEAK_VELOCITY * distance / limits.max_velocity)
+                .max((PEAK_ACCELERATION * distance / limits.max_acceleration).sqrt());
+            Poly4d::plan(
+                &TrajectoryPoint::at_rest(pair[0], 0.0),
+                &TrajectoryPoint::at_rest(pair[1], 0.0),
+                duration,
+            )
+        })
+        .collect();
+    Ok(Trajectory { pieces })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub const START: Vec3 = Vec3::new(0.5, 0.5, 1.0);
+    pub const GOAL: Vec3 = Vec3::new(3.5, 0.5, 1.0);
+    /// Where the wall is open, along y.
+    pub const GAP: std::ops::Range<f32> = 2.5..3.5;
+
+    /// 4 x 4 x 2 m room split by a wall at x = 2, with a full-height gap
+    /// from y = 2.5 to 3.5, inflated by 0.1 m.
+    pub fn wall_with_gap() -> VoxelGrid {
+        let mut grid = VoxelGrid::new(Vec3::ZERO, Vec3::new(4.0, 4.0, 2.0), 0.1);
+        grid.mark_box(Vec3::new(2.0, 1.25, 1.0), Vec3::new(0.05, 1.25, 1.0));
+        grid.mark_box(Vec3::new(2.0, 3.75, 1.0), Vec3::new(0.05, 0.25, 1.0));
+        grid.inflate(0.1);
+        grid
+    }
+
+    #[test]
+    fn trajectory_through_the_gap_stays_free() {
+        let grid = wall_with_gap();
+        let path = astar(&grid, START, GOAL).unwrap();
+        let limits = Limits::default();
+        let trajectory = to_trajectory(&grid, &path, &limits).unwrap();
+
+        let start = trajectory.eval(0.0).position;
+        let end = trajectory.eval(trajectory.duration()).position;
+        assert!(start.distance(START) < 1e-3 && end.distance(GOAL) < 1e-3);
+        let steps = (trajectory.duration() / 0.01) as usize;
+        for i in 0..=steps {
+            let point = trajectory.eval(i as f32 * 0.01).position;
+            assert!(grid.is_free(point), "{} is occupied", point);
+        }
+    }
+
+    #[test]
+    fn shortcut_keeps_segments_free() {
+        let grid = wall_with_gap();
+        let path = astar(&grid, START, GOAL).unwrap();
+        let short = shortcut(&grid, &path);
+        assert!(short.len() < path.len());
+        assert!(short
+            .windows(2)
+            .all(|pair| grid.segment_free(pair[0], pair[1])));
+    }
+}
@@ -0,0 +1,235 @@
+//! RRT* in continuous space, against any [`Occupancy`].
+
+use anyhow::{bail, Result};
+use glam::Vec3;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::Occupancy;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RrtStar {
+    pub iterations: usize,
+    pub step: f32,            // meters, longest new edge
+    pub neighbor_radius: f32, // meters, for choosing parents and rewiring
+    pub goal_bias: f64,       // chance of sampling the goal
+    pub seed: Option<u64>,    // for repeatable plans
+}
+
+impl Default for RrtStar {
+    fn default() -> Self {
+        Self {
+            iterations: 2000,
+            step: 0.3,
+            neighbor_radius: 0.8,
+            goal_bias: 0.1,
+            seed: None,
+        }
+    }
+}
+
+struct Node {
+    position: Vec3,
+    parent: Option<usize>,
+    cost: f32,
+    children: Vec<usize>,
+}
+
+impl RrtStar {
+    /// Plans from `start` to `goal`, sampling inside the box `min` to `max`.
+    /// Runs every iteration and returns the cheapest path found. Fails on a
+    /// goal bias outside 0 to 1, a non-positive step or an inverted box.
+    pub fn plan(
+        &self,
+        world: &impl Occupancy,
+        min: Vec3,
+        max: Vec3,
+        start: Vec3,
+        goal: Vec3,
+    ) -> Result<Vec<Vec3>> {
+        if !(0.0..=1.0).contains(&self.goal_bias) {
+            bail!("goal bias {} is outside 0 to 1", self.goal_bias);
+        }
+        if !(self.step.is_finite() && self.step > 0.0) {
+            bail!("step {} must be positive", self.step);
+        }
+        if !(min.is_finite() && max.is_finite()) || min.cmpgt(max).any() {
+            bail!("sampling box {} to {} is invalid", min, max);
+        }
+        if !world.is_free(start) || !world.is_free(goal) {
+            bail!("start or goal is occupied");
+        }
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        let mut nodes = vec![Node {
+            position: start,
+            parent: None,
+            cost: 0.0,
+            children: Vec::new(),
+        }];
+        // Nodes with a free edge to the goal
+        let reaches_goal = |position: Vec3| {
+            position.distance(goal) <= self.step && world.segment_free(position, goal)
+        };
+        let mut goal_links: Vec<usize> = Vec::new();
+        if reaches_goal(start) {
+            goal_links.push(0);
+        }
+
+        for _ in 0..self.iterations {
+            let sample = if rng.gen_bool(self.goal_bias) {
+                goal
+            } else {
+                Vec3::new(
+                    rng.gen_range(min.x..=max.x),
+                    rng.gen_range(min.y..=max.y),
+                    rng.gen_range(min.z..=max.z),
+                )
+            };
+
+            let nearest = (0..nodes.len())
+                .min_by(|&a, &b| {
+                    let da = nodes[a].position.distance_squared(sample);
+                    let db = nodes[b].position.distance_squared(sample);
+                    da.total_cmp(&db)
+                })
+                .unwrap();
+            let from = nodes[nearest].position;
+            let position = from + (sample - from).clamp_length_max(self.step);
+            if !world.segment_free(from, position) {
+                continue;
+            }
+
+            // Cheapest parent among the neighbors
+            let neighbors: Vec<usize> = (0..nodes.len())
+                .filter(|&i| nodes[i].position.distance(position) <= self.neighbor_radius)
+                .collect();
+            let mut parent = nearest;
+            let mut cost = nodes[nearest].cost + from.distance(position);
+            for &i in &neighbors {
+                let through = nodes[i].cost + nodes[i].position.distance(position);
+                if through < cost && world.segment_free(nodes[i].position, position) {
+                    parent = i;
+                    cost = through;
+                }
+            }
+
+            let new = nodes.len();
+            nodes.push(Node {
+                position,
+                parent: Some(parent),
+                cost,
+                children: Vec::new(),
+            });
+            nodes[parent].children.push(new);
+
+            // Rewire neighbors that are cheaper to reach through the new node
+            for &i in &neighbors {
+                let through = cost + position.distance(nodes[i].position);
+                if through < nodes[i].cost && world.segment_free(position, nodes[i].position) {
+                    if let Some(old) = nodes[i].parent {
+                        nodes[old].children.retain(|&child| child != i);
+                    }
+                    nodes[i].parent = Some(new);
+                    nodes[new].children.push(i);
+                    propagate_cost(&mut nodes, i, through);
+                }
+            }
+
+            if reaches_goal(position) {
+                goal_links.push(new);
+            }
+        }
+
+        // Rewiring keeps lowering costs, so pick the best link only now
+        let best = goal_links.into_iter().min_by(|&a, &b| {
+            let cost = |i: usize| nodes[i].cost + nodes[i].position.distance(goal);
+            cost(a).total_cmp(&cost(b))
+        });
+        let Some(mut i) = best else {
+            bail!("no path to the goal after {} iterations", self.iterations);
+        };
+        let mut path = vec![goal];
+        loop {
+            path.push(nodes[i].position);
+            match nodes[i].parent {
+                Some(parent) => i = parent,
+                None => break,
+            }
+        }
+        path.reverse();
+        Ok(path)
+    }
+}
+
+/// Sets the cost of `root` and shifts its whole subtree by the same amount.
+fn propagate_cost(nodes: &mut [Node], root: usize, cost: f32) {
+    let delta = cost - nodes[root].cost;
+    let mut stack = vec![root];
+    while let Some(i) = stack.pop() {
+        nodes[i].cost += delta;
+        stack.extend(nodes[i].children.iter().copied());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planning::tests::{wall_with_gap, GAP, GOAL, START};
+
+    fn planner() -> RrtStar {
+        RrtStar {
+            iterations: 3000,
+            seed: Some(7),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_a_free_path_through_the_gap() {
+        let grid = wall_with_gap();
+        let (min, max) = (Vec3::new(0.0, 0.0, 0.2), Vec3::new(4.0, 4.0, 1.8));
+        let path = planner().plan(&grid, min, max, START, GOAL).unwrap();
+        assert_eq!(path.first(), Some(&START));
+        assert_eq!(path.last(), Some(&GOAL));
+        assert!(path
+            .windows(2)
+            .all(|pair| grid.segment_free(pair[0], pair[1])));
+        let crossing = path
+            .windows(2)
+            .find(|pair| pair[0].x < 2.0 && pair[1].x >= 2.0)
+            .unwrap();
+        let y = crossing[0]
+            .lerp(
+                crossing[1],
+                (2.0 - crossing[0].x) / (crossing[1].x - crossing[0].x),
+            )
+            .y;
+        assert!(GAP.contains(&y), "crossed at y = {}", y);
+    }
+
+    #[test]
+    fn rejects_bad_settings() {
+        let grid = wall_with_gap();
+        let (min, max) = (Vec3::ZERO, Vec3::new(4.0, 4.0, 2.0));
+        for goal_bias in [-0.1, 1.5, f64::NAN] {
+            let rrt = RrtStar {
+                goal_bias,
+                ..planner()
+            };
+            assert!(rrt.plan(&grid, min, max, START, GOAL).is_err());
+        }
+        let rrt = RrtStar {
+            step: 0.0,
+            ..planner()
+        };
+        assert!(rrt.plan(&grid, min, max, START, GOAL).is_err());
+        let inverted = Vec3::new(4.0, -1.0, 2.0);
+        assert!(planner().plan(&grid, min, inverted, START, GOAL).is_err());
+        let nan = Vec3::new(4.0, f32::NAN, 2.0);
+        assert!(planner().plan(&grid, min, nan, START, GOAL).is_err());
+    }
+}
@@ -4,7 +4,7 @@ use r2r;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::types::{DroneCommand, DroneInterface, RpytCommand};
+use crate::types::{DroneCommand, DroneError, DroneInterface, RpytCommand};
 
 pub struct Node {
     node: r2r::Node,
@@ -22,8 +22,15 @@ impl Node {
         })
     }
 
-    pub async fn spin(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        // Updated return type
+    /// Opens the drone from a driver URI, e.g. `sim://default` or
+    /// `radio://0/80/2M/E7E7E7E7E7`, see [`crate::drivers::open`].
+    pub async fn open(uri: &str) -> Result<Self> {
+        Self::new(crate::drivers::open(uri).await?)
+    }
+
+    /// Runs until the drone can't be reached. Commands that fail for other
+    /// reasons are logged, or reported back through the service response.
+    pub async fn spin(&mut self) -> Result<()> {
         // Initialize drone
         self.drone.lock().await.init().await?;
 
@@ -43,13 +50,22 @@ impl Node {
         let mut arm_srv = self
             .node
             .create_service::<r2r::std_srvs::srv::SetBool::Service>("cf/arm")?;
+        let mut emergency_stop_srv = self
+            .node
+            .create_service::<r2r::std_srvs::srv::Trigger::Service>("cf/emergency_stop")?;
+
+        // State updates, pushed by the driver
+        let mut state_rx = self.drone.lock().await.subscribe_state();
 
         // Main loop
         loop {
             tokio::select! {
-                // Publish state
-                _ = tokio::time::sleep(std::time::Duration::from_millis(10)) => {
-                    let state = self.drone.lock().await.get_state().await?;
+                // Publish state as it arrives
+                changed = state_rx.changed() => {
+                    if changed.is_err() {
+                        return Err(DroneError::Link("state updates stopped".to_string()).into());
+                    }
+                    let state = *state_rx.borrow_and_update();
                     let msg = r2r::std_msgs::msg::String {
                         data: serde_json::to_string(&state)?
                     };
@@ -64,7 +80,9 @@ impl Node {
                         yaw: msg.angular.z as f32,
                         thrust: (msg.linear.z * 65535.0) as u16,
                     });
-                    self.drone.lock().await.send_command(cmd).await?;
+                    if let Err(err) = self.drone.lock().await.send_command(cmd).await {
+                        check(err)?;
+                    }
                 }
 
                 // Handle arm/disarm service
@@ -74,14 +92,45 @@ impl Node {
                     } else {
                         DroneCommand::Disarm
                     };
-                    self.drone.lock().await.send_command(cmd).await?;
+                    let result = self.drone.lock().await.send_command(cmd).await;
                     let resp = r2r::std_srvs::srv::SetBool::Response {
-                        success: true,
-                        message: "Command executed".to_string(),
+                        success: result.is_ok(),
+                        message: match &result {
+                            Ok(()) => "Command executed".to_string(),
+                            Err(err) => err.to_string(),
+                        },
                     };
                     req.respond(resp)?;
+                    if let Err(err) = result {
+                        check(err)?;
+                    }
+                }
+
+                // Handle emergency stop service
+                Some(req) = emergency_stop_srv.next() => {
+                    let result = self.drone.lock().await.send_command(DroneCommand::EmergencyStop).await;
+                    let resp = r2r::std_srvs::srv::Trigger::Response {
+                        success: result.is_ok(),
+                        message: match &result {
+                            Ok(()) => "Motors stopped".to_string(),
+                            Err(err) => err.to_string(),
+                        },
+                    };
+                    req.respond(resp)?;
+                    if let Err(err) = result {
+                        check(err)?;
+                    }
                 }
             }
         }
     }
 }
+
+/// Ends the node when the drone is gone, otherwise just logs the failure.
+fn check(err: DroneError) -> Result<(), DroneError> {
+    if err.is_fatal() {
+        return Err(err);
+    }
+    tracing::warn!("Command failed: {}", err);
+    Ok(())
+}
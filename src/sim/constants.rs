@@ -4,5 +4,9 @@ pub const HOVER_THRUST: f32 = DRONE_MASS * GRAVITY;
 pub const MAX_THRUST_PER_MOTOR: f32 = HOVER_THRUST / 2.0; // Each motor needs to provide 1/4 of hover thrust
 pub const BASE_THROTTLE: f32 = 0.5; // 50% throttle should hover
 
-pub const HEIGHT_P_GAIN: f32 = 0.5;
-pub const HEIGHT_D_GAIN: f32 = 0.2;
+pub const ARM_LENGTH: f32 = 0.046; // Motor to center, meters
+pub const YAW_TORQUE_COEFF: f32 = 0.006; // Reaction torque per newton of thrust
+pub const DRONE_INERTIA: [f32; 3] = [1.66e-5, 1.66e-5, 2.93e-5]; // kg·m², body x/y/z
+
+pub const PHYSICS_DT: f32 = 1.0 / 100.0; // Firmware estimator rate
+pub const TOF_MAX_RANGE: f32 = 4.0; // meters, also the Multi-ranger's
@@ -1,6 +1,23 @@
-use crate::{sim::constants::*, types::RpytCommand};
+use super::{
+    frame::{from_cf, quat_to_cf, to_cf},
+    log::DroneLogs,
+    plugin::SimDrones,
+    sensors::{
+        DroneAcceleration, DroneEstimate, DroneEstimator, DroneRanges, EstimatorFactory,
+        GroundTruth, SimSensors,
+    },
+};
+use crate::{
+    control::{
+        AltitudeAssist, Commander, ControlOutput, Controller, HighLevelCommander, PidController,
+        Priority, Setpoint, StateEstimate, Supervisor, VehicleParams,
+    },
+    estimator::Estimator,
+    sim::constants::*,
+};
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
+use std::sync::Arc;
 
 #[derive(Component)]
 pub struct DroneMotor {
@@ -13,17 +30,67 @@ pub struct Drone {
     pub motors: Vec<DroneMotor>,
 }
 
+/// Index of the drone in the order it was added to the sim.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DroneId(pub usize);
+
+/// The controller flying this drone. Replace it to fly a custom implementation.
 #[derive(Component)]
-pub struct HeightController {
-    pub target: f32,
-    pub integral: f32,
-    pub last_error: f32,
+pub struct DroneController(pub Box<dyn Controller>);
+
+impl DroneController {
+    pub fn new(controller: impl Controller + 'static) -> Self {
+        Self(Box::new(controller))
+    }
+}
+
+impl Default for DroneController {
+    fn default() -> Self {
+        Self::new(PidController::new(sim_vehicle()))
+    }
+}
+
+/// Setpoint arbitration and watchdog for this drone.
+#[derive(Component, Default)]
+pub struct DroneCommander(pub Commander);
+
+/// Manual flight assist applied to this drone's Rpyt commands.
+#[derive(Component, Default)]
+pub struct DroneAssist(pub AltitudeAssist);
+
+/// Arming, crash and lockout state. Motors only run while it allows them.
+#[derive(Component, Default)]
+pub struct DroneSupervisor(pub Supervisor);
+
+/// On-board style high-level commander. Its setpoints go to the commander at
+/// [`Priority::HighLevel`].
+#[derive(Component, Default)]
+pub struct DroneHighLevel(pub HighLevelCommander);
+
+/// Builds the controller attached to each spawned drone.
+#[derive(Resource, Clone)]
+pub struct ControllerFactory(pub Arc<dyn Fn() -> Box<dyn Controller> + Send + Sync>);
+
+impl Default for ControllerFactory {
+    fn default() -> Self {
+        Self(Arc::new(|| Box::new(PidController::new(sim_vehicle()))))
+    }
 }
 
 #[derive(Bundle)]
 pub struct DroneBundle {
     drone: Drone,
-    height_controller: HeightController,
+    controller: DroneController,
+    commander: DroneCommander,
+    supervisor: DroneSupervisor,
+    assist: DroneAssist,
+    high_level: DroneHighLevel,
+    sensors: SimSensors,
+    estimator: DroneEstimator,
+    estimate: DroneEstimate,
+    acceleration: DroneAcceleration,
+    ranges: DroneRanges,
+    ground_truth: GroundTruth,
     rigid_body: RigidBody,
     collider: Collider,
     velocity: Velocity,
@@ -57,11 +124,17 @@ impl Default for DroneBundle {
                     },
                 ],
             },
-            height_controller: HeightController {
-                target: 1.0,
-                integral: 0.0,
-                last_error: 0.0,
-            },
+            controller: DroneController::default(),
+            commander: DroneCommander::default(),
+            supervisor: DroneSupervisor::default(),
+            assist: DroneAssist::default(),
+            high_level: DroneHighLevel::default(),
+            sensors: SimSensors::default(),
+            estimator: DroneEstimator::default(),
+            estimate: DroneEstimate::default(),
+            acceleration: DroneAcceleration::default(),
+            ranges: DroneRanges::default(),
+            ground_truth: GroundTruth::default(),
             rigid_body: RigidBody::Dynamic,
             collider: Collider::cuboid(0.05, 0.02, 0.05), // Simple box shape
             velocity: Velocity::zero(),
@@ -70,97 +143,169 @@ impl Default for DroneBundle {
                 linear_damping: 0.1,
                 angular_damping: 1.0,
             },
-            mass_properties: ColliderMassProperties::Mass(DRONE_MASS),
+            mass_properties: ColliderMassProperties::MassProperties(MassProperties {
+                local_center_of_mass: Vec3::ZERO,
+                mass: DRONE_MASS,
+                principal_inertia: from_cf(Vec3::from(DRONE_INERTIA)).abs(),
+                principal_inertia_local_frame: Quat::IDENTITY,
+            }),
             transform: Transform::from_xyz(0.0, 0.0, 0.0), // Start at ground
             global_transform: GlobalTransform::default(),
         }
     }
 }
 
-pub fn setup_drone(mut commands: Commands) {
-    commands.spawn(DroneBundle::default());
-}
+impl DroneBundle {
+    pub fn with_controller(mut self, controller: Box<dyn Controller>) -> Self {
+        self.controller = DroneController(controller);
+        self
+    }
 
-pub fn apply_motor_forces(
-    mut query: Query<(&mut Drone, &mut ExternalForce, &Transform, &Velocity)>,
-) {
-    for (mut drone, mut external_force, transform, velocity) in query.iter_mut() {
-        let mut total_thrust = 0.0;
+    pub fn with_estimator(mut self, estimator: Box<dyn Estimator>) -> Self {
+        self.estimator = DroneEstimator(estimator);
+        self
+    }
 
-        // Calculate total thrust
-        for motor in drone.motors.iter_mut() {
-            motor.current_throttle = motor.target_throttle;
-            let thrust = motor.current_throttle * MAX_THRUST_PER_MOTOR;
-            total_thrust += thrust;
-        }
+    /// Start position in the controller's frame.
+    pub fn with_position(mut self, position: Vec3) -> Self {
+        self.transform = Transform::from_translation(from_cf(position));
+        self
+    }
+}
 
-        // Calculate net force (thrust - gravity)
-        let gravity_force = GRAVITY * DRONE_MASS;
-        let net_force = total_thrust - gravity_force;
+pub fn sim_vehicle() -> VehicleParams {
+    VehicleParams {
+        mass: DRONE_MASS,
+        inertia: Vec3::from(DRONE_INERTIA),
+        max_thrust: MAX_THRUST_PER_MOTOR * 4.0,
+    }
+}
 
-        // Apply forces
-        external_force.force = Vec3::new(0.0, net_force, 0.0);
-        external_force.torque = Vec3::ZERO;
+pub fn setup_drone(
+    mut commands: Commands,
+    drones: Res<SimDrones>,
+    controllers: Res<ControllerFactory>,
+    estimators: Res<EstimatorFactory>,
+) {
+    for (id, (link, position)) in drones.0.iter().enumerate() {
+        let mut estimator = (estimators.0)();
+        estimator.reset(&StateEstimate {
+            position: *position,
+            ..Default::default()
+        });
 
-        println!(
-            "Physics: Height={:.3}m Vel={:.3}m/s Force={:.3}N Damping={:.3}",
-            transform.translation.y,
-            velocity.linvel.y,
-            external_force.force.y,
-            velocity.linvel.y * -1.0 // Damping force
-        );
+        let bundle = DroneBundle::default()
+            .with_controller((controllers.0)())
+            .with_estimator(estimator)
+            .with_position(*position);
+        commands.spawn((bundle, DroneId(id), link.clone(), DroneLogs::default()));
+    }
+}
 
-        println!(
-            "Forces: Thrust={:.4}N | Gravity={:.4}N | Net={:.4}N | Throttles=[{:.3}, {:.3}, {:.3}, {:.3}]",
-            total_thrust,
-            gravity_force,
-            net_force,
-            drone.motors[0].current_throttle,
-            drone.motors[1].current_throttle,
-            drone.motors[2].current_throttle,
-            drone.motors[3].current_throttle,
-        );
+/// Ground-truth state in the controller's frame.
+pub fn true_state(transform: &Transform, velocity: &Velocity) -> StateEstimate {
+    StateEstimate {
+        position: to_cf(transform.translation),
+        velocity: to_cf(velocity.linvel),
+        attitude: quat_to_cf(transform.rotation),
+        angular_velocity: to_cf(transform.rotation.inverse() * velocity.angvel),
     }
 }
 
-pub fn calculate_motor_throttles(cmd: &RpytCommand, height_correction: f32) -> [f32; 4] {
-    let height_correction = height_correction.clamp(-0.3, 0.3);
+pub fn run_high_level(mut query: Query<(&mut DroneHighLevel, &mut DroneCommander)>) {
+    for (mut high_level, mut commander) in query.iter_mut() {
+        if high_level.0.is_active() {
+            // Motors stop once a landing completes
+            let setpoint = high_level.0.update(PHYSICS_DT).unwrap_or_default();
+            commander.0.set_setpoint(setpoint, Priority::HighLevel);
+        }
+    }
+}
 
-    // Map the input thrust differently
-    let thrust_normalized = (cmd.thrust as f32) / 65535.0; // 0 to 1
-    let base_thrust = thrust_normalized + height_correction;
-    let thrust = base_thrust.clamp(0.0, 1.0);
+pub fn run_controllers(
+    mut query: Query<(
+        &mut Drone,
+        &mut DroneController,
+        &mut DroneCommander,
+        &mut DroneSupervisor,
+        &DroneEstimate,
+    )>,
+) {
+    for (mut drone, mut controller, mut commander, mut supervisor, estimate) in query.iter_mut() {
+        let setpoint = commander.0.update(PHYSICS_DT);
+        supervisor
+            .0
+            .update(&estimate.0, &setpoint, commander.0.status(), PHYSICS_DT);
 
-    // For now, ignore roll/pitch/yaw
-    [thrust, thrust, thrust, thrust]
-}
+        let throttles = if supervisor.0.motors_allowed() {
+            match controller.0.update(&setpoint, &estimate.0, PHYSICS_DT) {
+                ControlOutput::Wrench { thrust, torque } => mix_wrench(thrust, torque),
+                ControlOutput::Motors(throttles) => throttles,
+            }
+        } else {
+            controller.0.reset();
+            [0.0; 4]
+        };
 
-impl Default for HeightController {
-    fn default() -> Self {
-        Self {
-            target: 1.0, // Target height in meters
-            integral: 0.0,
-            last_error: 0.0,
+        if throttles.iter().any(|t| t.is_nan()) {
+            continue;
+        }
+        for (motor, &throttle) in drone.motors.iter_mut().zip(throttles.iter()) {
+            motor.target_throttle = throttle.clamp(0.0, 1.0);
         }
     }
 }
 
-pub fn height_control(mut query: Query<(&HeightController, &Transform, &Velocity, &mut Drone)>) {
-    for (controller, transform, velocity, mut drone) in query.iter_mut() {
-        let error = controller.target - transform.translation.y;
+/// Splits collective thrust and body torques across the four motors of an
+/// X-frame (M1 front-right, then clockwise seen from above), as throttles.
+pub fn mix_wrench(thrust: f32, torque: Vec3) -> [f32; 4] {
+    let arm = ARM_LENGTH * std::f32::consts::FRAC_1_SQRT_2;
+    let t = thrust / 4.0;
+    let r = torque.x / (4.0 * arm);
+    let p = torque.y / (4.0 * arm);
+    let y = torque.z / (4.0 * YAW_TORQUE_COEFF);
+
+    [t - r - p + y, t - r + p - y, t + r + p + y, t + r - p - y]
+        .map(|force| (force / MAX_THRUST_PER_MOTOR).clamp(0.0, 1.0))
+}
+
+pub fn apply_motor_forces(mut query: Query<(&mut Drone, &mut ExternalForce, &Transform)>) {
+    let arm = ARM_LENGTH * std::f32::consts::FRAC_1_SQRT_2;
+    // Motor positions in the body frame (x forward, y left) and spin direction
+    let layout = [
+        (Vec2::new(arm, -arm), 1.0),
+        (Vec2::new(-arm, -arm), -1.0),
+        (Vec2::new(-arm, arm), 1.0),
+        (Vec2::new(arm, arm), -1.0),
+    ];
 
-        // Simple PD controller
-        let correction = error * HEIGHT_P_GAIN + (-velocity.linvel.y * HEIGHT_D_GAIN);
-        let throttle = (BASE_THROTTLE + correction).clamp(0.0, 1.0);
+    for (mut drone, mut external_force, transform) in query.iter_mut() {
+        let mut total_thrust = 0.0;
+        let mut torque = Vec3::ZERO;
 
-        // Apply same throttle to all motors
-        for motor in drone.motors.iter_mut() {
-            motor.target_throttle = throttle;
+        for (motor, (position, spin)) in drone.motors.iter_mut().zip(layout) {
+            motor.current_throttle = motor.target_throttle;
+            let thrust = motor.current_throttle * MAX_THRUST_PER_MOTOR;
+            total_thrust += thrust;
+            torque += Vec3::new(
+                position.y * thrust,
+                -position.x * thrust,
+                spin * YAW_TORQUE_COEFF * thrust,
+            );
         }
 
-        println!(
-            "Control: Height={:.3}m Target={:.1}m Error={:.3}m Velocity={:.3}m/s Throttle={:.3}",
-            transform.translation.y, controller.target, error, velocity.linvel.y, throttle
+        // Gravity is applied by Rapier, so only thrust and torque go here
+        external_force.force = transform.rotation * Vec3::Y * total_thrust;
+        external_force.torque = transform.rotation * from_cf(torque);
+
+        debug!(
+            "Forces: Thrust={:.4}N | Torque={:?} | Throttles=[{:.3}, {:.3}, {:.3}, {:.3}]",
+            total_thrust,
+            torque,
+            drone.motors[0].current_throttle,
+            drone.motors[1].current_throttle,
+            drone.motors[2].current_throttle,
+            drone.motors[3].current_throttle,
         );
     }
 }
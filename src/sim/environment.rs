@@ -1,14 +1,28 @@
+use super::frame::from_cf;
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
+/// Static box obstacle, in the controller's frame (Z-up).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Obstacle {
+    pub center: Vec3,       // meters
+    pub half_extents: Vec3, // meters
+}
+
+/// Obstacles spawned at startup.
+#[derive(Resource, Clone, Default)]
+pub struct SimObstacles(pub Vec<Obstacle>);
+
 pub fn setup_environment(mut commands: Commands) {
     // Add camera
+    #[cfg(feature = "render")]
     commands.spawn(Camera3dBundle {
         transform: Transform::from_xyz(-2.0, 2.0, -2.0).looking_at(Vec3::ZERO, Vec3::Y),
         ..default()
     });
 
     // Add light
+    #[cfg(feature = "render")]
     commands.spawn(DirectionalLightBundle {
         transform: Transform::from_xyz(3.0, 8.0, 3.0).looking_at(Vec3::ZERO, Vec3::Y),
         ..default()
@@ -20,3 +34,19 @@ pub fn setup_environment(mut commands: Commands) {
         TransformBundle::from(Transform::from_xyz(0.0, -0.1, 0.0)),
     ));
 }
+
+pub fn setup_obstacles(mut commands: Commands, obstacles: Res<SimObstacles>) {
+    for obstacle in &obstacles.0 {
+        spawn_obstacle(&mut commands, obstacle);
+    }
+}
+
+pub fn spawn_obstacle(commands: &mut Commands, obstacle: &Obstacle) -> Entity {
+    let half_extents = from_cf(obstacle.half_extents).abs();
+    commands
+        .spawn((
+            Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+            TransformBundle::from(Transform::from_translation(from_cf(obstacle.center))),
+        ))
+        .id()
+}
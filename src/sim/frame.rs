@@ -0,0 +1,20 @@
+//! Bevy is Y-up while the Crazyflie and the control stack are Z-up
+//! (x forward, y left). These map vectors and rotations between the two.
+
+use bevy::prelude::*;
+
+pub fn to_cf(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, -v.z, v.y)
+}
+
+pub fn from_cf(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, v.z, -v.y)
+}
+
+pub fn quat_to_cf(q: Quat) -> Quat {
+    Quat::from_xyzw(q.x, -q.z, q.y, q.w)
+}
+
+pub fn quat_from_cf(q: Quat) -> Quat {
+    Quat::from_xyzw(q.x, q.z, -q.y, q.w)
+}
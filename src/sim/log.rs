@@ -0,0 +1,143 @@
+use super::{constants::GRAVITY, drone::Drone, plugin::DroneLink};
+use crate::types::{DroneState, LogSample, LogVariable, ParamType, ParamValue};
+use bevy::prelude::*;
+use tokio::sync::mpsc;
+
+/// Log variables the sim can sample, under their firmware names and types.
+pub const LOG_VARIABLES: [(&str, ParamType); 34] = [
+    ("stateEstimate.x", ParamType::F32),
+    ("stateEstimate.y", ParamType::F32),
+    ("stateEstimate.z", ParamType::F32),
+    ("stateEstimate.vx", ParamType::F32),
+    ("stateEstimate.vy", ParamType::F32),
+    ("stateEstimate.vz", ParamType::F32),
+    ("stateEstimate.ax", ParamType::F32),
+    ("stateEstimate.ay", ParamType::F32),
+    ("stateEstimate.az", ParamType::F32),
+    ("stateEstimate.roll", ParamType::F32),
+    ("stateEstimate.pitch", ParamType::F32),
+    ("stateEstimate.yaw", ParamType::F32),
+    ("stateEstimate.qx", ParamType::F32),
+    ("stateEstimate.qy", ParamType::F32),
+    ("stateEstimate.qz", ParamType::F32),
+    ("stateEstimate.qw", ParamType::F32),
+    ("stabilizer.roll", ParamType::F32),
+    ("stabilizer.pitch", ParamType::F32),
+    ("stabilizer.yaw", ParamType::F32),
+    ("stabilizer.thrust", ParamType::F32),
+    ("gyro.x", ParamType::F32),
+    ("gyro.y", ParamType::F32),
+    ("gyro.z", ParamType::F32),
+    ("motor.m1", ParamType::U16),
+    ("motor.m2", ParamType::U16),
+    ("motor.m3", ParamType::U16),
+    ("motor.m4", ParamType::U16),
+    ("pm.vbat", ParamType::F32),
+    ("range.front", ParamType::U16),
+    ("range.back", ParamType::U16),
+    ("range.left", ParamType::U16),
+    ("range.right", ParamType::U16),
+    ("range.up", ParamType::U16),
+    ("range.zrange", ParamType::U16),
+];
+
+pub fn log_variables() -> Vec<LogVariable> {
+    LOG_VARIABLES
+        .iter()
+        .map(|&(name, kind)| LogVariable {
+            name: name.to_string(),
+            kind,
+        })
+        .collect()
+}
+
+/// A driver's request to sample `variables` every `period` seconds.
+pub struct LogSubscription {
+    pub variables: Vec<String>,
+    pub period: f64,
+    pub samples: mpsc::Sender<LogSample>,
+}
+
+/// Active log subscriptions of a drone, with when each is next due.
+#[derive(Component, Default)]
+pub struct DroneLogs(Vec<(LogSubscription, f64)>);
+
+pub fn run_log_subscriptions(mut query: Query<(&DroneLink, &Drone, &mut DroneLogs)>) {
+    for (link, drone, mut logs) in query.iter_mut() {
+        let state = *link.state.borrow();
+        if let Some(Ok(mut receiver)) = link.logs.as_ref().map(|logs| logs.try_lock()) {
+            while let Ok(subscription) = receiver.try_recv() {
+                logs.0.push((subscription, state.timestamp));
+            }
+        }
+
+        // Subscriptions end when their stream is dropped
+        logs.0
+            .retain(|(subscription, _)| !subscription.samples.is_closed());
+        for (subscription, due) in logs.0.iter_mut() {
+            if state.timestamp < *due {
+                continue;
+            }
+            // Faster than the physics rate samples every step
+            *due = (*due + subscription.period).max(state.timestamp);
+            let sample = LogSample {
+                timestamp: state.timestamp,
+                values: subscription
+                    .variables
+                    .iter()
+                    .map(|name| read_variable(name, &state, drone))
+                    .collect(),
+            };
+            // Like the radio, a slow reader misses samples
+            let _ = subscription.samples.try_send(sample);
+        }
+    }
+}
+
+/// Current value of a variable from [`LOG_VARIABLES`], in firmware units.
+fn read_variable(name: &str, state: &DroneState, drone: &Drone) -> ParamValue {
+    // Out of range reads as a large value, as on the decks
+    let range = |meters: Option<f32>| {
+        ParamValue::U16(meters.map_or(u16::MAX, |meters| (meters * 1000.0) as u16))
+    };
+    let motor = |index: usize| {
+        let throttle = drone.motors.get(index).map_or(0.0, |m| m.current_throttle);
+        ParamValue::U16((throttle * 65535.0) as u16)
+    };
+
+    match name {
+        "stateEstimate.x" => ParamValue::F32(state.x),
+        "stateEstimate.y" => ParamValue::F32(state.y),
+        "stateEstimate.z" => ParamValue::F32(state.z),
+        "stateEstimate.vx" => ParamValue::F32(state.vx),
+        "stateEstimate.vy" => ParamValue::F32(state.vy),
+        "stateEstimate.vz" => ParamValue::F32(state.vz),
+        // In g, like the firmware
+        "stateEstimate.ax" => ParamValue::F32(state.ax / GRAVITY),
+        "stateEstimate.ay" => ParamValue::F32(state.ay / GRAVITY),
+        "stateEstimate.az" => ParamValue::F32(state.az / GRAVITY),
+        "stateEstimate.roll" | "stabilizer.roll" => ParamValue::F32(state.roll),
+        "stateEstimate.pitch" | "stabilizer.pitch" => ParamValue::F32(state.pitch),
+        "stateEstimate.yaw" | "stabilizer.yaw" => ParamValue::F32(state.yaw),
+        "stateEstimate.qx" => ParamValue::F32(state.qx),
+        "stateEstimate.qy" => ParamValue::F32(state.qy),
+        "stateEstimate.qz" => ParamValue::F32(state.qz),
+        "stateEstimate.qw" => ParamValue::F32(state.qw),
+        "stabilizer.thrust" => ParamValue::F32(state.thrust as f32),
+        "gyro.x" => ParamValue::F32(state.roll_rate),
+        "gyro.y" => ParamValue::F32(state.pitch_rate),
+        "gyro.z" => ParamValue::F32(state.yaw_rate),
+        "motor.m1" => motor(0),
+        "motor.m2" => motor(1),
+        "motor.m3" => motor(2),
+        "motor.m4" => motor(3),
+        "pm.vbat" => ParamValue::F32(state.battery_voltage),
+        "range.front" => range(state.ranges.front),
+        "range.back" => range(state.ranges.back),
+        "range.left" => range(state.ranges.left),
+        "range.right" => range(state.ranges.right),
+        "range.up" => range(state.ranges.up),
+        "range.zrange" => range(state.ranges.down),
+        _ => ParamValue::F32(0.0), // Names are checked by the driver
+    }
+}
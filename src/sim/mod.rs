@@ -1,6 +1,14 @@
 pub mod constants;
 pub mod drone;
 pub mod environment;
+pub mod frame;
+pub mod log;
+pub mod occupancy;
+pub mod params;
 pub mod plugin;
+pub mod sensors;
 pub mod state;
+#[cfg(feature = "render")]
 pub mod world;
+
+pub use plugin::SimulationPlugin;
@@ -0,0 +1,49 @@
+use super::frame::from_cf;
+use crate::planning::{Occupancy, VoxelGrid};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+/// Planning against the sim's static colliders, e.g. the ground and spawned
+/// obstacles. Points are in the controller's frame (Z-up).
+pub struct RapierOccupancy<'a> {
+    context: &'a RapierContext,
+    probe: Collider,
+    resolution: f32,
+}
+
+impl<'a> RapierOccupancy<'a> {
+    /// Keeps `clearance` meters from every collider, checking segments every
+    /// `resolution` meters.
+    pub fn new(context: &'a RapierContext, clearance: f32, resolution: f32) -> Self {
+        Self {
+            context,
+            probe: Collider::ball(clearance),
+            resolution,
+        }
+    }
+
+    /// Rasterizes the colliders into a grid, for A*.
+    pub fn to_grid(&self, min: Vec3, max: Vec3, resolution: f32) -> VoxelGrid {
+        let mut grid = VoxelGrid::new(min, max, resolution);
+        grid.fill(|point| !self.is_free(point));
+        grid
+    }
+}
+
+impl Occupancy for RapierOccupancy<'_> {
+    fn is_free(&self, point: Vec3) -> bool {
+        // Drones are dynamic bodies, only static geometry blocks a plan
+        self.context
+            .intersection_with_shape(
+                from_cf(point),
+                Quat::IDENTITY,
+                &self.probe,
+                QueryFilter::only_fixed(),
+            )
+            .is_none()
+    }
+
+    fn resolution(&self) -> f32 {
+        self.resolution
+    }
+}
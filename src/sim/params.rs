@@ -0,0 +1,137 @@
+use super::{
+    drone::{DroneAssist, DroneCommander, DroneController, DroneSupervisor},
+    plugin::DroneLink,
+};
+use crate::types::{AssistMode, DroneError, ParamInfo, ParamType, ParamValue, SupervisorState};
+use bevy::prelude::*;
+use tokio::sync::oneshot;
+
+/// Parameter access from a driver, answered by [`process_params`] on the next
+/// physics step.
+pub enum ParamRequest {
+    Get(String, oneshot::Sender<Result<ParamValue, DroneError>>),
+    Set(String, ParamValue, oneshot::Sender<Result<(), DroneError>>),
+    List(oneshot::Sender<Vec<ParamInfo>>),
+}
+
+/// Firmware parameters the sim maps onto its own modes, besides the
+/// controller's gains.
+const MODE_PARAMS: [&str; 4] = [
+    "flightmode.althold",
+    "stabilizer.stop",
+    "stabilizer.controller",
+    "commander.enHighLevel",
+];
+
+/// The sim's controller is the firmware's PID (1), and it can't be swapped
+/// in flight.
+const PID_CONTROLLER: u8 = 1;
+
+pub fn process_params(
+    mut query: Query<(
+        &DroneLink,
+        &mut DroneController,
+        &mut DroneAssist,
+        &mut DroneSupervisor,
+        &mut DroneCommander,
+    )>,
+) {
+    for (link, mut controller, mut assist, mut supervisor, mut commander) in query.iter_mut() {
+        let Some(params) = &link.params else {
+            continue;
+        };
+        let Ok(mut receiver) = params.try_lock() else {
+            continue;
+        };
+        while let Ok(request) = receiver.try_recv() {
+            // A dropped reply only means the driver stopped waiting
+            match request {
+                ParamRequest::Get(name, reply) => {
+                    let value = match name.as_str() {
+                        "flightmode.althold" => Ok(ParamValue::U8(
+                            (assist.0.mode == AssistMode::AltitudeHold) as u8,
+                        )),
+                        "stabilizer.stop" => Ok(ParamValue::U8(matches!(
+                            supervisor.0.state(),
+                            SupervisorState::EmergencyStopped
+                        ) as u8)),
+                        "stabilizer.controller" => Ok(ParamValue::U8(PID_CONTROLLER)),
+                        "commander.enHighLevel" => Ok(ParamValue::U8(1)),
+                        _ => controller
+                            .0
+                            .params()
+                            .into_iter()
+                            .find(|(param, _)| *param == name)
+                            .map(|(_, value)| ParamValue::F32(value))
+                            .ok_or_else(|| unknown(&name)),
+                    };
+                    let _ = reply.send(value);
+                }
+                ParamRequest::Set(name, value, reply) => {
+                    let result = match name.as_str() {
+                        "flightmode.althold" => {
+                            let mode = if value.as_f64() != 0.0 {
+                                AssistMode::AltitudeHold
+                            } else {
+                                AssistMode::Off
+                            };
+                            assist.0.set_mode(mode);
+                            Ok(())
+                        }
+                        "stabilizer.stop" => {
+                            if value.as_f64() != 0.0 {
+                                commander.0.stop();
+                                supervisor.0.emergency_stop();
+                            } else if matches!(
+                                supervisor.0.state(),
+                                SupervisorState::EmergencyStopped | SupervisorState::Locked
+                            ) {
+                                // Clearing the stop stands in for the reboot
+                                // the firmware needs to get out of these
+                                supervisor.0.reset();
+                                controller.0.reset();
+                            }
+                            Ok(())
+                        }
+                        "stabilizer.controller" if value.as_f64() == PID_CONTROLLER as f64 => {
+                            Ok(())
+                        }
+                        "stabilizer.controller" | "commander.enHighLevel" => {
+                            Err(DroneError::InvalidArgument(format!(
+                                "{} can't be changed in the sim",
+                                name
+                            )))
+                        }
+                        _ if controller.0.set_param(&name, value.as_f32()) => Ok(()),
+                        _ => Err(unknown(&name)),
+                    };
+                    let _ = reply.send(result);
+                }
+                ParamRequest::List(reply) => {
+                    let mut params: Vec<ParamInfo> = MODE_PARAMS
+                        .iter()
+                        .map(|name| ParamInfo {
+                            name: name.to_string(),
+                            kind: ParamType::U8,
+                        })
+                        .collect();
+                    params.extend(
+                        controller
+                            .0
+                            .params()
+                            .into_iter()
+                            .map(|(name, _)| ParamInfo {
+                                name,
+                                kind: ParamType::F32,
+                            }),
+                    );
+                    let _ = reply.send(params);
+                }
+            }
+        }
+    }
+}
+
+fn unknown(name: &str) -> DroneError {
+    DroneError::InvalidArgument(format!("unknown parameter {}", name))
+}
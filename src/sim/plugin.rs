@@ -1,44 +1,194 @@
-use crate::types::{DroneCommand, DroneState};
-use bevy::prelude::*;
-use bevy_rapier3d::{
-    plugin::{RapierConfiguration, TimestepMode},
-    prelude::Velocity,
+use crate::{
+    control::{Controller, Priority, Setpoint},
+    estimator::Estimator,
+    trajectory::Trajectory,
+    types::{Capabilities, CommandSupport, DroneCommand, DroneState, HighLevelCommand, Sensors},
 };
-use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex};
+use bevy::prelude::*;
+use bevy_rapier3d::plugin::{PhysicsSet, RapierConfiguration, TimestepMode};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::{mpsc, watch, Mutex};
 
 use super::{
+    constants::PHYSICS_DT,
     drone::{
-        apply_motor_forces, calculate_motor_throttles, height_control, setup_drone, Drone,
-        HeightController,
+        apply_motor_forces, run_controllers, run_high_level, setup_drone, ControllerFactory,
+        DroneAssist, DroneCommander, DroneController, DroneHighLevel, DroneSupervisor,
     },
-    environment::setup_environment,
-    state::{update_state_sync, SimStateSync},
+    environment::{setup_environment, setup_obstacles, Obstacle, SimObstacles},
+    log::{run_log_subscriptions, LogSubscription},
+    params::{process_params, ParamRequest},
+    sensors::{run_estimators, run_range_sensors, DroneEstimate, EstimatorFactory, FittedSensors},
+    state::update_state_sync,
 };
 
-#[derive(Resource)]
-pub struct SimCommandQueue(pub Arc<Mutex<mpsc::Receiver<DroneCommand>>>);
+/// Uploaded trajectories by id, shared with the driver.
+#[derive(Clone, Default)]
+pub struct SimTrajectories(pub Arc<Mutex<HashMap<u8, Trajectory>>>);
+
+/// Connection between one simulated drone and its driver.
+#[derive(Component, Clone)]
+pub struct DroneLink {
+    pub commands: Arc<Mutex<mpsc::Receiver<DroneCommand>>>,
+    pub state: Arc<watch::Sender<DroneState>>,
+    pub trajectories: SimTrajectories,
+    pub params: Option<Arc<Mutex<mpsc::Receiver<ParamRequest>>>>,
+    pub logs: Option<Arc<Mutex<mpsc::Receiver<LogSubscription>>>>,
+}
+
+impl DroneLink {
+    pub fn new(
+        command_rx: mpsc::Receiver<DroneCommand>,
+        state: Arc<watch::Sender<DroneState>>,
+    ) -> Self {
+        Self {
+            commands: Arc::new(Mutex::new(command_rx)),
+            state,
+            trajectories: SimTrajectories::default(),
+            params: None,
+            logs: None,
+        }
+    }
+
+    /// Answers parameter requests for this drone, see [`ParamRequest`].
+    pub fn with_params(mut self, param_rx: mpsc::Receiver<ParamRequest>) -> Self {
+        self.params = Some(Arc::new(Mutex::new(param_rx)));
+        self
+    }
+
+    /// Starts log subscriptions for this drone, see [`LogSubscription`].
+    pub fn with_logs(mut self, log_rx: mpsc::Receiver<LogSubscription>) -> Self {
+        self.logs = Some(Arc::new(Mutex::new(log_rx)));
+        self
+    }
+
+    pub fn with_trajectories(mut self, trajectories: SimTrajectories) -> Self {
+        self.trajectories = trajectories;
+        self
+    }
+}
+
+/// Drones to spawn at startup, with their start positions (Z-up).
+#[derive(Resource, Clone, Default)]
+pub struct SimDrones(pub Vec<(DroneLink, Vec3)>);
+
 pub struct SimulationPlugin {
-    command_rx: Arc<Mutex<mpsc::Receiver<DroneCommand>>>,
-    state: Arc<Mutex<DroneState>>,
+    drones: SimDrones,
+    controller_factory: ControllerFactory,
+    estimator_factory: EstimatorFactory,
+    obstacles: SimObstacles,
+    sensors: FittedSensors,
 }
 
 impl SimulationPlugin {
-    pub fn new(command_rx: mpsc::Receiver<DroneCommand>, state: Arc<Mutex<DroneState>>) -> Self {
+    /// Simulates a single drone starting at the origin.
+    pub fn new(
+        command_rx: mpsc::Receiver<DroneCommand>,
+        state: Arc<watch::Sender<DroneState>>,
+    ) -> Self {
+        Self::swarm().with_drone(DroneLink::new(command_rx, state), Vec3::ZERO)
+    }
+
+    /// Simulates no drones yet, add them with [`SimulationPlugin::with_drone`].
+    pub fn swarm() -> Self {
         Self {
-            command_rx: Arc::new(Mutex::new(command_rx)),
-            state,
+            drones: SimDrones::default(),
+            controller_factory: ControllerFactory::default(),
+            estimator_factory: EstimatorFactory::default(),
+            obstacles: SimObstacles::default(),
+            sensors: FittedSensors::default(),
+        }
+    }
+
+    /// Adds a drone starting at `position`, in the controller's frame.
+    pub fn with_drone(mut self, link: DroneLink, position: Vec3) -> Self {
+        self.drones.0.push((link, position));
+        self
+    }
+
+    /// Sets the trajectory store of the most recently added drone.
+    pub fn with_trajectories(mut self, trajectories: SimTrajectories) -> Self {
+        if let Some((link, _)) = self.drones.0.last_mut() {
+            link.trajectories = trajectories;
+        }
+        self
+    }
+
+    /// What each simulated drone offers, from its fitted sensors. Every
+    /// command runs through the same commander as on the firmware, but
+    /// without a position sensor the sim has nothing to hold position with,
+    /// so only attitude and height commands are flyable.
+    pub fn capabilities(&self) -> Capabilities {
+        let sensors = self.sensors.0;
+        let positioned = sensors.position;
+        Capabilities {
+            commands: CommandSupport {
+                position: positioned,
+                velocity: positioned,
+                z_distance: positioned || sensors.z_range,
+                hover: positioned,
+                full_state: positioned,
+                high_level: positioned,
+                trajectories: positioned,
+                assist: positioned,
+                ..CommandSupport::all()
+            },
+            sensors,
+            decks: Vec::new(),
+            firmware: None,
+            trajectory_memory: None, // Unlimited
+            max_setpoint_rate: 1.0 / PHYSICS_DT,
+            state_rate: 1.0 / PHYSICS_DT,
         }
     }
+
+    /// Chooses which sensors the drones carry, see [`FittedSensors`]. The
+    /// IMU is always fitted, and there is no battery or flow sensor.
+    pub fn with_sensors(mut self, sensors: Sensors) -> Self {
+        self.sensors = FittedSensors(Sensors {
+            imu: true,
+            battery: false,
+            flow: false,
+            ..sensors
+        });
+        self
+    }
+
+    /// Adds box obstacles to the world, e.g. for the range sensors.
+    pub fn with_obstacles(mut self, obstacles: Vec<Obstacle>) -> Self {
+        self.obstacles = SimObstacles(obstacles);
+        self
+    }
+
+    /// Flies spawned drones with a custom controller instead of the default PID.
+    pub fn with_controller<F>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> Box<dyn Controller> + Send + Sync + 'static,
+    {
+        self.controller_factory = ControllerFactory(Arc::new(factory));
+        self
+    }
+
+    /// Fuses sensors with a custom estimator instead of the default Kalman filter.
+    pub fn with_estimator<F>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> Box<dyn Estimator> + Send + Sync + 'static,
+    {
+        self.estimator_factory = EstimatorFactory(Arc::new(factory));
+        self
+    }
 }
 
 impl Plugin for SimulationPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(SimCommandQueue(self.command_rx.clone()))
-            .insert_resource(SimStateSync(self.state.clone()))
+        app.insert_resource(self.drones.clone())
+            .insert_resource(self.controller_factory.clone())
+            .insert_resource(self.estimator_factory.clone())
+            .insert_resource(self.obstacles.clone())
+            .insert_resource(self.sensors)
             .insert_resource(RapierConfiguration {
                 timestep_mode: TimestepMode::Fixed {
-                    dt: 1.0 / 60.0,
+                    dt: PHYSICS_DT,
                     substeps: 1,
                 },
                 gravity: Vec3::new(0.0, -9.81, 0.0),
@@ -47,55 +197,172 @@ impl Plugin for SimulationPlugin {
                 scaled_shape_subdivision: 10,
                 force_update_from_transform_changes: true,
             })
-            .add_systems(Startup, (setup_drone, setup_environment))
+            // Sensors, estimation and control step with physics, at the
+            // firmware's estimator rate
+            .insert_resource(Time::<Fixed>::from_seconds(PHYSICS_DT as f64))
+            .add_systems(Startup, (setup_drone, setup_environment, setup_obstacles))
             .add_systems(
-                Update,
+                FixedUpdate,
                 (
-                    height_control,
+                    run_estimators,
+                    run_range_sensors,
                     process_commands,
+                    process_params,
+                    run_high_level,
+                    run_controllers,
                     apply_motor_forces,
                     update_state_sync,
+                    run_log_subscriptions,
                 )
-                    .chain(),
+                    .chain()
+                    .before(PhysicsSet::SyncBackend),
             );
     }
 }
 
 fn process_commands(
-    command_queue: ResMut<SimCommandQueue>,
-    mut drone_query: Query<&mut Drone>,
-    mut height_query: Query<(&mut HeightController, &Transform, &Velocity)>,
+    mut drone_query: Query<(
+        &DroneLink,
+        &mut DroneCommander,
+        &mut DroneController,
+        &mut DroneHighLevel,
+        &mut DroneSupervisor,
+        &mut DroneAssist,
+        &DroneEstimate,
+    )>,
 ) {
-    if let Ok(mut drone) = drone_query.get_single_mut() {
-        let height_correction =
-            if let Ok((mut controller, transform, velocity)) = height_query.get_single_mut() {
-                let error = controller.target - transform.translation.y;
-                // Simple P controller with velocity damping
-                let correction = error * 0.5 + (-velocity.linvel.y * 0.2);
-                correction.clamp(-0.3, 0.3)
-            } else {
-                0.0
-            };
-
-        if let Ok(mut receiver) = command_queue.0.try_lock() {
+    for (
+        link,
+        mut commander,
+        mut controller,
+        mut high_level,
+        mut supervisor,
+        mut assist,
+        estimate,
+    ) in drone_query.iter_mut()
+    {
+        if let Ok(mut receiver) = link.commands.try_lock() {
             while let Ok(command) = receiver.try_recv() {
+                // Low-level setpoints take over from the high-level commander,
+                // and a high-level command hands control back to it
+                match command {
+                    DroneCommand::HighLevel(_) | DroneCommand::NotifySetpointStop { .. } => {
+                        commander.0.relax_priority()
+                    }
+                    DroneCommand::Assist(_) => {}
+                    _ => high_level.0.stop(),
+                }
+
                 match command {
                     DroneCommand::Rpyt(cmd) => {
-                        let throttles = calculate_motor_throttles(&cmd, height_correction);
-                        if !throttles.iter().any(|t| t.is_nan()) {
-                            for (motor, &throttle) in drone.motors.iter_mut().zip(throttles.iter())
-                            {
-                                motor.target_throttle = throttle.clamp(0.0, 1.0);
+                        let setpoint = assist.0.setpoint(cmd, &estimate.0);
+                        apply_setpoint(&mut commander, setpoint)
+                    }
+                    DroneCommand::Position(cmd) => apply_setpoint(&mut commander, cmd.into()),
+                    DroneCommand::Velocity(cmd) => apply_setpoint(&mut commander, cmd.into()),
+                    DroneCommand::ZDistance(cmd) => apply_setpoint(&mut commander, cmd.into()),
+                    DroneCommand::Hover(cmd) => {
+                        let yaw = estimate.0.rpy_degrees().z;
+                        apply_setpoint(&mut commander, Setpoint::hover(cmd, yaw))
+                    }
+                    DroneCommand::FullState(cmd) => apply_setpoint(&mut commander, cmd.into()),
+                    DroneCommand::Stop => apply_setpoint(&mut commander, Setpoint::default()),
+                    DroneCommand::NotifySetpointStop { remain_valid_ms } => {
+                        let remain_valid = remain_valid_ms as f32 / 1000.0;
+                        commander.0.notify_setpoint_stop(remain_valid);
+                    }
+                    DroneCommand::HighLevel(cmd) => {
+                        let state = estimate.0;
+                        let high_level = &mut high_level.0;
+                        match cmd {
+                            HighLevelCommand::Takeoff { height, duration } => {
+                                high_level.takeoff(&state, height, duration)
+                            }
+                            HighLevelCommand::Land { height, duration } => {
+                                high_level.land(&state, height, duration)
+                            }
+                            HighLevelCommand::GoTo {
+                                x,
+                                y,
+                                z,
+                                yaw,
+                                duration,
+                                relative,
+                            } => high_level.go_to(
+                                &state,
+                                Vec3::new(x, y, z),
+                                yaw,
+                                duration,
+                                relative,
+                            ),
+                            HighLevelCommand::StartTrajectory {
+                                id,
+                                time_scale,
+                                relative,
+                                reversed,
+                            } => {
+                                // Uploads only hold the lock to insert
+                                let trajectory =
+                                    link.trajectories.0.blocking_lock().get(&id).cloned();
+                                match trajectory {
+                                    Some(trajectory) => high_level.start_trajectory(
+                                        &state, trajectory, time_scale, relative, reversed,
+                                    ),
+                                    None => warn!("Unknown trajectory id {}", id),
+                                }
+                            }
+                            HighLevelCommand::Stop => {
+                                high_level.stop();
+                                commander.0.stop();
                             }
                         }
                     }
-                    DroneCommand::Arm | DroneCommand::Disarm => {
-                        for motor in &mut drone.motors {
-                            motor.target_throttle = 0.0;
-                        }
+                    DroneCommand::Assist(mode) => assist.0.set_mode(mode),
+                    DroneCommand::Arm => {
+                        commander.0.stop();
+                        controller.0.reset();
+                        supervisor.0.arm();
+                    }
+                    DroneCommand::Disarm => {
+                        commander.0.stop();
+                        controller.0.reset();
+                        supervisor.0.disarm();
+                    }
+                    DroneCommand::EmergencyStop => {
+                        commander.0.stop();
+                        supervisor.0.emergency_stop();
                     }
                 }
             }
         }
     }
 }
+
+fn apply_setpoint(commander: &mut DroneCommander, setpoint: Setpoint) {
+    commander.0.set_setpoint(setpoint, Priority::Crtp);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_follow_fitted_sensors() {
+        let plugin = SimulationPlugin::swarm();
+        assert!(plugin.capabilities().commands.high_level);
+
+        let plugin = plugin.with_sensors(Sensors {
+            position: false,
+            z_range: true,
+            multiranger: false,
+            ..Default::default()
+        });
+        let capabilities = plugin.capabilities();
+        assert!(capabilities.sensors.imu);
+        assert!(!capabilities.sensors.multiranger);
+        assert!(!capabilities.commands.position);
+        assert!(!capabilities.commands.high_level);
+        assert!(capabilities.commands.z_distance);
+        assert!(capabilities.commands.rpyt);
+    }
+}
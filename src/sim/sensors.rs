@@ -0,0 +1,291 @@
+use super::{
+    constants::{PHYSICS_DT, TOF_MAX_RANGE},
+    drone::true_state,
+    frame::from_cf,
+};
+use crate::{
+    control::StateEstimate,
+    estimator::{Estimator, ImuSample, KalmanEstimator, Measurement, GRAVITY},
+    types::{Ranges, Sensors},
+};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+use rand_distr::StandardNormal;
+use std::sync::Arc;
+
+/// Noise levels (standard deviations) for the simulated sensors.
+#[derive(Component)]
+pub struct SimSensors {
+    pub gyro_noise: f32,     // rad/s
+    pub accel_noise: f32,    // m/s²
+    pub position_noise: f32, // meters, external position system
+    pub tof_noise: f32,      // meters, downward range sensor
+    pub range_noise: f32,    // meters, Multi-ranger
+    last_velocity: Vec3,
+}
+
+impl Default for SimSensors {
+    fn default() -> Self {
+        Self {
+            gyro_noise: 0.005,
+            accel_noise: 0.1,
+            position_noise: 0.002,
+            tof_noise: 0.005,
+            range_noise: 0.005,
+            last_velocity: Vec3::ZERO,
+        }
+    }
+}
+
+/// Sensors fitted to every simulated drone. The IMU always is, and there is
+/// no battery or flow sensor to fit.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct FittedSensors(pub Sensors);
+
+impl Default for FittedSensors {
+    fn default() -> Self {
+        Self(Sensors {
+            imu: true,
+            battery: false,
+            position: true,
+            flow: false,
+            z_range: true,
+            multiranger: true,
+        })
+    }
+}
+
+/// The state estimator the controllers fly on.
+#[derive(Component)]
+pub struct DroneEstimator(pub Box<dyn Estimator>);
+
+impl Default for DroneEstimator {
+    fn default() -> Self {
+        Self(Box::new(KalmanEstimator::default()))
+    }
+}
+
+/// Builds the estimator attached to each spawned drone.
+#[derive(Resource, Clone)]
+pub struct EstimatorFactory(pub Arc<dyn Fn() -> Box<dyn Estimator> + Send + Sync>);
+
+impl Default for EstimatorFactory {
+    fn default() -> Self {
+        Self(Arc::new(|| Box::new(KalmanEstimator::default())))
+    }
+}
+
+/// Latest output of the drone's estimator.
+#[derive(Component, Default)]
+pub struct DroneEstimate(pub StateEstimate);
+
+/// Acceleration in the world frame without gravity, from the accelerometer
+/// and the estimated attitude like the firmware's `stateEstimate.acc`.
+#[derive(Component, Default)]
+pub struct DroneAcceleration(pub Vec3);
+
+/// Latest range readings, ray cast against the physics world.
+#[derive(Component, Default)]
+pub struct DroneRanges(pub Ranges);
+
+/// True state in the controller's frame, for comparison with the estimate.
+#[derive(Component, Default)]
+pub struct GroundTruth(pub StateEstimate);
+
+pub fn run_estimators(
+    fitted: Res<FittedSensors>,
+    mut query: Query<(
+        &mut SimSensors,
+        &mut DroneEstimator,
+        &mut DroneEstimate,
+        &mut DroneAcceleration,
+        &mut GroundTruth,
+        &Transform,
+        &Velocity,
+    )>,
+) {
+    let mut rng = rand::thread_rng();
+    let mut noise = |std_dev: f32| -> Vec3 {
+        Vec3::new(
+            rng.sample::<f32, _>(StandardNormal),
+            rng.sample::<f32, _>(StandardNormal),
+            rng.sample::<f32, _>(StandardNormal),
+        ) * std_dev
+    };
+
+    for (
+        mut sensors,
+        mut estimator,
+        mut estimate,
+        mut acceleration,
+        mut ground_truth,
+        transform,
+        velocity,
+    ) in query.iter_mut()
+    {
+        let truth = true_state(transform, velocity);
+        let accel = (truth.velocity - sensors.last_velocity) / PHYSICS_DT;
+        sensors.last_velocity = truth.velocity;
+
+        let imu = ImuSample {
+            gyro: truth.angular_velocity + noise(sensors.gyro_noise),
+            accel: truth.attitude.inverse() * (accel + Vec3::Z * GRAVITY)
+                + noise(sensors.accel_noise),
+        };
+        estimator.0.predict(&imu, PHYSICS_DT);
+        if fitted.0.position {
+            estimator.0.update(&Measurement::Position {
+                position: truth.position + noise(sensors.position_noise),
+                std_dev: sensors.position_noise,
+            });
+        }
+
+        // Range to the floor along the body z axis, while it points down
+        let body_z = truth.attitude * Vec3::Z;
+        if fitted.0.z_range && body_z.z > 0.1 {
+            let distance = truth.position.z.max(0.0) / body_z.z;
+            if distance < TOF_MAX_RANGE {
+                estimator.0.update(&Measurement::Tof {
+                    distance: distance + noise(sensors.tof_noise).x,
+                    std_dev: sensors.tof_noise,
+                });
+            }
+        }
+
+        estimate.0 = estimator.0.state();
+        acceleration.0 = estimate.0.attitude * imu.accel - Vec3::Z * GRAVITY;
+        ground_truth.0 = truth;
+    }
+}
+
+pub fn run_range_sensors(
+    rapier_context: Res<RapierContext>,
+    fitted: Res<FittedSensors>,
+    mut query: Query<(Entity, &SimSensors, &mut DroneRanges, &Transform)>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for (entity, sensors, mut ranges, transform) in query.iter_mut() {
+        let filter = QueryFilter::default().exclude_rigid_body(entity);
+        let mut range = |direction: Vec3, present: bool| {
+            if !present {
+                return None;
+            }
+            let direction = transform.rotation * from_cf(direction);
+            rapier_context
+                .cast_ray(
+                    transform.translation,
+                    direction,
+                    TOF_MAX_RANGE,
+                    true,
+                    filter,
+                )
+                .map(|(_, distance)| {
+                    // Noise can't put an obstacle behind the sensor
+                    (distance + rng.sample::<f32, _>(StandardNormal) * sensors.range_noise).max(0.0)
+                })
+        };
+
+        let multiranger = fitted.0.multiranger;
+        ranges.0 = Ranges {
+            front: range(Vec3::X, multiranger),
+            back: range(Vec3::NEG_X, multiranger),
+            left: range(Vec3::Y, multiranger),
+            right: range(Vec3::NEG_Y, multiranger),
+            up: range(Vec3::Z, multiranger),
+            down: range(Vec3::NEG_Z, fitted.0.z_range),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::environment::{setup_obstacles, Obstacle, SimObstacles};
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn ranges_hit_spawned_obstacles() {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            HierarchyPlugin,
+            RapierPhysicsPlugin::<NoUserData>::default(),
+        ))
+        .insert_resource(SimObstacles(vec![
+            Obstacle {
+                center: Vec3::new(1.0, 0.0, 1.0),
+                half_extents: Vec3::splat(0.1),
+            },
+            Obstacle {
+                center: Vec3::new(0.0, 0.6, 1.0),
+                half_extents: Vec3::splat(0.1),
+            },
+        ]))
+        .init_resource::<FittedSensors>()
+        .add_systems(Startup, setup_obstacles);
+
+        let drone = app
+            .world_mut()
+            .spawn((
+                SimSensors {
+                    range_noise: 0.0,
+                    ..Default::default()
+                },
+                DroneRanges::default(),
+                Transform::from_translation(from_cf(Vec3::new(0.0, 0.0, 1.0))),
+            ))
+            .id();
+
+        // Let Rapier pick up the colliders before casting against them
+        app.update();
+        app.update();
+        app.world_mut().run_system_once(run_range_sensors);
+
+        let ranges = app.world().get::<DroneRanges>(drone).unwrap().0;
+        assert!((ranges.front.unwrap() - 0.9).abs() < 1e-3);
+        assert!((ranges.left.unwrap() - 0.5).abs() < 1e-3);
+        assert_eq!(ranges.back, None);
+        assert_eq!(ranges.right, None);
+        assert_eq!(ranges.up, None);
+        assert_eq!(ranges.down, None);
+    }
+
+    #[test]
+    fn unfitted_sensors_read_nothing() {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            HierarchyPlugin,
+            RapierPhysicsPlugin::<NoUserData>::default(),
+        ))
+        .insert_resource(SimObstacles(vec![Obstacle {
+            center: Vec3::new(1.0, 0.0, 1.0),
+            half_extents: Vec3::splat(0.1),
+        }]))
+        .insert_resource(FittedSensors(Sensors {
+            multiranger: false,
+            ..FittedSensors::default().0
+        }))
+        .add_systems(Startup, setup_obstacles);
+
+        let drone = app
+            .world_mut()
+            .spawn((
+                SimSensors::default(),
+                DroneRanges::default(),
+                Transform::from_translation(from_cf(Vec3::new(0.0, 0.0, 1.0))),
+            ))
+            .id();
+
+        app.update();
+        app.update();
+        app.world_mut().run_system_once(run_range_sensors);
+
+        let ranges = app.world().get::<DroneRanges>(drone).unwrap().0;
+        assert_eq!(ranges.front, None);
+    }
+}
@@ -1,25 +1,57 @@
-use super::drone::Drone;
+use super::{
+    drone::{Drone, DroneCommander, DroneSupervisor},
+    plugin::DroneLink,
+    sensors::{DroneAcceleration, DroneEstimate, DroneRanges},
+};
 use crate::types::DroneState;
 use bevy::prelude::*;
-use std::sync::Arc;
-use tokio::sync::Mutex;
 
-#[derive(Resource)]
-pub struct SimStateSync(pub Arc<Mutex<DroneState>>);
-
-pub fn update_state_sync(query: Query<(&Transform, &Drone)>, state_sync: Res<SimStateSync>) {
-    if let Ok((transform, drone)) = query.get_single() {
-        if let Ok(mut state) = state_sync.0.try_lock() {
-            let (roll, pitch, yaw) = transform.rotation.to_euler(EulerRot::XYZ);
-            *state = DroneState {
-                roll: roll.to_degrees(),
-                pitch: pitch.to_degrees(),
-                yaw: yaw.to_degrees(),
-                thrust: (drone.motors.iter().map(|m| m.current_throttle).sum::<f32>() * 65535.0)
-                    as u16,
-                armed: drone.motors.iter().any(|m| m.current_throttle > 0.0),
-                battery_voltage: 3.7, // Simulated battery voltage
-            };
-        }
+pub fn update_state_sync(
+    query: Query<(
+        &DroneLink,
+        &DroneEstimate,
+        &DroneAcceleration,
+        &Drone,
+        &DroneCommander,
+        &DroneSupervisor,
+        &DroneRanges,
+    )>,
+    time: Res<Time>,
+) {
+    for (link, estimate, acceleration, drone, commander, supervisor, ranges) in query.iter() {
+        // Like the hardware, report what the estimator believes
+        let rpy = estimate.0.rpy_degrees();
+        let rates = estimate.0.angular_velocity * 180.0 / std::f32::consts::PI;
+        let position = estimate.0.position;
+        let velocity = estimate.0.velocity;
+        let attitude = estimate.0.attitude;
+        link.state.send_replace(DroneState {
+            x: position.x,
+            y: position.y,
+            z: position.z,
+            vx: velocity.x,
+            vy: velocity.y,
+            vz: velocity.z,
+            ax: acceleration.0.x,
+            ay: acceleration.0.y,
+            az: acceleration.0.z,
+            roll: rpy.x,
+            pitch: rpy.y,
+            yaw: rpy.z,
+            roll_rate: rates.x,
+            pitch_rate: rates.y,
+            yaw_rate: rates.z,
+            qx: attitude.x,
+            qy: attitude.y,
+            qz: attitude.z,
+            qw: attitude.w,
+            timestamp: time.elapsed_seconds_f64(),
+            thrust: (drone.motors.iter().map(|m| m.current_throttle).sum::<f32>() * 65535.0) as u16,
+            armed: supervisor.0.is_armed(),
+            battery_voltage: 3.7, // Simulated battery voltage
+            commander: commander.0.status(),
+            supervisor: supervisor.0.state(),
+            ranges: ranges.0,
+        });
     }
 }
@@ -0,0 +1,289 @@
+//! Buffered Voronoi cell collision avoidance, after the firmware's
+//! `collision_avoidance` module. Each drone keeps to its own side of the
+//! bisecting plane to every peer, less a buffer of its ellipsoid, so no two
+//! drones following their filtered setpoints can touch.
+
+use glam::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{
+    DroneCommand, DroneState, FullStateCommand, HoverCommand, PositionCommand, VelocityCommand,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BufferedVoronoi {
+    /// Half-axes of the collision ellipsoid around each drone, meters. Taller
+    /// than wide to keep out of each other's downwash.
+    pub ellipsoid_radii: Vec3,
+    pub horizon: f32,   // seconds, how far ahead velocity setpoints are checked
+    pub max_speed: f32, // m/s, for filtered velocity setpoints
+    /// Optional world-frame box to stay within, as (min, max).
+    pub bbox: Option<(Vec3, Vec3)>,
+    pub max_iters: usize,
+    pub tolerance: f32, // meters
+}
+
+impl Default for BufferedVoronoi {
+    fn default() -> Self {
+        Self {
+            ellipsoid_radii: Vec3::new(0.12, 0.12, 0.3),
+            horizon: 1.0,
+            max_speed: 0.5,
+            bbox: None,
+            max_iters: 100,
+            tolerance: 1e-3,
+        }
+    }
+}
+
+/// Convex set in the space scaled by the ellipsoid radii.
+enum Constraint {
+    HalfSpace { normal: Vec3, offset: f32 },
+    Box { min: Vec3, max: Vec3 },
+}
+
+impl Constraint {
+    fn project(&self, point: Vec3) -> Vec3 {
+        match *self {
+            Constraint::HalfSpace { normal, offset } => {
+                let excess = normal.dot(point) - offset;
+                if excess > 0.0 {
+                    point - normal * excess
+                } else {
+                    point
+                }
+            }
+            Constraint::Box { min, max } => point.clamp(min, max),
+        }
+    }
+}
+
+impl BufferedVoronoi {
+    pub fn with_radii(mut self, radii: Vec3) -> Self {
+        self.ellipsoid_radii = radii;
+        self
+    }
+
+    pub fn with_bbox(mut self, min: Vec3, max: Vec3) -> Self {
+        self.bbox = Some((min, max));
+        self
+    }
+
+    /// Nearest point to `goal` inside the drone's buffered cell.
+    pub fn project(&self, own: Vec3, peers: &[Vec3], goal: Vec3) -> Vec3 {
+        let scale = self.ellipsoid_radii.recip();
+        let own_scaled = own * scale;
+
+        let mut constraints: Vec<Constraint> = peers
+            .iter()
+            .filter_map(|&peer| {
+                let offset = peer * scale - own_scaled;
+                let distance = offset.length();
+                // Bisecting plane, moved back by the ellipsoid
+                let normal = offset.try_normalize()?;
+                Some(Constraint::HalfSpace {
+                    normal,
+                    offset: normal.dot(own_scaled) + distance / 2.0 - 1.0,
+                })
+            })
+            .collect();
+        if let Some((min, max)) = self.bbox {
+            constraints.push(Constraint::Box {
+                min: min * scale,
+                max: max * scale,
+            });
+        }
+
+        // Dykstra's alternating projection onto the intersection
+        let mut point = goal * scale;
+        let mut increments = vec![Vec3::ZERO; constraints.len()];
+        let tolerance = self.tolerance * scale.min_element();
+        for _ in 0..self.max_iters {
+            let previous = point;
+            for (constraint, increment) in constraints.iter().zip(increments.iter_mut()) {
+                let shifted = point + *increment;
+                point = constraint.project(shifted);
+                *increment = shifted - point;
+            }
+            if previous.distance(point) < tolerance {
+                break;
+            }
+        }
+        point / scale
+    }
+
+    /// Velocity that stays inside the cell over the horizon.
+    fn limit_velocity(&self, own: Vec3, peers: &[Vec3], velocity: Vec3) -> Vec3 {
+        let target = self.project(own, peers, own + velocity * self.horizon);
+        ((target - own) / self.horizon).clamp_length_max(self.max_speed)
+    }
+
+    /// Keeps position, velocity, hover and full-state setpoints inside the
+    /// cell of the drone in state `own`. Other commands pass through
+    /// unchanged.
+    pub fn filter(&self, own: &DroneState, peers: &[Vec3], cmd: DroneCommand) -> DroneCommand {
+        let position = own.position();
+        match cmd {
+            DroneCommand::Position(PositionCommand { x, y, z, yaw }) => {
+                let target = self.project(position, peers, Vec3::new(x, y, z));
+                DroneCommand::Position(PositionCommand {
+                    x: target.x,
+                    y: target.y,
+                    z: target.z,
+                    yaw,
+                })
+            }
+            DroneCommand::Velocity(VelocityCommand {
+                vx,
+                vy,
+                vz,
+                yaw_rate,
+            }) => {
+                let velocity = self.limit_velocity(position, peers, Vec3::new(vx, vy, vz));
+                DroneCommand::Velocity(VelocityCommand {
+                    vx: velocity.x,
+                    vy: velocity.y,
+                    vz: velocity.z,
+                    yaw_rate,
+                })
+            }
+            DroneCommand::Hover(cmd) => {
+                // Body frame, and the height is held
+                let heading = Quat::from_rotation_z(own.yaw.to_radians());
+                let velocity = heading * Vec3::new(cmd.vx, cmd.vy, 0.0);
+                let velocity = self.limit_velocity(position, peers, velocity);
+                let body = heading.inverse() * velocity.truncate().extend(0.0);
+                DroneCommand::Hover(HoverCommand {
+                    vx: body.x,
+                    vy: body.y,
+                    ..cmd
+                })
+            }
+            DroneCommand::FullState(cmd) => DroneCommand::FullState(FullStateCommand {
+                position: self.project(position, peers, cmd.position),
+                velocity: self.limit_velocity(position, peers, cmd.velocity),
+                ..cmd
+            }),
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Distance in ellipsoids, where 2 means the ellipsoids just touch.
+    fn scaled_distance(avoidance: &BufferedVoronoi, a: Vec3, b: Vec3) -> f32 {
+        ((a - b) / avoidance.ellipsoid_radii).length()
+    }
+
+    fn at(position: Vec3) -> DroneState {
+        DroneState {
+            x: position.x,
+            y: position.y,
+            z: position.z,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn head_on_drones_stay_apart() {
+        let avoidance = BufferedVoronoi::default();
+        // Exactly head on they stop at the buffer, slightly off they slide past
+        for offset in [0.0, 0.02] {
+            let start = [Vec3::new(-1.0, 0.0, 1.0), Vec3::new(1.0, offset, 1.0)];
+            let mut positions = start;
+            for _ in 0..50 {
+                // Both aim for the other's start and jump to their filtered
+                // target, staying in their convex cells on the way
+                positions = [
+                    avoidance.project(positions[0], &[positions[1]], start[1]),
+                    avoidance.project(positions[1], &[positions[0]], start[0]),
+                ];
+                assert!(scaled_distance(&avoidance, positions[0], positions[1]) >= 2.0 - 1e-2);
+            }
+            if offset > 0.0 {
+                assert!(positions[0].distance(start[1]) < 1e-2);
+                assert!(positions[1].distance(start[0]) < 1e-2);
+            } else {
+                assert!(scaled_distance(&avoidance, positions[0], positions[1]) < 2.0 + 1e-2);
+            }
+        }
+    }
+
+    #[test]
+    fn goal_inside_the_cell_is_kept() {
+        let avoidance = BufferedVoronoi::default();
+        let goal = Vec3::new(0.2, 0.0, 1.0);
+        let target = avoidance.project(Vec3::new(0.0, 0.0, 1.0), &[Vec3::new(2.0, 0.0, 1.0)], goal);
+        assert!(target.distance(goal) < 1e-3);
+    }
+
+    #[test]
+    fn bbox_is_respected() {
+        let avoidance = BufferedVoronoi::default().with_bbox(Vec3::ZERO, Vec3::splat(2.0));
+        let own = Vec3::new(1.0, 1.0, 1.0);
+        let target = avoidance.project(own, &[Vec3::new(1.0, 0.5, 1.0)], Vec3::new(3.0, -1.0, 2.5));
+        assert!(
+            target.cmpge(Vec3::splat(-1e-3)).all() && target.cmple(Vec3::splat(2.0 + 1e-3)).all()
+        );
+        assert!(scaled_distance(&avoidance, target, Vec3::new(1.0, 0.5, 1.0)) >= 1.0 - 1e-2);
+    }
+
+    #[test]
+    fn velocity_and_hover_slowed_towards_peers() {
+        let avoidance = BufferedVoronoi::default();
+        let own = at(Vec3::new(0.0, 0.0, 1.0));
+        let peers = [Vec3::new(0.3, 0.0, 1.0)];
+
+        let cmd = DroneCommand::Velocity(VelocityCommand {
+            vx: 0.5,
+            vy: 0.0,
+            vz: 0.0,
+            yaw_rate: 0.0,
+        });
+        let DroneCommand::Velocity(velocity) = avoidance.filter(&own, &peers, cmd) else {
+            panic!("expected a velocity command");
+        };
+        assert!(velocity.vx < 0.05);
+
+        // Facing +x at yaw 0, facing -y at -90 with the peer on the left
+        for (yaw, peer) in [
+            (0.0, Vec3::new(0.3, 0.0, 1.0)),
+            (-90.0, Vec3::new(0.0, -0.3, 1.0)),
+        ] {
+            let own = DroneState { yaw, ..own };
+            let cmd = DroneCommand::Hover(HoverCommand {
+                vx: 0.5,
+                vy: 0.2,
+                yaw_rate: 0.0,
+                z_distance: 1.0,
+            });
+            let DroneCommand::Hover(hover) = avoidance.filter(&own, &[peer], cmd) else {
+                panic!("expected a hover command");
+            };
+            assert!(hover.vx < 0.05, "yaw {}: vx {}", yaw, hover.vx);
+            assert!(hover.vy > 0.1, "yaw {}: vy {}", yaw, hover.vy);
+        }
+    }
+
+    #[test]
+    fn full_state_kept_in_the_cell() {
+        let avoidance = BufferedVoronoi::default();
+        let own = at(Vec3::new(0.0, 0.0, 1.0));
+        let peer = Vec3::new(0.3, 0.0, 1.0);
+        let cmd = DroneCommand::FullState(FullStateCommand {
+            position: peer,
+            velocity: Vec3::new(0.5, 0.0, 0.0),
+            acceleration: Vec3::ZERO,
+            attitude: Quat::IDENTITY,
+            rates: Vec3::ZERO,
+        });
+        let DroneCommand::FullState(cmd) = avoidance.filter(&own, &[peer], cmd) else {
+            panic!("expected a full-state command");
+        };
+        assert!(scaled_distance(&avoidance, cmd.position, peer) >= 1.0 - 1e-2);
+        assert!(cmd.velocity.x < 0.05);
+    }
+}
@@ -0,0 +1,291 @@
+//! Formation shapes and leader-follower tracking. Both produce one position
+//! setpoint per drone, to be streamed through a [`Swarm`](super::Swarm).
+
+use glam::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    control::pid::wrap_angle,
+    types::{DroneState, PositionCommand},
+};
+
+/// Slots in the formation frame, centered on the formation's origin. The
+/// frame is rotated by the formation's yaw, and the slots are level with its
+/// center.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Formation {
+    /// Along the formation's x axis.
+    Line {
+        spacing: f32,
+    },
+    Circle {
+        radius: f32,
+    },
+    /// Filled row by row along x, `columns` wide.
+    Grid {
+        columns: usize,
+        spacing: f32,
+    },
+    /// One offset per drone, extra drones stack on the origin.
+    Custom(Vec<Vec3>),
+}
+
+impl Formation {
+    pub fn offsets(&self, count: usize) -> Vec<Vec3> {
+        match self {
+            Formation::Line { spacing } => {
+                let start = -(count.saturating_sub(1) as f32) * spacing / 2.0;
+                (0..count)
+                    .map(|i| Vec3::new(start + i as f32 * spacing, 0.0, 0.0))
+                    .collect()
+            }
+            Formation::Circle { radius } => (0..count)
+                .map(|i| {
+                    let angle = std::f32::consts::TAU * i as f32 / count as f32;
+                    Vec3::new(angle.cos(), angle.sin(), 0.0) * *radius
+                })
+                .collect(),
+            Formation::Grid { columns, spacing } => {
+                let columns = (*columns).max(1);
+                let rows = count.div_ceil(columns);
+                let width = (columns.min(count).saturating_sub(1)) as f32 * spacing;
+                let depth = rows.saturating_sub(1) as f32 * spacing;
+                (0..count)
+                    .map(|i| {
+                        let (row, column) = (i / columns, i % columns);
+                        Vec3::new(
+                            column as f32 * spacing - width / 2.0,
+                            row as f32 * spacing - depth / 2.0,
+                            0.0,
+                        )
+                    })
+                    .collect()
+            }
+            Formation::Custom(offsets) => (0..count)
+                .map(|i| offsets.get(i).copied().unwrap_or(Vec3::ZERO))
+                .collect(),
+        }
+    }
+
+    /// World-frame slot positions for a formation centered at `center` and
+    /// rotated by `yaw` degrees.
+    pub fn positions(&self, count: usize, center: Vec3, yaw: f32) -> Vec<Vec3> {
+        let rotation = Quat::from_rotation_z(yaw.to_radians());
+        self.offsets(count)
+            .into_iter()
+            .map(|offset| center + rotation * offset)
+            .collect()
+    }
+}
+
+/// Moves a set of drones between formations. Each drone follows a straight
+/// line to its new slot with a minimum-jerk time profile, so all of them
+/// start and arrive together at rest.
+#[derive(Debug, Clone)]
+pub struct FormationControl {
+    from: Vec<Vec3>,
+    to: Vec<Vec3>,
+    from_yaw: f32, // degrees
+    to_yaw: f32,   // degrees
+    duration: f32, // seconds
+    elapsed: f32,  // seconds
+}
+
+impl FormationControl {
+    /// Holds the drones where they are, e.g. from [`Swarm::positions`](super::Swarm::positions),
+    /// facing `yaw` degrees.
+    pub fn new(positions: Vec<Vec3>, yaw: f32) -> Self {
+        Self {
+            from: positions.clone(),
+            to: positions,
+            from_yaw: yaw,
+            to_yaw: yaw,
+            duration: 0.0,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.to.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to.is_empty()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+        let t = (self.elapsed / self.duration).clamp(0.0, 1.0);
+        t * t * t * (10.0 - 15.0 * t + 6.0 * t * t)
+    }
+
+    /// Current targets.
+    pub fn positions(&self) -> Vec<Vec3> {
+        let s = self.progress();
+        self.from
+            .iter()
+            .zip(&self.to)
+            .map(|(from, to)| from.lerp(*to, s))
+            .collect()
+    }
+
+    /// Current heading in degrees, turning the short way.
+    pub fn yaw(&self) -> f32 {
+        let turn = wrap_degrees(self.to_yaw - self.from_yaw);
+        wrap_degrees(self.from_yaw + turn * self.progress())
+    }
+
+    /// Starts moving from the current targets into `formation` over
+    /// `duration` seconds. Slots are handed out nearest first so drones take
+    /// short, mostly non-crossing paths.
+    pub fn transition(&mut self, formation: &Formation, center: Vec3, yaw: f32, duration: f32) {
+        let current = self.positions();
+        let mut slots: Vec<Option<Vec3>> = formation
+            .positions(current.len(), center, yaw)
+            .into_iter()
+            .map(Some)
+            .collect();
+
+        let mut to = current.clone();
+        let mut unassigned: Vec<usize> = (0..current.len()).collect();
+        while !unassigned.is_empty() {
+            let mut best = (0, 0, f32::MAX);
+            for (k, &drone) in unassigned.iter().enumerate() {
+                for (slot, position) in slots.iter().enumerate() {
+                    if let Some(position) = position {
+                        let distance = current[drone].distance_squared(*position);
+                        if distance < best.2 {
+                            best = (k, slot, distance);
+                        }
+                    }
+                }
+            }
+            let drone = unassigned.swap_remove(best.0);
+            to[drone] = slots[best.1].take().unwrap_or(current[drone]);
+        }
+
+        self.from_yaw = self.yaw();
+        self.to_yaw = yaw;
+        self.from = current;
+        self.to = to;
+        self.duration = duration.max(0.0);
+        self.elapsed = 0.0;
+    }
+
+    /// Advances by `dt` seconds and returns one setpoint per drone.
+    pub fn update(&mut self, dt: f32) -> Vec<PositionCommand> {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+        let yaw = self.yaw();
+        self.positions()
+            .into_iter()
+            .map(|position| PositionCommand {
+                x: position.x,
+                y: position.y,
+                z: position.z,
+                yaw,
+            })
+            .collect()
+    }
+}
+
+fn wrap_degrees(angle: f32) -> f32 {
+    wrap_angle(angle.to_radians()).to_degrees()
+}
+
+/// Followers hold fixed offsets from a leader, in the leader's heading frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeaderFollower {
+    pub leader: usize,
+    /// Offset for each drone by index, the leader's own entry is ignored.
+    pub offsets: Vec<Vec3>,
+    /// Turn the offsets with the leader's yaw, otherwise keep them in the
+    /// world frame.
+    pub rotate_with_leader: bool,
+}
+
+impl LeaderFollower {
+    pub fn new(leader: usize, offsets: Vec<Vec3>) -> Self {
+        Self {
+            leader,
+            offsets,
+            rotate_with_leader: true,
+        }
+    }
+
+    /// Followers in `formation` behind the leader, e.g. a line or a grid.
+    pub fn from_formation(leader: usize, formation: &Formation, count: usize) -> Self {
+        let offsets = formation.offsets(count);
+        let origin = offsets.get(leader).copied().unwrap_or(Vec3::ZERO);
+        Self::new(
+            leader,
+            offsets.iter().map(|offset| *offset - origin).collect(),
+        )
+    }
+
+    /// Setpoints for every follower as `(index, setpoint)`, given the states
+    /// of all drones.
+    pub fn setpoints(&self, states: &[DroneState]) -> Vec<(usize, PositionCommand)> {
+        let Some(leader) = states.get(self.leader) else {
+            return Vec::new();
+        };
+        let rotation = if self.rotate_with_leader {
+            Quat::from_rotation_z(leader.yaw.to_radians())
+        } else {
+            Quat::IDENTITY
+        };
+        let position = leader.position();
+
+        (0..states.len())
+            .filter(|&i| i != self.leader)
+            .map(|i| {
+                let offset = self.offsets.get(i).copied().unwrap_or(Vec3::ZERO);
+                let target = position + rotation * offset;
+                let command = PositionCommand {
+                    x: target.x,
+                    y: target.y,
+                    z: target.z,
+                    yaw: leader.yaw,
+                };
+                (i, command)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_the_starting_heading() {
+        let mut control = FormationControl::new(vec![Vec3::ZERO, Vec3::X], 120.0);
+        for cmd in control.update(0.1) {
+            assert!((cmd.yaw - 120.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn turns_the_short_way() {
+        let mut control = FormationControl::new(vec![Vec3::ZERO], 350.0);
+        control.transition(&Formation::Line { spacing: 1.0 }, Vec3::ZERO, 10.0, 2.0);
+
+        let yaws: Vec<f32> = (0..20).map(|_| control.update(0.1)[0].yaw).collect();
+        // Through 0, never round by 180
+        assert!(yaws.iter().all(|yaw| yaw.abs() <= 10.0 + 1e-3));
+        assert!((control.yaw() - 10.0).abs() < 1e-3);
+
+        // From 10 to -150 turns right through 0, not left through 180
+        control.transition(&Formation::Line { spacing: 1.0 }, Vec3::ZERO, -150.0, 2.0);
+        let yaws: Vec<f32> = (0..20).map(|_| control.update(0.1)[0].yaw).collect();
+        assert!(yaws
+            .iter()
+            .all(|yaw| (-150.0 - 1e-3..=10.0 + 1e-3).contains(yaw)));
+        assert!((control.yaw() + 150.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,255 @@
+//! Several drones flown together, in one sim world or over several radios.
+
+pub mod collision;
+pub mod formation;
+
+pub use collision::BufferedVoronoi;
+pub use formation::{Formation, FormationControl, LeaderFollower};
+
+use glam::Vec3;
+use tokio::sync::watch;
+
+use crate::{
+    trajectory::Trajectory,
+    types::{DroneCommand, DroneError, DroneInterface, DroneState},
+};
+
+/// A group of drones. With collision avoidance on, every position, velocity,
+/// hover and full-state setpoint is filtered against the positions of the
+/// other drones before it's sent.
+pub struct Swarm<D: DroneInterface> {
+    drones: Vec<D>,
+    pub collision_avoidance: Option<BufferedVoronoi>,
+}
+
+impl<D: DroneInterface> Swarm<D> {
+    pub fn new(drones: Vec<D>) -> Self {
+        Self {
+            drones,
+            collision_avoidance: None,
+        }
+    }
+
+    pub fn with_collision_avoidance(mut self, avoidance: BufferedVoronoi) -> Self {
+        self.collision_avoidance = Some(avoidance);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.drones.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.drones.is_empty()
+    }
+
+    pub fn drone(&self, index: usize) -> Option<&D> {
+        self.drones.get(index)
+    }
+
+    pub fn drone_mut(&mut self, index: usize) -> Option<&mut D> {
+        self.drones.get_mut(index)
+    }
+
+    pub fn into_inner(self) -> Vec<D> {
+        self.drones
+    }
+
+    pub async fn init(&mut self) -> Result<(), DroneError> {
+        for drone in &mut self.drones {
+            drone.init().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn states(&self) -> Result<Vec<DroneState>, DroneError> {
+        let mut states = Vec::with_capacity(self.drones.len());
+        for drone in &self.drones {
+            states.push(drone.get_state().await?);
+        }
+        Ok(states)
+    }
+
+    pub fn subscribe_states(&self) -> Vec<watch::Receiver<DroneState>> {
+        self.drones.iter().map(D::subscribe_state).collect()
+    }
+
+    /// Current positions, world frame.
+    pub async fn positions(&self) -> Result<Vec<Vec3>, DroneError> {
+        Ok(self
+            .states()
+            .await?
+            .iter()
+            .map(DroneState::position)
+            .collect())
+    }
+
+    /// Runs `cmd` through collision avoidance for drone `index`.
+    fn filter(&self, index: usize, states: &[DroneState], cmd: DroneCommand) -> DroneCommand {
+        match &self.collision_avoidance {
+            Some(avoidance) => {
+                let peers: Vec<Vec3> = states
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| i != index)
+                    .map(|(_, state)| state.position())
+                    .collect();
+                avoidance.filter(&states[index], &peers, cmd)
+            }
+            None => cmd,
+        }
+    }
+
+    pub async fn send_command(
+        &mut self,
+        index: usize,
+        cmd: DroneCommand,
+    ) -> Result<(), DroneError> {
+        if index >= self.drones.len() {
+            return Err(DroneError::InvalidArgument(format!(
+                "no drone {} in swarm of {}",
+                index,
+                self.drones.len()
+            )));
+        }
+        let cmd = if self.collision_avoidance.is_some() {
+            let states = self.states().await?;
+            self.filter(index, &states, cmd)
+        } else {
+            cmd
+        };
+        self.drones[index].send_command(cmd).await
+    }
+
+    /// Sends one command per drone, in order, all filtered against the same
+    /// snapshot of states.
+    pub async fn send_commands(&mut self, cmds: Vec<DroneCommand>) -> Result<(), DroneError> {
+        if cmds.len() != self.drones.len() {
+            return Err(DroneError::InvalidArgument(format!(
+                "{} commands for a swarm of {}",
+                cmds.len(),
+                self.drones.len()
+            )));
+        }
+        let states = match self.collision_avoidance {
+            Some(_) => self.states().await?,
+            None => Vec::new(),
+        };
+        for (index, cmd) in cmds.into_iter().enumerate() {
+            let cmd = self.filter(index, &states, cmd);
+            self.drones[index].send_command(cmd).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends the same command to every drone.
+    pub async fn broadcast(&mut self, cmd: DroneCommand) -> Result<(), DroneError> {
+        self.send_commands(vec![cmd; self.drones.len()]).await
+    }
+
+    pub async fn upload_trajectory(
+        &mut self,
+        id: u8,
+        trajectory: &Trajectory,
+    ) -> Result<(), DroneError> {
+        for drone in &mut self.drones {
+            drone.upload_trajectory(id, trajectory).await?;
+        }
+        Ok(())
+    }
+
+    /// Steps `formation` by `dt` seconds and sends every drone its slot.
+    /// Call at the setpoint rate until [`FormationControl::is_done`], and
+    /// keep calling to hold the formation.
+    pub async fn fly_formation(
+        &mut self,
+        formation: &mut FormationControl,
+        dt: f32,
+    ) -> Result<(), DroneError> {
+        let cmds = formation
+            .update(dt)
+            .into_iter()
+            .map(DroneCommand::Position)
+            .collect();
+        self.send_commands(cmds).await
+    }
+
+    /// Sends the followers their setpoints around the leader's current
+    /// position. The leader itself is left to the caller.
+    pub async fn follow_leader(&mut self, follow: &LeaderFollower) -> Result<(), DroneError> {
+        let states = self.states().await?;
+        for (index, setpoint) in follow.setpoints(&states) {
+            let cmd = self.filter(index, &states, DroneCommand::Position(setpoint));
+            self.drones[index].send_command(cmd).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        drivers::mock::MockDriver,
+        types::{PositionCommand, RpytCommand},
+    };
+
+    fn drone_at(x: f32) -> MockDriver {
+        MockDriver::with_state(DroneState {
+            x,
+            z: 1.0,
+            ..Default::default()
+        })
+    }
+
+    fn position(x: f32) -> DroneCommand {
+        DroneCommand::Position(PositionCommand {
+            x,
+            y: 0.0,
+            z: 1.0,
+            yaw: 0.0,
+        })
+    }
+
+    fn last_x(swarm: &Swarm<MockDriver>, index: usize) -> f32 {
+        match swarm.drone(index).unwrap().sent.last() {
+            Some(DroneCommand::Position(cmd)) => cmd.x,
+            other => panic!("expected a position command, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_setpoints_against_the_other_drones() {
+        let avoidance = BufferedVoronoi::default();
+        let mut swarm =
+            Swarm::new(vec![drone_at(0.0), drone_at(1.0)]).with_collision_avoidance(avoidance);
+
+        swarm
+            .send_commands(vec![position(1.0), position(0.0)])
+            .await
+            .unwrap();
+        let gap = last_x(&swarm, 1) - last_x(&swarm, 0);
+        assert!(gap >= 2.0 * avoidance.ellipsoid_radii.x - 1e-3);
+
+        // Commands without a target go through
+        let rpyt = DroneCommand::Rpyt(RpytCommand {
+            roll: 0.0,
+            pitch: 0.0,
+            yaw: 0.0,
+            thrust: 0,
+        });
+        swarm.send_command(0, rpyt.clone()).await.unwrap();
+        assert!(matches!(
+            swarm.drone(0).unwrap().sent.last(),
+            Some(DroneCommand::Rpyt(_))
+        ));
+        assert!(swarm.send_command(2, rpyt).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn passes_setpoints_without_avoidance() {
+        let mut swarm = Swarm::new(vec![drone_at(0.0), drone_at(1.0)]);
+        swarm.send_command(0, position(1.0)).await.unwrap();
+        assert_eq!(last_x(&swarm, 0), 1.0);
+    }
+}
@@ -0,0 +1,403 @@
+//! Minimum-snap trajectory generation through waypoints.
+//!
+//! Each axis is solved independently as a piecewise 7th-order polynomial. With
+//! free interior waypoints the snap-optimal solution is continuous up to the
+//! 6th derivative, so the coefficients follow from a single square linear
+//! system rather than a general QP. Fixing a velocity or acceleration at an
+//! interior waypoint trades it for the 6th or 5th derivative continuity.
+
+use anyhow::{bail, Result};
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use super::{Poly, Poly4d, Trajectory, POLY_COEFFS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Waypoint {
+    pub position: Vec3,             // meters
+    pub yaw: f32,                   // radians
+    pub time: Option<f32>,          // seconds from the start of the trajectory
+    pub velocity: Option<Vec3>,     // m/s, free if unset (zero at the ends)
+    pub acceleration: Option<Vec3>, // m/s², free if unset (zero at the ends)
+}
+
+impl Waypoint {
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            position,
+            ..Default::default()
+        }
+    }
+
+    pub fn at(mut self, time: f32) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    pub fn with_yaw(mut self, yaw: f32) -> Self {
+        self.yaw = yaw;
+        self
+    }
+
+    pub fn with_velocity(mut self, velocity: Vec3) -> Self {
+        self.velocity = Some(velocity);
+        self
+    }
+
+    pub fn with_acceleration(mut self, acceleration: Vec3) -> Self {
+        self.acceleration = Some(acceleration);
+        self
+    }
+}
+
+/// Limits used to pick segment durations when waypoints are untimed, and to
+/// stretch the result if it still exceeds them. Timed waypoints are kept as
+/// given, with a warning if the result exceeds the limits.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Limits {
+    pub max_velocity: f32,     // m/s
+    pub max_acceleration: f32, // m/s²
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_velocity: 1.0,
+            max_acceleration: 2.0,
+        }
+    }
+}
+
+/// Plans a minimum-snap trajectory through `waypoints`. Either every waypoint
+/// has a time, used as given with the first at 0, or none has and segment
+/// durations are allocated from `limits`.
+pub fn generate(waypoints: &[Waypoint], limits: &Limits) -> Result<Trajectory> {
+    if waypoints.len() < 2 {
+        bail!("need at least two waypoints");
+    }
+    if limits.max_velocity <= 0.0 || limits.max_acceleration <= 0.0 {
+        bail!("limits must be positive");
+    }
+
+    let timed = waypoints.iter().all(|wp| wp.time.is_some());
+    if !timed && waypoints.iter().any(|wp| wp.time.is_some()) {
+        bail!("either every waypoint needs a time or none");
+    }
+    if timed && waypoints[0].time != Some(0.0) {
+        bail!("the first waypoint must be at time 0");
+    }
+    let mut durations: Vec<f64> = if timed {
+        waypoints
+            .windows(2)
+            .map(|pair| (pair[1].time.unwrap() - pair[0].time.unwrap()) as f64)
+            .collect()
+    } else {
+        waypoints
+            .windows(2)
+            .map(|pair| allocate_time(pair[0].position.distance(pair[1].position), limits))
+            .collect()
+    };
+    if durations.iter().any(|t| !t.is_finite() || *t <= 0.0) {
+        bail!("waypoint times must be strictly increasing");
+    }
+
+    let mut trajectory = solve(waypoints, &durations)?;
+    if timed {
+        let (velocity, acceleration) = peaks(&trajectory);
+        if velocity > limits.max_velocity || acceleration > limits.max_acceleration {
+            tracing::warn!(
+                "timed waypoints reach {:.2} m/s and {:.2} m/s², beyond the limits",
+                velocity,
+                acceleration
+            );
+        }
+        return Ok(trajectory);
+    }
+
+    // Uniformly stretching time scales velocity by 1/k and acceleration by
+    // 1/k², so a few passes settle any fixed-derivative constraints too.
+    for _ in 0..4 {
+        let (velocity, acceleration) = peaks(&trajectory);
+        let scale =
+            (velocity / limits.max_velocity).max((acceleration / limits.max_acceleration).sqrt());
+        if scale <= 1.0 + 1e-3 {
+            break;
+        }
+        durations.iter_mut().for_each(|t| *t *= scale as f64);
+        trajectory = solve(waypoints, &durations)?;
+    }
+    Ok(trajectory)
+}
+
+/// Rest-to-rest time for a trapezoidal velocity profile over `distance`.
+fn allocate_time(distance: f32, limits: &Limits) -> f64 {
+    let (v, a) = (limits.max_velocity, limits.max_acceleration);
+    let time = if distance > v * v / a {
+        distance / v + v / a
+    } else {
+        2.0 * (distance / a).sqrt()
+    };
+    time.max(0.5) as f64
+}
+
+fn solve(waypoints: &[Waypoint], durations: &[f64]) -> Result<Trajectory> {
+    // Yaw is unwrapped so each segment turns the short way
+    let mut yaws = vec![waypoints[0].yaw];
+    for pair in waypoints.windows(2) {
+        let last = *yaws.last().unwrap();
+        yaws.push(last + crate::control::pid::wrap_angle(pair[1].yaw - pair[0].yaw));
+    }
+
+    let mut axes = Vec::with_capacity(4);
+    for axis in 0..4 {
+        let constraints: Vec<AxisWaypoint> = waypoints
+            .iter()
+            .zip(&yaws)
+            .map(|(wp, &yaw)| match axis {
+                3 => AxisWaypoint {
+                    position: yaw as f64,
+                    velocity: None,
+                    acceleration: None,
+                },
+                _ => AxisWaypoint {
+                    position: wp.position[axis] as f64,
+                    velocity: wp.velocity.map(|v| v[axis] as f64),
+                    acceleration: wp.acceleration.map(|a| a[axis] as f64),
+                },
+            })
+            .collect();
+        axes.push(solve_axis(&constraints, durations)?);
+    }
+
+    let pieces = durations
+        .iter()
+        .enumerate()
+        .map(|(i, &duration)| Poly4d {
+            duration: duration as f32,
+            x: axes[0][i],
+            y: axes[1][i],
+            z: axes[2][i],
+            yaw: axes[3][i],
+        })
+        .collect();
+    Ok(Trajectory { pieces })
+}
+
+struct AxisWaypoint {
+    position: f64,
+    velocity: Option<f64>,
+    acceleration: Option<f64>,
+}
+
+fn solve_axis(waypoints: &[AxisWaypoint], durations: &[f64]) -> Result<Vec<Poly>> {
+    let segments = durations.len();
+    let size = POLY_COEFFS * segments;
+    let mut a = vec![vec![0.0; size]; size];
+    let mut b = vec![0.0; size];
+    let mut row = 0;
+
+    // Each term is (segment, derivative, time, weight)
+    let mut constrain = |terms: &[(usize, usize, f64, f64)], value: f64| {
+        for &(segment, derivative, t, weight) in terms {
+            for (j, coeff) in basis(derivative, t).iter().enumerate() {
+                a[row][segment * POLY_COEFFS + j] += weight * coeff;
+            }
+        }
+        b[row] = value;
+        row += 1;
+    };
+
+    let first = &waypoints[0];
+    let last = &waypoints[segments];
+    let end = durations[segments - 1];
+    constrain(&[(0, 1, 0.0, 1.0)], first.velocity.unwrap_or(0.0));
+    constrain(&[(0, 2, 0.0, 1.0)], first.acceleration.unwrap_or(0.0));
+    constrain(&[(0, 3, 0.0, 1.0)], 0.0);
+    constrain(&[(segments - 1, 1, end, 1.0)], last.velocity.unwrap_or(0.0));
+    constrain(
+        &[(segments - 1, 2, end, 1.0)],
+        last.acceleration.unwrap_or(0.0),
+    );
+    constrain(&[(segments - 1, 3, end, 1.0)], 0.0);
+
+    for segment in 0..segments {
+        let t = durations[segment];
+        constrain(&[(segment, 0, 0.0, 1.0)], waypoints[segment].position);
+        constrain(&[(segment, 0, t, 1.0)], waypoints[segment + 1].position);
+
+        if segment + 1 == segments {
+            continue;
+        }
+        let joint = &waypoints[segment + 1];
+        for derivative in 1..=6 {
+            match (derivative, joint.velocity, joint.acceleration) {
+                (6, Some(velocity), _) => constrain(&[(segment, 1, t, 1.0)], velocity),
+                (5, _, Some(acceleration)) => constrain(&[(segment, 2, t, 1.0)], acceleration),
+                // End of this segment matches the start of the next
+                _ => constrain(
+                    &[
+                        (segment, derivative, t, 1.0),
+                        (segment + 1, derivative, 0.0, -1.0),
+                    ],
+                    0.0,
+                ),
+            }
+        }
+    }
+
+    let coeffs = solve_linear(a, b).ok_or_else(|| anyhow::anyhow!("singular waypoint system"))?;
+    Ok(coeffs
+        .chunks(POLY_COEFFS)
+        .map(|chunk| {
+            let mut poly = [0.0; POLY_COEFFS];
+            for (out, c) in poly.iter_mut().zip(chunk) {
+                *out = *c as f32;
+            }
+            Poly(poly)
+        })
+        .collect())
+}
+
+/// Row of the `derivative`-th derivative of each monomial, evaluated at `t`.
+fn basis(derivative: usize, t: f64) -> [f64; POLY_COEFFS] {
+    let mut row = [0.0; POLY_COEFFS];
+    for (j, value) in row.iter_mut().enumerate().skip(derivative) {
+        let factor: f64 = ((j - derivative + 1)..=j).map(|k| k as f64).product();
+        *value = factor * t.powi((j - derivative) as i32);
+    }
+    row
+}
+
+/// Gaussian elimination with partial pivoting.
+fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let (pivot_rows, rows) = a.split_at_mut(col + 1);
+        let pivot_row = &pivot_rows[col];
+        for (offset, target) in rows.iter_mut().enumerate() {
+            let factor = target[col] / pivot_row[col];
+            if factor == 0.0 {
+                continue;
+            }
+            for (value, pivot) in target[col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= factor * pivot;
+            }
+            b[col + 1 + offset] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Largest speed and acceleration magnitude, sampled along the trajectory.
+fn peaks(trajectory: &Trajectory) -> (f32, f32) {
+    const SAMPLES: usize = 20;
+    let mut velocity: f32 = 0.0;
+    let mut acceleration: f32 = 0.0;
+    for piece in &trajectory.pieces {
+        for i in 0..=SAMPLES {
+            let point = piece.eval(piece.duration * i as f32 / SAMPLES as f32);
+            velocity = velocity.max(point.velocity.length());
+            acceleration = acceleration.max(point.acceleration.length());
+        }
+    }
+    (velocity, acceleration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waypoints() -> Vec<Waypoint> {
+        vec![
+            Waypoint::new(Vec3::ZERO),
+            Waypoint::new(Vec3::new(1.0, 0.5, 1.0)).with_yaw(1.0),
+            Waypoint::new(Vec3::new(2.0, -0.5, 1.5)),
+            Waypoint::new(Vec3::new(2.5, 0.0, 1.0)).with_yaw(-0.5),
+        ]
+    }
+
+    #[test]
+    fn passes_through_waypoints() {
+        let waypoints = waypoints();
+        let trajectory = generate(&waypoints, &Limits::default()).unwrap();
+        assert_eq!(trajectory.pieces.len(), waypoints.len() - 1);
+
+        let mut t = 0.0;
+        for (piece, wp) in trajectory.pieces.iter().zip(&waypoints) {
+            assert!(piece.eval(0.0).position.distance(wp.position) < 1e-3);
+            t += piece.duration;
+        }
+        let end = trajectory.eval(t);
+        assert!(end.position.distance(waypoints[3].position) < 1e-3);
+        assert!(end.velocity.length() < 1e-3);
+    }
+
+    #[test]
+    fn continuous_between_pieces() {
+        let trajectory = generate(&waypoints(), &Limits::default()).unwrap();
+        for pair in trajectory.pieces.windows(2) {
+            let end = pair[0].eval(pair[0].duration);
+            let start = pair[1].eval(0.0);
+            assert!(end.position.distance(start.position) < 1e-3);
+            assert!(end.velocity.distance(start.velocity) < 1e-3);
+            assert!(end.acceleration.distance(start.acceleration) < 1e-2);
+            assert!((end.yaw - start.yaw).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn respects_limits() {
+        let limits = Limits::default();
+        let trajectory = generate(&waypoints(), &limits).unwrap();
+        let (velocity, acceleration) = peaks(&trajectory);
+        assert!(velocity <= limits.max_velocity * 1.01);
+        assert!(acceleration <= limits.max_acceleration * 1.01);
+    }
+
+    #[test]
+    fn keeps_given_times() {
+        let waypoints: Vec<_> = waypoints()
+            .into_iter()
+            .zip([0.0, 2.0, 3.0, 5.0])
+            .map(|(wp, time)| wp.at(time))
+            .collect();
+        let trajectory = generate(&waypoints, &Limits::default()).unwrap();
+        let durations: Vec<f32> = trajectory.pieces.iter().map(|p| p.duration).collect();
+        assert_eq!(durations, [2.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn rejects_bad_times() {
+        let limits = Limits::default();
+        let mut waypoints = waypoints();
+        waypoints[1].time = Some(1.0);
+        assert!(generate(&waypoints, &limits).is_err(), "mixed times");
+
+        let late: Vec<_> = waypoints
+            .iter()
+            .enumerate()
+            .map(|(i, wp)| wp.at(1.0 + i as f32))
+            .collect();
+        assert!(generate(&late, &limits).is_err(), "first time isn't 0");
+
+        let backwards: Vec<_> = waypoints
+            .iter()
+            .zip([0.0, 2.0, 1.0, 3.0])
+            .map(|(wp, time)| wp.at(time))
+            .collect();
+        assert!(generate(&backwards, &limits).is_err(), "decreasing times");
+    }
+}
@@ -0,0 +1,297 @@
+//! Polynomial trajectories in the firmware's poly4d layout: eight coefficients
+//! per axis (lowest order first), evaluated from 0 to the piece duration.
+
+pub mod min_snap;
+
+use anyhow::{bail, Context, Result};
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const POLY_COEFFS: usize = 8;
+/// Size of one packed piece in trajectory memory.
+pub const POLY4D_SIZE: usize = (4 * POLY_COEFFS + 1) * 4;
+
+/// Values per CSV row: duration, then eight coefficients each for x, y, z, yaw.
+const CSV_COLUMNS: usize = 1 + 4 * POLY_COEFFS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Poly(pub [f32; POLY_COEFFS]);
+
+impl Poly {
+    pub fn constant(value: f32) -> Self {
+        let mut coeffs = [0.0; POLY_COEFFS];
+        coeffs[0] = value;
+        Self(coeffs)
+    }
+
+    pub fn eval(&self, t: f32) -> f32 {
+        self.0.iter().rev().fold(0.0, |acc, c| acc * t + c)
+    }
+
+    pub fn derivative(&self) -> Self {
+        let mut coeffs = [0.0; POLY_COEFFS];
+        for i in 1..POLY_COEFFS {
+            coeffs[i - 1] = self.0[i] * i as f32;
+        }
+        Self(coeffs)
+    }
+
+    /// 7th-order polynomial from `start` to `end` (position, velocity,
+    /// acceleration) with zero jerk at both ends, like the firmware's
+    /// `piecewise_plan_7th_order_no_jerk`.
+    pub fn plan_7th_order(duration: f32, start: [f32; 3], end: [f32; 3]) -> Self {
+        let [p0, v0, a0] = start;
+        let [p1, v1, a1] = end;
+        let t = duration;
+        let dp = p1 - (p0 + v0 * t + 0.5 * a0 * t * t);
+        let dv = (v1 - (v0 + a0 * t)) * t;
+        let da = (a1 - a0) * t * t;
+
+        let d4 = 35.0 * dp - 15.0 * dv + 2.5 * da;
+        let d5 = -84.0 * dp + 39.0 * dv - 7.0 * da;
+        let d6 = 70.0 * dp - 34.0 * dv + 6.5 * da;
+        let d7 = -20.0 * dp + 10.0 * dv - 2.0 * da;
+
+        Self([
+            p0,
+            v0,
+            0.5 * a0,
+            0.0,
+            d4 / t.powi(4),
+            d5 / t.powi(5),
+            d6 / t.powi(6),
+            d7 / t.powi(7),
+        ])
+    }
+}
+
+/// One piece of a trajectory. Yaw is in radians, as on the Crazyflie.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Poly4d {
+    pub duration: f32, // seconds
+    pub x: Poly,
+    pub y: Poly,
+    pub z: Poly,
+    pub yaw: Poly,
+}
+
+impl Poly4d {
+    /// Smooth move between two points, coming to rest at `end`.
+    pub fn plan(start: &TrajectoryPoint, end: &TrajectoryPoint, duration: f32) -> Self {
+        let axis = |i: usize| {
+            Poly::plan_7th_order(
+                duration,
+                [start.position[i], start.velocity[i], start.acceleration[i]],
+                [end.position[i], end.velocity[i], end.acceleration[i]],
+            )
+        };
+        Self {
+            duration,
+            x: axis(0),
+            y: axis(1),
+            z: axis(2),
+            yaw: Poly::plan_7th_order(duration, [start.yaw, 0.0, 0.0], [end.yaw, 0.0, 0.0]),
+        }
+    }
+
+    /// Evaluates the piece, clamping `t` to its duration.
+    pub fn eval(&self, t: f32) -> TrajectoryPoint {
+        let t = t.clamp(0.0, self.duration);
+        let eval3 =
+            |polys: [&Poly; 3]| Vec3::new(polys[0].eval(t), polys[1].eval(t), polys[2].eval(t));
+        let velocity = [
+            self.x.derivative(),
+            self.y.derivative(),
+            self.z.derivative(),
+        ];
+        let acceleration = velocity.map(|p| p.derivative());
+
+        TrajectoryPoint {
+            position: eval3([&self.x, &self.y, &self.z]),
+            velocity: eval3([&velocity[0], &velocity[1], &velocity[2]]),
+            acceleration: eval3([&acceleration[0], &acceleration[1], &acceleration[2]]),
+            yaw: self.yaw.eval(t),
+        }
+    }
+}
+
+/// Piecewise polynomial trajectory, as uploaded to the Crazyflie.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Trajectory {
+    pub pieces: Vec<Poly4d>,
+}
+
+impl From<Poly4d> for Trajectory {
+    fn from(piece: Poly4d) -> Self {
+        Self {
+            pieces: vec![piece],
+        }
+    }
+}
+
+impl Trajectory {
+    pub fn duration(&self) -> f32 {
+        self.pieces.iter().map(|piece| piece.duration).sum()
+    }
+
+    /// Checks the trajectory can be flown: it has pieces, and each lasts a
+    /// finite, positive time.
+    pub fn validate(&self) -> Result<()> {
+        if self.pieces.is_empty() {
+            bail!("trajectory has no pieces");
+        }
+        for (i, piece) in self.pieces.iter().enumerate() {
+            if !(piece.duration.is_finite() && piece.duration > 0.0) {
+                bail!("piece {} has duration {}", i, piece.duration);
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates the trajectory at `t` seconds from its start, holding the
+    /// first and last points outside of it.
+    pub fn eval(&self, t: f32) -> TrajectoryPoint {
+        let mut t = t.max(0.0);
+        for (i, piece) in self.pieces.iter().enumerate() {
+            if t <= piece.duration || i == self.pieces.len() - 1 {
+                return piece.eval(t);
+            }
+            t -= piece.duration;
+        }
+        TrajectoryPoint::default()
+    }
+
+    /// Parses the `uav_trajectories` CSV format: one piece per row, with the
+    /// duration followed by x^0..x^7, y^0..y^7, z^0..z^7 and yaw^0..yaw^7.
+    /// A header row is optional.
+    pub fn from_csv(csv: &str) -> Result<Self> {
+        let mut pieces = Vec::new();
+
+        for (line_no, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if line_no == 0 && fields[0].parse::<f32>().is_err() {
+                continue; // Header
+            }
+            if fields.len() != CSV_COLUMNS {
+                bail!(
+                    "line {}: expected {} columns, found {}",
+                    line_no + 1,
+                    CSV_COLUMNS,
+                    fields.len()
+                );
+            }
+
+            let mut values = [0.0; CSV_COLUMNS];
+            for (value, field) in values.iter_mut().zip(&fields) {
+                *value = field
+                    .parse()
+                    .with_context(|| format!("line {}: invalid number {:?}", line_no + 1, field))?;
+            }
+
+            let poly = |axis: usize| {
+                let start = 1 + axis * POLY_COEFFS;
+                let mut coeffs = [0.0; POLY_COEFFS];
+                coeffs.copy_from_slice(&values[start..start + POLY_COEFFS]);
+                Poly(coeffs)
+            };
+            pieces.push(Poly4d {
+                duration: values[0],
+                x: poly(0),
+                y: poly(1),
+                z: poly(2),
+                yaw: poly(3),
+            });
+        }
+
+        let trajectory = Self { pieces };
+        trajectory.validate()?;
+        Ok(trajectory)
+    }
+
+    pub fn load_csv(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let csv = std::fs::read_to_string(path)
+            .with_context(|| format!("reading trajectory {}", path.display()))?;
+        Self::from_csv(&csv)
+    }
+
+    /// Packs the pieces as the firmware's `struct poly4d` (x, y, z and yaw
+    /// coefficients then duration, little-endian f32), ready for the
+    /// trajectory memory.
+    pub fn to_poly4d_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pieces.len() * POLY4D_SIZE);
+        for piece in &self.pieces {
+            for poly in [&piece.x, &piece.y, &piece.z, &piece.yaw] {
+                for coeff in poly.0 {
+                    bytes.extend_from_slice(&coeff.to_le_bytes());
+                }
+            }
+            bytes.extend_from_slice(&piece.duration.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// Sampled trajectory state, world frame.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct TrajectoryPoint {
+    pub position: Vec3,     // meters
+    pub velocity: Vec3,     // m/s
+    pub acceleration: Vec3, // m/s²
+    pub yaw: f32,           // radians
+}
+
+impl TrajectoryPoint {
+    pub fn at_rest(position: Vec3, yaw: f32) -> Self {
+        Self {
+            position,
+            yaw,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(duration: &str) -> String {
+        let mut fields = vec![duration.to_string()];
+        fields.extend((1..CSV_COLUMNS).map(|_| "0".to_string()));
+        fields.join(",")
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let csv = format!("duration,x^0\n{}\n{}\n", row("1.5"), row("0.5"));
+        let trajectory = Trajectory::from_csv(&csv).unwrap();
+        assert_eq!(trajectory.pieces.len(), 2);
+        assert_eq!(trajectory.duration(), 2.0);
+    }
+
+    #[test]
+    fn csv_rejects_bad_durations() {
+        for duration in ["-1", "0", "nan", "inf"] {
+            let csv = format!("{}\n{}\n", row("1"), row(duration));
+            assert!(Trajectory::from_csv(&csv).is_err(), "duration {}", duration);
+        }
+        assert!(Trajectory::from_csv("").is_err());
+    }
+
+    #[test]
+    fn validate_rejects_bad_durations() {
+        for duration in [-1.0, f32::NAN] {
+            let trajectory = Trajectory::from(Poly4d {
+                duration,
+                ..Default::default()
+            });
+            assert!(trajectory.validate().is_err());
+        }
+        assert!(Trajectory::default().validate().is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+
+use super::DroneCommand;
+
+/// What a driver, and the drone behind it, can do.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub commands: CommandSupport,
+    pub sensors: Sensors,
+    /// Detected expansion decks, by firmware name, e.g. "bcFlow2".
+    pub decks: Vec<String>,
+    pub firmware: Option<String>,
+    pub trajectory_memory: Option<usize>, // bytes
+    pub max_setpoint_rate: f32,           // Hz, faster setpoints gain nothing
+    pub state_rate: f32,                  // Hz, of subscribed state updates
+}
+
+impl Capabilities {
+    pub fn supports(&self, cmd: &DroneCommand) -> bool {
+        let commands = &self.commands;
+        match cmd {
+            DroneCommand::Rpyt(_) => commands.rpyt,
+            DroneCommand::Position(_) => commands.position,
+            DroneCommand::Velocity(_) => commands.velocity,
+            DroneCommand::ZDistance(_) => commands.z_distance,
+            DroneCommand::Hover(_) => commands.hover,
+            DroneCommand::FullState(_) => commands.full_state,
+            DroneCommand::Stop => commands.stop,
+            DroneCommand::NotifySetpointStop { .. } => commands.notify_setpoint_stop,
+            DroneCommand::HighLevel(_) => commands.high_level,
+            DroneCommand::Assist(_) => commands.assist,
+            DroneCommand::Arm | DroneCommand::Disarm => commands.arm,
+            DroneCommand::EmergencyStop => commands.emergency_stop,
+        }
+    }
+
+    pub fn has_deck(&self, name: &str) -> bool {
+        self.decks.iter().any(|deck| deck == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct CommandSupport {
+    pub rpyt: bool,
+    pub position: bool,
+    pub velocity: bool,
+    pub z_distance: bool,
+    pub hover: bool,
+    pub full_state: bool,
+    pub stop: bool,
+    pub notify_setpoint_stop: bool,
+    pub high_level: bool,
+    pub trajectories: bool, // upload_trajectory
+    pub assist: bool,
+    pub arm: bool, // Arm and Disarm
+    pub emergency_stop: bool,
+}
+
+impl CommandSupport {
+    pub fn all() -> Self {
+        Self {
+            rpyt: true,
+            position: true,
+            velocity: true,
+            z_distance: true,
+            hover: true,
+            full_state: true,
+            stop: true,
+            notify_setpoint_stop: true,
+            high_level: true,
+            trajectories: true,
+            assist: true,
+            arm: true,
+            emergency_stop: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Sensors {
+    pub imu: bool,
+    pub battery: bool,
+    /// Absolute position, e.g. Lighthouse, Loco or motion capture.
+    pub position: bool,
+    pub flow: bool,
+    /// Downward range, `Ranges::down`.
+    pub z_range: bool,
+    /// Horizontal and upward ranges, as on the Multi-ranger.
+    pub multiranger: bool,
+}
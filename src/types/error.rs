@@ -0,0 +1,45 @@
+use std::fmt;
+
+/// Why a [`DroneInterface`](super::DroneInterface) call failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DroneError {
+    /// The radio or transport failed, or the connection was lost.
+    Link(String),
+    /// No answer in time, with what was being waited for.
+    Timeout(String),
+    /// The driver can't carry out this kind of command.
+    UnsupportedCommand(&'static str),
+    InvalidArgument(String),
+    /// Refused to keep the drone safe, e.g. by a geofence.
+    SafetyRejected(String),
+    /// Sent sooner than the rate limit allows, and not forwarded.
+    RateLimited,
+    /// The sim is no longer running.
+    SimShutdown,
+}
+
+impl DroneError {
+    /// True when the drone can't be reached any more, rather than a single
+    /// command failing.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, DroneError::Link(_) | DroneError::SimShutdown)
+    }
+}
+
+impl fmt::Display for DroneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DroneError::Link(reason) => write!(f, "link error: {}", reason),
+            DroneError::Timeout(what) => write!(f, "timed out waiting for {}", what),
+            DroneError::UnsupportedCommand(name) => {
+                write!(f, "{} commands aren't supported by this driver", name)
+            }
+            DroneError::InvalidArgument(reason) => write!(f, "invalid argument: {}", reason),
+            DroneError::SafetyRejected(reason) => write!(f, "rejected for safety: {}", reason),
+            DroneError::RateLimited => write!(f, "sent faster than the rate limit"),
+            DroneError::SimShutdown => write!(f, "the simulation has shut down"),
+        }
+    }
+}
+
+impl std::error::Error for DroneError {}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use super::{ParamType, ParamValue};
+
+/// A variable in the firmware's log TOC. Log values share the parameter types.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogVariable {
+    pub name: String, // "group.name", e.g. "stateEstimate.x"
+    pub kind: ParamType,
+}
+
+/// One reading of every variable in a subscription, in the order they were
+/// subscribed. Each value has its variable's type; drivers drop samples they
+/// can't fully decode rather than fill in values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogSample {
+    pub timestamp: f64, // seconds, drone clock
+    pub values: Vec<ParamValue>,
+}
+
+/// Samples of a log subscription at its period. Dropping the stream ends the
+/// subscription.
+pub struct LogStream {
+    variables: Vec<String>,
+    samples: mpsc::Receiver<LogSample>,
+}
+
+impl LogStream {
+    pub fn new(variables: Vec<String>, samples: mpsc::Receiver<LogSample>) -> Self {
+        Self { variables, samples }
+    }
+
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// Position of `name` in each sample's values.
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.variables.iter().position(|variable| variable == name)
+    }
+
+    /// Waits for the next sample. `None` once the drone stops logging, e.g.
+    /// when the link is lost.
+    pub async fn next(&mut self) -> Option<LogSample> {
+        self.samples.recv().await
+    }
+}
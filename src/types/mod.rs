@@ -1,14 +1,142 @@
 use async_trait::async_trait;
+use glam::{Quat, Vec3};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::watch;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+use crate::trajectory::Trajectory;
+
+mod capabilities;
+mod error;
+mod log;
+mod params;
+
+pub use capabilities::{Capabilities, CommandSupport, Sensors};
+pub use error::DroneError;
+pub use log::{LogSample, LogStream, LogVariable};
+pub use params::{ParamInfo, ParamType, ParamValue};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct DroneState {
-    pub roll: f32,   // degrees
-    pub pitch: f32,  // degrees
-    pub yaw: f32,    // degrees
+    pub x: f32,          // meters, world frame
+    pub y: f32,          // meters, world frame
+    pub z: f32,          // meters, world frame
+    pub vx: f32,         // m/s, world frame
+    pub vy: f32,         // m/s, world frame
+    pub vz: f32,         // m/s, world frame
+    pub ax: f32,         // m/s², world frame, without gravity
+    pub ay: f32,         // m/s², world frame, without gravity
+    pub az: f32,         // m/s², world frame, without gravity
+    pub roll: f32,       // degrees
+    pub pitch: f32,      // degrees
+    pub yaw: f32,        // degrees
+    pub roll_rate: f32,  // degrees/s, body frame
+    pub pitch_rate: f32, // degrees/s, body frame
+    pub yaw_rate: f32,   // degrees/s, body frame
+    pub qx: f32,         // body to world
+    pub qy: f32,
+    pub qz: f32,
+    pub qw: f32,
+    /// Seconds, monotonic. Counted from the Crazyflie's boot, or the start of
+    /// the sim.
+    pub timestamp: f64,
     pub thrust: u16, // 0-65535
     pub armed: bool,
     pub battery_voltage: f32,
+    pub commander: CommanderStatus,
+    pub supervisor: SupervisorState,
+    pub ranges: Ranges,
+}
+
+impl Default for DroneState {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            vx: 0.0,
+            vy: 0.0,
+            vz: 0.0,
+            ax: 0.0,
+            ay: 0.0,
+            az: 0.0,
+            roll: 0.0,
+            pitch: 0.0,
+            yaw: 0.0,
+            roll_rate: 0.0,
+            pitch_rate: 0.0,
+            yaw_rate: 0.0,
+            qx: 0.0,
+            qy: 0.0,
+            qz: 0.0,
+            qw: 1.0,
+            timestamp: 0.0,
+            thrust: 0,
+            armed: false,
+            battery_voltage: 0.0,
+            commander: CommanderStatus::default(),
+            supervisor: SupervisorState::default(),
+            ranges: Ranges::default(),
+        }
+    }
+}
+
+impl DroneState {
+    pub fn position(&self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    pub fn velocity(&self) -> Vec3 {
+        Vec3::new(self.vx, self.vy, self.vz)
+    }
+
+    pub fn acceleration(&self) -> Vec3 {
+        Vec3::new(self.ax, self.ay, self.az)
+    }
+
+    /// Body rates in degrees/s.
+    pub fn rates(&self) -> Vec3 {
+        Vec3::new(self.roll_rate, self.pitch_rate, self.yaw_rate)
+    }
+
+    pub fn attitude(&self) -> Quat {
+        Quat::from_xyzw(self.qx, self.qy, self.qz, self.qw)
+    }
+}
+
+/// Range sensor readings along the body axes, like the Multi-ranger and Flow
+/// decks. `None` when there is no sensor or nothing in range.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Ranges {
+    pub front: Option<f32>, // meters
+    pub back: Option<f32>,  // meters
+    pub left: Option<f32>,  // meters
+    pub right: Option<f32>, // meters
+    pub up: Option<f32>,    // meters
+    pub down: Option<f32>,  // meters
+}
+
+/// Setpoint watchdog state, as in the firmware commander.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CommanderStatus {
+    #[default]
+    Idle, // No setpoint since start or the last stop
+    Active,
+    LevelingOut, // No setpoint for 500 ms
+    TimedOut,    // No setpoint for 2 s, motors cut
+}
+
+/// Safety supervisor state, as in the firmware supervisor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SupervisorState {
+    #[default]
+    PreFlight, // Checks not passed or not armed
+    ReadyToFly,
+    Flying,
+    Landed,
+    Crashed,          // Tumbled, motors cut until re-armed upright
+    Locked,           // Setpoints timed out in flight, needs a reboot
+    EmergencyStopped, // Needs a reboot
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -19,16 +147,168 @@ pub struct RpytCommand {
     pub thrust: u16, // 0-65535
 }
 
+/// How the thrust channel of an [`RpytCommand`] is interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AssistMode {
+    #[default]
+    Off, // Raw thrust
+    AltitudeHold, // Climb rate around the stick centre, holds height when centred
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PositionCommand {
+    pub x: f32,   // meters, world frame
+    pub y: f32,   // meters, world frame
+    pub z: f32,   // meters, world frame
+    pub yaw: f32, // degrees
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct VelocityCommand {
+    pub vx: f32,       // m/s, world frame
+    pub vy: f32,       // m/s, world frame
+    pub vz: f32,       // m/s, world frame
+    pub yaw_rate: f32, // degrees/sec
+}
+
+/// Attitude with an absolute height, e.g. over a Flow deck.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ZDistanceCommand {
+    pub roll: f32,       // degrees
+    pub pitch: f32,      // degrees
+    pub yaw_rate: f32,   // degrees/sec
+    pub z_distance: f32, // meters
+}
+
+/// Body-frame horizontal velocity at an absolute height.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HoverCommand {
+    pub vx: f32,         // m/s, body frame
+    pub vy: f32,         // m/s, body frame
+    pub yaw_rate: f32,   // degrees/sec
+    pub z_distance: f32, // meters
+}
+
+/// Full state reference, e.g. from a trajectory planned off board.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FullStateCommand {
+    pub position: Vec3,     // meters, world frame
+    pub velocity: Vec3,     // m/s, world frame
+    pub acceleration: Vec3, // m/s², world frame
+    pub attitude: Quat,     // body to world
+    pub rates: Vec3,        // roll, pitch, yaw in degrees/sec, body frame
+}
+
+/// Commands for the on-board high-level commander. Heights and positions are
+/// in meters, yaw in degrees and durations in seconds.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum HighLevelCommand {
+    Takeoff {
+        height: f32,
+        duration: f32,
+    },
+    Land {
+        height: f32,
+        duration: f32,
+    },
+    GoTo {
+        x: f32,
+        y: f32,
+        z: f32,
+        yaw: f32,
+        duration: f32,
+        relative: bool,
+    },
+    /// Flies a trajectory previously uploaded under `id`.
+    StartTrajectory {
+        id: u8,
+        time_scale: f32,
+        relative: bool,
+        reversed: bool,
+    },
+    Stop,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DroneCommand {
     Rpyt(RpytCommand),
-    Arm,    // Sends zero thrust to unlock
-    Disarm, // Stops motors
+    Position(PositionCommand), // Position hold
+    Velocity(VelocityCommand), // World-frame velocity
+    ZDistance(ZDistanceCommand),
+    Hover(HoverCommand),
+    FullState(FullStateCommand),
+    /// Stops the motors, like a zero-thrust setpoint.
+    Stop,
+    /// Ends a stream of low-level setpoints. The last one stays valid for
+    /// `remain_valid_ms`, then the high-level commander can take over again.
+    NotifySetpointStop {
+        remain_valid_ms: u32,
+    },
+    HighLevel(HighLevelCommand),
+    Assist(AssistMode), // Manual flight assist for Rpyt commands
+    Arm,                // Sends zero thrust to unlock
+    Disarm,             // Stops motors
+    EmergencyStop,      // Stops motors until reboot
+}
+
+impl DroneCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DroneCommand::Rpyt(_) => "rpyt",
+            DroneCommand::Position(_) => "position",
+            DroneCommand::Velocity(_) => "velocity",
+            DroneCommand::ZDistance(_) => "z-distance",
+            DroneCommand::Hover(_) => "hover",
+            DroneCommand::FullState(_) => "full-state",
+            DroneCommand::Stop => "stop",
+            DroneCommand::NotifySetpointStop { .. } => "notify-setpoint-stop",
+            DroneCommand::HighLevel(_) => "high-level",
+            DroneCommand::Assist(_) => "assist",
+            DroneCommand::Arm => "arm",
+            DroneCommand::Disarm => "disarm",
+            DroneCommand::EmergencyStop => "emergency-stop",
+        }
+    }
+
+    /// Low-level setpoints, which are streamed and time out without a fresh one.
+    pub fn is_setpoint(&self) -> bool {
+        matches!(
+            self,
+            DroneCommand::Rpyt(_)
+                | DroneCommand::Position(_)
+                | DroneCommand::Velocity(_)
+                | DroneCommand::ZDistance(_)
+                | DroneCommand::Hover(_)
+                | DroneCommand::FullState(_)
+        )
+    }
 }
 
 #[async_trait]
 pub trait DroneInterface: Send + Sync {
-    async fn init(&mut self) -> Result<(), Box<dyn std::error::Error>>;
-    async fn get_state(&self) -> Result<DroneState, Box<dyn std::error::Error>>;
-    async fn send_command(&mut self, cmd: DroneCommand) -> Result<(), Box<dyn std::error::Error>>;
+    async fn init(&mut self) -> Result<(), DroneError>;
+    async fn get_state(&self) -> Result<DroneState, DroneError>;
+    /// Every state update as it arrives from the drone, without polling.
+    /// The receiver reports an error once the driver is gone.
+    fn subscribe_state(&self) -> watch::Receiver<DroneState>;
+    fn capabilities(&self) -> Capabilities;
+    async fn send_command(&mut self, cmd: DroneCommand) -> Result<(), DroneError>;
+    async fn upload_trajectory(
+        &mut self,
+        id: u8,
+        trajectory: &Trajectory,
+    ) -> Result<(), DroneError>;
+    /// Firmware parameters by "group.name", e.g. `pid_rate.roll_kp`. Unknown
+    /// names are an [`DroneError::InvalidArgument`].
+    async fn get_param(&self, name: &str) -> Result<ParamValue, DroneError>;
+    /// Sets a parameter, converting `value` to the parameter's own type.
+    async fn set_param(&mut self, name: &str, value: ParamValue) -> Result<(), DroneError>;
+    async fn list_params(&self) -> Result<Vec<ParamInfo>, DroneError>;
+    async fn list_log_variables(&self) -> Result<Vec<LogVariable>, DroneError>;
+    /// Samples `variables` every `period`, by their firmware names.
+    async fn subscribe_log(
+        &self,
+        variables: &[&str],
+        period: Duration,
+    ) -> Result<LogStream, DroneError>;
 }
@@ -0,0 +1,91 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Value of a firmware parameter, in the type the firmware declares it with.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ParamValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    F32(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ParamType {
+    U8,
+    U16,
+    U32,
+    I8,
+    I16,
+    I32,
+    F32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParamInfo {
+    pub name: String, // "group.name", e.g. "pid_rate.roll_kp"
+    pub kind: ParamType,
+}
+
+impl ParamValue {
+    pub fn kind(&self) -> ParamType {
+        match self {
+            ParamValue::U8(_) => ParamType::U8,
+            ParamValue::U16(_) => ParamType::U16,
+            ParamValue::U32(_) => ParamType::U32,
+            ParamValue::I8(_) => ParamType::I8,
+            ParamValue::I16(_) => ParamType::I16,
+            ParamValue::I32(_) => ParamType::I32,
+            ParamValue::F32(_) => ParamType::F32,
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            ParamValue::U8(v) => v as f64,
+            ParamValue::U16(v) => v as f64,
+            ParamValue::U32(v) => v as f64,
+            ParamValue::I8(v) => v as f64,
+            ParamValue::I16(v) => v as f64,
+            ParamValue::I32(v) => v as f64,
+            ParamValue::F32(v) => v as f64,
+        }
+    }
+
+    pub fn as_f32(&self) -> f32 {
+        self.as_f64() as f32
+    }
+}
+
+impl ParamType {
+    /// Converts `value` to this type, saturating integers.
+    pub fn value(&self, value: f64) -> ParamValue {
+        match self {
+            ParamType::U8 => ParamValue::U8(value as u8),
+            ParamType::U16 => ParamValue::U16(value as u16),
+            ParamType::U32 => ParamValue::U32(value as u32),
+            ParamType::I8 => ParamValue::I8(value as i8),
+            ParamType::I16 => ParamValue::I16(value as i16),
+            ParamType::I32 => ParamValue::I32(value as i32),
+            ParamType::F32 => ParamValue::F32(value as f32),
+        }
+    }
+}
+
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamValue::U8(v) => write!(f, "{}", v),
+            ParamValue::U16(v) => write!(f, "{}", v),
+            ParamValue::U32(v) => write!(f, "{}", v),
+            ParamValue::I8(v) => write!(f, "{}", v),
+            ParamValue::I16(v) => write!(f, "{}", v),
+            ParamValue::I32(v) => write!(f, "{}", v),
+            ParamValue::F32(v) => write!(f, "{}", v),
+        }
+    }
+}